@@ -0,0 +1,72 @@
+/*!
+ Benchmarks rendering the largest handwriting fixture.
+
+ Run with `cargo bench -p imessage-database --features bench`.
+*/
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    env::current_dir,
+    fs::File,
+    io::Read,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use imessage_database::message_types::handwriting::HandwrittenMessage;
+
+/// Counts allocations so the benchmark can report how many a render makes
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn load(name: &str) -> HandwrittenMessage {
+    let protobuf_path = current_dir()
+        .unwrap()
+        .as_path()
+        .join("test_data/handwritten_message")
+        .join(name);
+    let mut proto_data = File::open(protobuf_path).unwrap();
+    let mut data = vec![];
+    proto_data.read_to_end(&mut data).unwrap();
+    HandwrittenMessage::from_payload(&data).unwrap()
+}
+
+fn render_svg(c: &mut Criterion) {
+    let balloon = load("pollock.bin");
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let svg = balloon.render_svg();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "render_svg on pollock.bin: {} points, {} bytes, {allocations} allocations",
+        balloon.strokes.iter().map(Vec::len).sum::<usize>(),
+        svg.len(),
+    );
+
+    c.bench_function("render_svg pollock.bin", |b| {
+        b.iter(|| balloon.render_svg())
+    });
+}
+
+criterion_group!(benches, render_svg);
+criterion_main!(benches);
@@ -0,0 +1,20 @@
+/*!
+ Throws arbitrary bytes at the handwriting parser and renders anything that parses.
+
+ Run with `cargo +nightly fuzz run from_payload` from `imessage-database`. The seed corpus in
+ `corpus/from_payload` holds copies of the handwriting fixtures in `test_data`.
+*/
+
+#![no_main]
+
+use imessage_database::message_types::handwriting::HandwrittenMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(balloon) = HandwrittenMessage::from_payload(data) {
+        let _ = balloon.render_svg();
+        let _ = balloon.render_svg_animated();
+        let _ = balloon.render_ascii(40);
+        let _ = balloon.render_ascii(0);
+    }
+});
@@ -4,41 +4,163 @@
 
 use std::fmt::{Display, Formatter, Result};
 
+use crate::message_types::handwriting::models::MAX_IMAGE_DIMENSION;
+
 /// Errors that can happen when parsing `handwriting` data
 #[derive(Debug)]
 pub enum HandwritingError {
     ProtobufError(protobuf::Error),
-    InvalidFrameSize(usize),
+    /// The frame was not the expected size: `(expected, got)`
+    InvalidFrameSize(usize, usize),
     XZError(lzma_rs::error::Error),
     CompressionUnknown,
-    InvalidStrokesLength(usize, usize),
+    /// Stroke data ended early: `(needed, available, offset)`, where `offset` is the index parsing stopped at
+    InvalidStrokesLength(usize, usize, usize),
     ConversionError,
     DecompressedNotSet,
     InvalidDecompressedLength(usize, usize),
     ResizeError(std::num::TryFromIntError),
+    PdfError(std::io::Error),
+    /// The payload parsed, but its `Handwriting` message is missing or empty
+    MissingHandwritingData,
+    RasterError(String),
+    /// The image scale was `0`, or made the image wider or taller than [`MAX_IMAGE_DIMENSION`](crate::message_types::handwriting::models::MAX_IMAGE_DIMENSION)
+    InvalidScale(u32),
+    /// The payload contains fields this parser does not know about, by field number
+    UnknownFields(Vec<u32>),
+    /// The payload does not start like a handwriting protobuf, so it is likely some other kind of data
+    NotHandwritingPayload,
+    /// The text given to [`from_base64()`](crate::message_types::handwriting::HandwrittenMessage::from_base64) is not valid `base64`
+    InvalidBase64,
+    /// The reader given to [`from_reader()`](crate::message_types::handwriting::HandwrittenMessage::from_reader) failed
+    ReadError(std::io::Error),
+    /// The stroke data has more strokes than [`ParseOptions::max_strokes`](crate::message_types::handwriting::models::ParseOptions::max_strokes): `(count, limit)`,
+    /// where `count` is the number of strokes found when parsing stopped
+    TooManyStrokes(usize, usize),
 }
 
 impl Display for HandwritingError {
+    /// Every message starts with `handwriting: `, so it can be told apart when wrapped by other errors.
     fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
+        write!(fmt, "handwriting: ")?;
         match self {
-            HandwritingError::ProtobufError(why) => {
-                write!(fmt, "failed to parse handwriting protobuf: {why}")
+            HandwritingError::ProtobufError(why) => write!(fmt, "failed to parse protobuf: {why}"),
+            HandwritingError::InvalidFrameSize(expected, got) => {
+                write!(fmt, "expected frame size of {expected} bytes, got {got}")
             }
-            HandwritingError::InvalidFrameSize(size) => write!(fmt, "expected size 8, got {size}"),
-            HandwritingError::XZError(why) => write!(fmt, "failed to decompress xz: {why}"),
-            HandwritingError::CompressionUnknown => write!(fmt, "compress method unknown"),
-            HandwritingError::InvalidStrokesLength(index, length) => {
-                write!(fmt, "can't access index {index} on array length {length}")
+            HandwritingError::XZError(why) => {
+                write!(fmt, "failed to decompress xz stroke data: {why}")
             }
-            HandwritingError::ConversionError => write!(fmt, "failed to convert num"),
-            HandwritingError::DecompressedNotSet => {
-                write!(fmt, "decompressed length not set on compressed message")
+            HandwritingError::CompressionUnknown => {
+                write!(fmt, "stroke data uses an unknown compression method")
             }
-            HandwritingError::InvalidDecompressedLength(expected, got) => {
-                write!(fmt, "expected decompressed length of {expected}, got {got}")
+            HandwritingError::InvalidStrokesLength(needed, length, offset) => write!(
+                fmt,
+                "stroke data needs {needed} bytes but only has {length}, parsing stopped at offset {offset}"
+            ),
+            HandwritingError::ConversionError => {
+                write!(fmt, "failed to convert a length to a native size")
+            }
+            HandwritingError::DecompressedNotSet => {
+                write!(
+                    fmt,
+                    "compressed stroke data does not set its decompressed length"
+                )
             }
+            HandwritingError::InvalidDecompressedLength(expected, got) => write!(
+                fmt,
+                "expected decompressed stroke data of {expected} bytes, got {got}"
+            ),
             HandwritingError::ResizeError(why) => {
-                write!(fmt, "failed to resize handwriting coordinates: {why}")
+                write!(fmt, "failed to resize coordinates: {why}")
+            }
+            HandwritingError::PdfError(why) => write!(fmt, "failed to generate pdf: {why}"),
+            HandwritingError::MissingHandwritingData => {
+                write!(fmt, "payload does not contain handwriting data")
+            }
+            HandwritingError::RasterError(why) => write!(fmt, "failed to render image: {why}"),
+            HandwritingError::InvalidScale(scale) => write!(
+                fmt,
+                "image scale {scale} is 0 or makes the image larger than {MAX_IMAGE_DIMENSION} pixels"
+            ),
+            HandwritingError::UnknownFields(fields) => {
+                let fields: Vec<String> = fields.iter().map(u32::to_string).collect();
+                write!(fmt, "unknown fields: {}", fields.join(", "))
+            }
+            HandwritingError::NotHandwritingPayload => {
+                write!(fmt, "payload is not a handwriting message")
+            }
+            HandwritingError::InvalidBase64 => write!(fmt, "payload is not valid base64"),
+            HandwritingError::ReadError(why) => write!(fmt, "failed to read payload: {why}"),
+            HandwritingError::TooManyStrokes(count, limit) => {
+                write!(fmt, "found {count} strokes, more than the limit of {limit}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::handwriting::HandwritingError;
+
+    #[test]
+    fn can_display_every_variant() {
+        let protobuf = protobuf::Error::from(std::io::Error::other("truncated"));
+        let xz = lzma_rs::error::Error::IoError(std::io::Error::other("bad magic"));
+        let resize = u8::try_from(300u16).unwrap_err();
+        let cases = [
+            (HandwritingError::ProtobufError(protobuf), vec!["truncated"]),
+            (HandwritingError::InvalidFrameSize(8, 4), vec!["8", "4"]),
+            (HandwritingError::XZError(xz), vec!["xz", "bad magic"]),
+            (HandwritingError::CompressionUnknown, vec!["compression"]),
+            (
+                HandwritingError::InvalidStrokesLength(12, 11, 10),
+                vec!["12", "11", "10"],
+            ),
+            (HandwritingError::ConversionError, vec!["convert"]),
+            (
+                HandwritingError::DecompressedNotSet,
+                vec!["decompressed length"],
+            ),
+            (
+                HandwritingError::InvalidDecompressedLength(100, 90),
+                vec!["100", "90"],
+            ),
+            (HandwritingError::ResizeError(resize), vec!["resize"]),
+            (
+                HandwritingError::PdfError(std::io::Error::other("disk full")),
+                vec!["pdf", "disk full"],
+            ),
+            (
+                HandwritingError::MissingHandwritingData,
+                vec!["handwriting data"],
+            ),
+            (
+                HandwritingError::RasterError("too big".to_string()),
+                vec!["too big"],
+            ),
+            (HandwritingError::InvalidScale(0), vec!["scale 0", "16384"]),
+            (
+                HandwritingError::UnknownFields(vec![12, 99]),
+                vec!["12, 99"],
+            ),
+            (
+                HandwritingError::NotHandwritingPayload,
+                vec!["not a handwriting"],
+            ),
+            (HandwritingError::InvalidBase64, vec!["base64"]),
+            (
+                HandwritingError::ReadError(std::io::Error::other("closed")),
+                vec!["read", "closed"],
+            ),
+            (HandwritingError::TooManyStrokes(3, 2), vec!["3", "2"]),
+        ];
+
+        for (error, parts) in cases {
+            let message = error.to_string();
+            assert!(message.starts_with("handwriting: "), "{message}");
+            for part in parts {
+                assert!(message.contains(part), "{message} should contain {part}");
             }
         }
     }
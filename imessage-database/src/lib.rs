@@ -1,7 +1,13 @@
 #![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![doc = include_str!("../README.md")]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
 pub mod error;
 pub mod message_types;
+#[cfg(feature = "std")]
 pub mod tables;
+#[cfg(feature = "std")]
 pub mod util;
@@ -0,0 +1,298 @@
+/*!
+ Geometry used to scale and plot [handwritten](crate::message_types::handwriting) strokes.
+
+ This module only depends on [`core`] and [`alloc`], so it can be used in `no_std` environments that
+ provide a global allocator. Parsing payloads (protobuf and XZ decompression) and building `svg` or ASCII
+ output require `std` and live in [`models`](crate::message_types::handwriting::models).
+*/
+#![deny(
+    clippy::std_instead_of_core,
+    clippy::std_instead_of_alloc,
+    clippy::alloc_instead_of_core
+)]
+
+use alloc::vec::Vec;
+
+/// Represents a point along a handwritten line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Point {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+}
+
+/// Converts all points from a canvas of `max_x` by `max_y` to a canvas of `height` and `width`.
+///
+/// Point widths are scaled from `0..=max_width` to `1..=10`.
+#[must_use]
+pub fn fit_strokes(
+    strokes: &[Vec<Point>],
+    height: u16,
+    width: u16,
+    max_x: u16,
+    max_y: u16,
+    max_width: u16,
+) -> Vec<Vec<Point>> {
+    strokes
+        .iter()
+        .map(|stroke| -> Vec<Point> {
+            stroke
+                .iter()
+                .map(|point| -> Point {
+                    Point {
+                        x: resize(point.x, width, max_x),
+                        y: resize(point.y, height, max_y),
+                        width: resize(point.width, 9, max_width) + 1,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Converts all points from a canvas of `max_x` by `max_y` to a canvas of `height` and `width`
+/// using a single scale factor for both axes.
+///
+/// Unlike [`fit_strokes()`], the drawing keeps its aspect ratio: the scale is the smaller of the two
+/// axis ratios and the result is centered along the other axis. Point widths are scaled the same way as
+/// [`fit_strokes()`].
+#[must_use]
+pub fn fit_strokes_uniform(
+    strokes: &[Vec<Point>],
+    height: u16,
+    width: u16,
+    max_x: u16,
+    max_y: u16,
+    max_width: u16,
+) -> Vec<Vec<Point>> {
+    // Compare `width / max_x` against `height / max_y` without dividing
+    let (box_size, max_v) = if u32::from(width) * u32::from(max_y.max(1))
+        <= u32::from(height) * u32::from(max_x.max(1))
+    {
+        (width, max_x)
+    } else {
+        (height, max_y)
+    };
+    let offset_x = width.saturating_sub(resize(max_x, box_size, max_v)) / 2;
+    let offset_y = height.saturating_sub(resize(max_y, box_size, max_v)) / 2;
+
+    strokes
+        .iter()
+        .map(|stroke| -> Vec<Point> {
+            stroke
+                .iter()
+                .map(|point| -> Point {
+                    Point {
+                        x: resize(point.x, box_size, max_v) + offset_x,
+                        y: resize(point.y, box_size, max_v) + offset_y,
+                        width: resize(point.width, 9, max_width) + 1,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Resize converts `v` from a coordinate where `max_v` is the current height/width and `box_size` is the wanted height/width.
+#[must_use]
+pub fn resize(v: u16, box_size: u16, max_v: u16) -> u16 {
+    (i64::from(v) * i64::from(box_size))
+        .checked_div(i64::from(max_v))
+        .unwrap_or(0) as u16
+}
+
+/// Iterates through each point in each stroke and extracts the maximum `x`, `y`, and `width` values.
+///
+/// The width is reduced by one to match the `+ 1` that [`fit_strokes()`] adds back; a width of `0` counts as `0`.
+#[must_use]
+pub fn get_max_dimension(strokes: &[Vec<Point>]) -> (u16, u16, u16) {
+    strokes.iter().flat_map(|stroke| stroke.iter()).fold(
+        (0, 0, 0),
+        |(max_x, max_y, max_width), point| {
+            (
+                max_x.max(point.x),
+                max_y.max(point.y),
+                max_width.max(point.width.saturating_sub(1)),
+            )
+        },
+    )
+}
+
+/// Resamples `stroke` to `target` points spaced evenly along its point sequence, interpolating between neighbors.
+///
+/// The first and last points are always kept, so `target` is raised to `2` for strokes with more than one point.
+/// Strokes that already have `target` points or fewer are copied unchanged.
+#[must_use]
+pub fn resample_stroke(stroke: &[Point], target: usize) -> Vec<Point> {
+    let target = target.max(2);
+    if stroke.len() <= target {
+        return stroke
+            .iter()
+            .map(|point| Point {
+                x: point.x,
+                y: point.y,
+                width: point.width,
+            })
+            .collect();
+    }
+
+    // Position `idx` of `target` lands `idx * span / steps` points along the stroke
+    let span = (stroke.len() - 1) as u64;
+    let steps = (target - 1) as u64;
+    let lerp = |from: u16, to: u16, frac: u64| -> u16 {
+        let from = i64::from(from);
+        let delta = (i64::from(to) - from) * frac as i64;
+        // Round to the nearest value
+        (from + (delta + delta.signum() * (steps as i64 / 2)) / steps as i64) as u16
+    };
+
+    (0..=steps)
+        .map(|idx| {
+            let position = idx * span;
+            let start = &stroke[(position / steps) as usize];
+            let end = &stroke[((position / steps) as usize + 1).min(stroke.len() - 1)];
+            let frac = position % steps;
+            Point {
+                x: lerp(start.x, end.x, frac),
+                y: lerp(start.y, end.y, frac),
+                width: lerp(start.width, end.width, frac),
+            }
+        })
+        .collect()
+}
+
+/// Walks a line from `start` to `end` using Bresenham's line algorithm, calling `plot` with each `(x, y)` cell along the way.
+pub fn walk_line(start: &Point, end: &Point, mut plot: impl FnMut(i64, i64)) {
+    let mut x_curr = i64::from(start.x);
+    let mut y_curr = i64::from(start.y);
+    let x_end = i64::from(end.x);
+    let y_end = i64::from(end.y);
+
+    let dx = (x_end - x_curr).abs();
+    let dy = -(y_end - y_curr).abs();
+    let sx = if x_curr < x_end { 1 } else { -1 };
+    let sy = if y_curr < y_end { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    while x_curr != x_end || y_curr != y_end {
+        plot(x_curr, y_curr);
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x_curr += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y_curr += sy;
+        }
+    }
+
+    plot(x_end, y_end);
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use crate::message_types::handwriting::geometry::{
+        Point, fit_strokes, fit_strokes_uniform, get_max_dimension, resample_stroke,
+    };
+
+    fn square() -> Vec<Vec<Point>> {
+        vec![
+            [(0, 0), (100, 0), (100, 100), (0, 100), (0, 0)]
+                .iter()
+                .map(|&(x, y)| Point { x, y, width: 1 })
+                .collect(),
+        ]
+    }
+
+    fn extent(strokes: &[Vec<Point>]) -> (u16, u16, u16, u16) {
+        let points = || strokes.iter().flatten();
+        (
+            points().map(|p| p.x).min().unwrap(),
+            points().map(|p| p.x).max().unwrap(),
+            points().map(|p| p.y).min().unwrap(),
+            points().map(|p| p.y).max().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_fit_strokes_stretches_square() {
+        let strokes = square();
+        let (max_x, max_y, max_width) = get_max_dimension(&strokes);
+        let fit = fit_strokes(&strokes, 20, 80, max_x, max_y, max_width);
+
+        assert_eq!(extent(&fit), (0, 80, 0, 20));
+    }
+
+    #[test]
+    fn test_get_max_dimension_zero_width() {
+        let strokes = vec![vec![
+            Point {
+                x: 5,
+                y: 7,
+                width: 0,
+            },
+            Point {
+                x: 3,
+                y: 9,
+                width: 0,
+            },
+        ]];
+        assert_eq!(get_max_dimension(&strokes), (5, 9, 0));
+
+        let fit = fit_strokes(&strokes, 9, 5, 5, 9, 0);
+        assert!(fit.iter().flatten().all(|point| point.width == 1));
+    }
+
+    #[test]
+    fn test_fit_strokes_uniform_keeps_square() {
+        let strokes = square();
+        let (max_x, max_y, max_width) = get_max_dimension(&strokes);
+        let fit = fit_strokes_uniform(&strokes, 20, 80, max_x, max_y, max_width);
+
+        // 20x20, centered horizontally in an 80 wide canvas
+        assert_eq!(extent(&fit), (30, 50, 0, 20));
+    }
+
+    #[test]
+    fn test_fit_strokes_uniform_tall_canvas() {
+        let strokes = square();
+        let (max_x, max_y, max_width) = get_max_dimension(&strokes);
+        let fit = fit_strokes_uniform(&strokes, 60, 10, max_x, max_y, max_width);
+
+        assert_eq!(extent(&fit), (0, 10, 25, 35));
+    }
+
+    #[test]
+    fn test_resample_stroke_interpolates() {
+        let stroke: Vec<Point> = (0..=10)
+            .map(|idx| Point {
+                x: idx * 10,
+                y: 100 - idx * 10,
+                width: 1,
+            })
+            .collect();
+        let resampled = resample_stroke(&stroke, 4);
+
+        assert_eq!(
+            resampled
+                .iter()
+                .map(|point| (point.x, point.y))
+                .collect::<Vec<_>>(),
+            vec![(0, 100), (33, 67), (67, 33), (100, 0)]
+        );
+    }
+
+    #[test]
+    fn test_resample_stroke_keeps_short_strokes() {
+        let stroke = vec![Point {
+            x: 4,
+            y: 2,
+            width: 3,
+        }];
+        assert_eq!(resample_stroke(&stroke, 0), stroke);
+        assert_eq!(resample_stroke(&square()[0], 5), square()[0]);
+    }
+}
@@ -187,6 +187,8 @@ impl ::protobuf::reflect::ProtobufValue for BaseMessage {
 #[derive(PartialEq,Clone,Default,Debug)]
 pub struct Handwriting {
     // message fields
+    // @@protoc_insertion_point(field:handwriting.Handwriting.Unknown2)
+    pub Unknown2: ::std::vec::Vec<u8>,
     // @@protoc_insertion_point(field:handwriting.Handwriting.Frame)
     pub Frame: ::std::vec::Vec<u8>,
     // @@protoc_insertion_point(field:handwriting.Handwriting.StrokesCount)
@@ -195,6 +197,8 @@ pub struct Handwriting {
     pub Compression: ::protobuf::EnumOrUnknown<Compression>,
     // @@protoc_insertion_point(field:handwriting.Handwriting.DecompressedLength)
     pub DecompressedLength: ::std::option::Option<i64>,
+    // @@protoc_insertion_point(field:handwriting.Handwriting.Unknown7)
+    pub Unknown7: i64,
     // @@protoc_insertion_point(field:handwriting.Handwriting.Strokes)
     pub Strokes: ::std::vec::Vec<u8>,
     // special fields
@@ -214,8 +218,13 @@ impl Handwriting {
     }
 
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(5);
+        let mut fields = ::std::vec::Vec::with_capacity(7);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "Unknown2",
+            |m: &Handwriting| { &m.Unknown2 },
+            |m: &mut Handwriting| { &mut m.Unknown2 },
+        ));
         fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
             "Frame",
             |m: &Handwriting| { &m.Frame },
@@ -236,6 +245,11 @@ impl Handwriting {
             |m: &Handwriting| { &m.DecompressedLength },
             |m: &mut Handwriting| { &mut m.DecompressedLength },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "Unknown7",
+            |m: &Handwriting| { &m.Unknown7 },
+            |m: &mut Handwriting| { &mut m.Unknown7 },
+        ));
         fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
             "Strokes",
             |m: &Handwriting| { &m.Strokes },
@@ -259,6 +273,9 @@ impl ::protobuf::Message for Handwriting {
     fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::Result<()> {
         while let Some(tag) = is.read_raw_tag_or_eof()? {
             match tag {
+                18 => {
+                    self.Unknown2 = is.read_bytes()?;
+                },
                 26 => {
                     self.Frame = is.read_bytes()?;
                 },
@@ -271,6 +288,9 @@ impl ::protobuf::Message for Handwriting {
                 48 => {
                     self.DecompressedLength = ::std::option::Option::Some(is.read_int64()?);
                 },
+                56 => {
+                    self.Unknown7 = is.read_int64()?;
+                },
                 66 => {
                     self.Strokes = is.read_bytes()?;
                 },
@@ -286,6 +306,9 @@ impl ::protobuf::Message for Handwriting {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u64 {
         let mut my_size = 0;
+        if !self.Unknown2.is_empty() {
+            my_size += ::protobuf::rt::bytes_size(2, &self.Unknown2);
+        }
         if !self.Frame.is_empty() {
             my_size += ::protobuf::rt::bytes_size(3, &self.Frame);
         }
@@ -298,6 +321,9 @@ impl ::protobuf::Message for Handwriting {
         if let Some(v) = self.DecompressedLength {
             my_size += ::protobuf::rt::int64_size(6, v);
         }
+        if self.Unknown7 != 0 {
+            my_size += ::protobuf::rt::int64_size(7, self.Unknown7);
+        }
         if !self.Strokes.is_empty() {
             my_size += ::protobuf::rt::bytes_size(8, &self.Strokes);
         }
@@ -307,6 +333,9 @@ impl ::protobuf::Message for Handwriting {
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::Result<()> {
+        if !self.Unknown2.is_empty() {
+            os.write_bytes(2, &self.Unknown2)?;
+        }
         if !self.Frame.is_empty() {
             os.write_bytes(3, &self.Frame)?;
         }
@@ -319,6 +348,9 @@ impl ::protobuf::Message for Handwriting {
         if let Some(v) = self.DecompressedLength {
             os.write_int64(6, v)?;
         }
+        if self.Unknown7 != 0 {
+            os.write_int64(7, self.Unknown7)?;
+        }
         if !self.Strokes.is_empty() {
             os.write_bytes(8, &self.Strokes)?;
         }
@@ -339,20 +371,24 @@ impl ::protobuf::Message for Handwriting {
     }
 
     fn clear(&mut self) {
+        self.Unknown2.clear();
         self.Frame.clear();
         self.StrokesCount = 0;
         self.Compression = ::protobuf::EnumOrUnknown::new(Compression::Unknown);
         self.DecompressedLength = ::std::option::Option::None;
+        self.Unknown7 = 0;
         self.Strokes.clear();
         self.special_fields.clear();
     }
 
     fn default_instance() -> &'static Handwriting {
         static instance: Handwriting = Handwriting {
+            Unknown2: ::std::vec::Vec::new(),
             Frame: ::std::vec::Vec::new(),
             StrokesCount: 0,
             Compression: ::protobuf::EnumOrUnknown::from_i32(0),
             DecompressedLength: ::std::option::Option::None,
+            Unknown7: 0,
             Strokes: ::std::vec::Vec::new(),
             special_fields: ::protobuf::SpecialFields::new(),
         };
@@ -452,14 +488,16 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     \n/src/message_types/handwriting/handwriting.proto\x12\x0bhandwriting\"w\
     \n\x0bBaseMessage\x12\x1c\n\tCreatedAt\x18\x02\x20\x01(\x10R\tCreatedAt\
     \x12\x0e\n\x02ID\x18\x03\x20\x01(\tR\x02ID\x12:\n\x0bHandwriting\x18\x04\
-    \x20\x01(\x0b2\x18.handwriting.HandwritingR\x0bHandwriting\"\xe9\x01\n\
-    \x0bHandwriting\x12\x14\n\x05Frame\x18\x03\x20\x01(\x0cR\x05Frame\x12\"\
-    \n\x0cStrokesCount\x18\x04\x20\x01(\x03R\x0cStrokesCount\x12:\n\x0bCompr\
-    ession\x18\x05\x20\x01(\x0e2\x18.handwriting.CompressionR\x0bCompression\
-    \x123\n\x12DecompressedLength\x18\x06\x20\x01(\x03H\0R\x12DecompressedLe\
-    ngth\x88\x01\x01\x12\x18\n\x07Strokes\x18\x08\x20\x01(\x0cR\x07StrokesB\
-    \x15\n\x13_DecompressedLength*,\n\x0bCompression\x12\x0b\n\x07Unknown\
-    \x10\0\x12\x08\n\x04None\x10\x01\x12\x06\n\x02XZ\x10\x04b\x06proto3\
+    \x20\x01(\x0b2\x18.handwriting.HandwritingR\x0bHandwriting\"\xa1\x02\n\
+    \x0bHandwriting\x12\x1a\n\x08Unknown2\x18\x02\x20\x01(\x0cR\x08Unknown2\
+    \x12\x14\n\x05Frame\x18\x03\x20\x01(\x0cR\x05Frame\x12\"\n\x0cStrokesCou\
+    nt\x18\x04\x20\x01(\x03R\x0cStrokesCount\x12:\n\x0bCompression\x18\x05\
+    \x20\x01(\x0e2\x18.handwriting.CompressionR\x0bCompression\x123\n\x12Dec\
+    ompressedLength\x18\x06\x20\x01(\x03H\0R\x12DecompressedLength\x88\x01\
+    \x01\x12\x1a\n\x08Unknown7\x18\x07\x20\x01(\x03R\x08Unknown7\x12\x18\n\
+    \x07Strokes\x18\x08\x20\x01(\x0cR\x07StrokesB\x15\n\x13_DecompressedLeng\
+    th*,\n\x0bCompression\x12\x0b\n\x07Unknown\x10\0\x12\x08\n\x04None\x10\
+    \x01\x12\x06\n\x02XZ\x10\x04b\x06proto3\
 ";
 
 /// `FileDescriptorProto` object which was a source for this generated file
@@ -2,9 +2,40 @@
 [Handwritten](https://support.apple.com/en-us/HT206894) messages are animated doodles or messages sent in your own handwriting.
 
 A writeup about the reverse engineering of this data can be found [here](https://github.com/trymoose/handwriting2svg/blob/0eb56cf458207bb1c2ceea48cf4b6b6510fa7b13/DISCOVERY.md).
+
+# Features
+
+The entry points need these features:
+
+- [`geometry`], including [`Point`](geometry::Point), [`fit_strokes()`](geometry::fit_strokes), [`resize()`](geometry::resize),
+  and [`walk_line()`](geometry::walk_line): none, since it only requires `core` and `alloc`. Build with
+  `cargo build -p imessage-database --no-default-features` to check that it still compiles for `no_std` targets.
+- `HandwrittenMessage::from_payload()`, XZ decompression, `render_svg()`, `render_ascii()`, `render_pdf()`,
+  and every other [`models`], [`svg`], [`pdf`], [`sink`], and [`stroke`] item: `std`, which is on by default.
+- `render_png()` and the other raster output: `png`, `webp`, `image`, or `gif`, which each enable `std`.
 */
 
+#[cfg(feature = "std")]
 pub use models::HandwrittenMessage;
+#[cfg(feature = "std")]
+pub use sink::StrokeSink;
+#[cfg(feature = "std")]
+pub use stroke::Stroke;
+#[cfg(feature = "std")]
+pub use svg::{AnimationOptions, LineCap, LineJoin, PaperStyle, SvgNode, SvgRenderOptions};
 
+pub mod geometry;
+#[cfg(feature = "std")]
 pub(crate) mod handwriting_proto;
+#[cfg(feature = "std")]
 pub mod models;
+#[cfg(feature = "std")]
+pub mod pdf;
+#[cfg(feature = "png")]
+pub(crate) mod raster;
+#[cfg(feature = "std")]
+pub mod sink;
+#[cfg(feature = "std")]
+pub mod stroke;
+#[cfg(feature = "std")]
+pub mod svg;
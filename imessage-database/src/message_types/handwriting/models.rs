@@ -2,16 +2,50 @@
 [Handwritten](https://support.apple.com/en-us/HT206894) messages are animated doodles or messages sent in your own handwriting.
 */
 
+use std::borrow::Cow;
 use std::fmt::Write;
-use std::io::Cursor;
+use std::io::Read;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+pub use crate::message_types::handwriting::geometry::Point;
 
 use crate::{
     error::handwriting::HandwritingError,
-    message_types::handwriting::handwriting_proto::{BaseMessage, Compression},
+    message_types::handwriting::{
+        geometry::{
+            fit_strokes, fit_strokes_uniform, get_max_dimension, resample_stroke, resize, walk_line,
+        },
+        handwriting_proto::{BaseMessage, Compression, Handwriting},
+        pdf::generate_pdf,
+        sink::{StrokeSink, drive},
+        stroke::{Bounds, Stroke},
+        svg::{
+            AnimationOptions, SvgNode, SvgRenderOptions, estimate_strokes_bytes, generate_animated,
+            generate_debug_frame, generate_endpoint_markers, generate_forensic_overlay,
+            generate_fragment, generate_minified, generate_outlines, generate_paper,
+            generate_stroke_nodes, generate_strokes, generate_thumbnail,
+        },
+    },
+    message_types::payload::starts_like_handwriting,
+    util::{
+        base64,
+        dates::{APPLE_EPOCH_OFFSET_SECS, TIMESTAMP_FACTOR},
+    },
 };
 
+use chrono::{DateTime, NaiveDateTime};
 use protobuf::Message;
 
+/// Number of parsed points between calls to a progress callback
+const PROGRESS_INTERVAL: usize = 1024;
+/// Number of bytes in a handwriting frame
+const FRAME_SIZE: usize = 8;
+/// Default padding added to the frame's width and height so strokes on the edge are not clipped
+pub const FRAME_PADDING: u16 = 5;
+/// A parsed `x` and `y` of this value mark a pen lift inside a stroke when [`ParseOptions::split_pen_lifts`] is set
+const PEN_UP_SENTINEL: u16 = 0xFFFF;
+
 /// Parser for [handwritten](https://support.apple.com/en-us/HT206894) iMessages.
 ///
 /// This message type is not documented by Apple, but represents messages displayed as
@@ -20,332 +54,1957 @@ use protobuf::Message;
 pub struct HandwrittenMessage {
     pub id: String,
     /// Timestamp for when the handwritten message was created, stored as a unix timestamp with an epoch of `2001-01-01 00:00:00` in the local time zone
+    ///
+    /// Use [`created_at_datetime()`](Self::created_at_datetime) to convert it to a date.
     pub created_at: i64,
+    /// Position of the drawing's frame as `(x, y)`, in the same units as [`width`](Self::width) and [`height`](Self::height)
+    ///
+    /// Stroke coordinates are normalized to the frame rather than stored relative to this point, so
+    /// [`strokes`](Self::strokes) always start from `(0, 0)` no matter where the frame is placed.
+    pub origin: (i16, i16),
     pub height: u16,
     pub width: u16,
+    /// Padding included in [`width`](Self::width) and [`height`](Self::height) so strokes on the edge are not clipped
+    pub padding: u16,
+    /// Width of Apple's frame as stored in the payload, before [`padding`](Self::padding) is added
+    ///
+    /// Unlike [`width`](Self::width), this is kept as it is by transforms such as [`rotate()`](Self::rotate) and [`cropped()`](Self::cropped).
+    pub frame_width: u16,
+    /// Height of Apple's frame as stored in the payload, before [`padding`](Self::padding) is added
+    pub frame_height: u16,
+    /// Number of points whose raw width was outside of `1..=`[`MAX_RAW_WIDTH`] and was clamped before fitting
+    ///
+    /// Real payloads never need clamping, so a nonzero count suggests the payload is corrupt.
+    pub clamped_widths: usize,
+    /// The `expressive_send_style_id` of the message that carried the drawing, such as [`INVISIBLE_INK_EFFECT`]
+    ///
+    /// Payloads do not store bubble effects, so this is `None` until it is set with [`with_effect()`](Self::with_effect).
+    pub effect: Option<String>,
     /// Collection of strokes that make up the handwritten image
     pub strokes: Vec<Vec<Point>>,
 }
 
-/// Represents a point along a handwritten line.
-#[derive(Debug, PartialEq, Eq)]
-pub struct Point {
-    pub x: u16,
-    pub y: u16,
-    pub width: u16,
+/// Drawings with less total ink than this, in canvas units, are considered [blank](HandwrittenMessage::is_blank).
+pub const BLANK_INK_LENGTH: f64 = 5.0;
+
+/// The widest raw point width accepted before fitting; real payloads use widths in `1..=24`
+pub const MAX_RAW_WIDTH: u16 = 32;
+
+/// The largest stroke coordinate Apple writes; stroke points are normalized into `0..=MAX_COORDINATE` on both axes.
+pub const MAX_COORDINATE: u16 = 0x7FFF;
+
+/// Space left around the ink on every side by [`HandwrittenMessage::cropped()`]
+pub const CROP_MARGIN: u16 = FRAME_PADDING;
+
+/// The `expressive_send_style_id` of the invisible ink bubble effect, which hides a message until it is tapped
+pub const INVISIBLE_INK_EFFECT: &str = "com.apple.MobileSMS.expressivesend.invisibleink";
+
+/// The `id` of the `svg` filter that blurs [invisible ink](INVISIBLE_INK_EFFECT) drawings
+const INVISIBLE_INK_FILTER: &str = "invisible-ink";
+/// The `id` of the `svg` filter that draws a [drop shadow](SvgRenderOptions::drop_shadow)
+const DROP_SHADOW_FILTER: &str = "drop-shadow";
+/// The start of the `id` of the `svg` gradient drawn as the [background gradient](SvgRenderOptions::background_gradient)
+const BACKGROUND_GRADIENT: &str = "background-gradient";
+/// Side length, in cells, of the grid [`HandwrittenMessage::perceptual_hash()`] averages over
+const HASH_GRID: usize = 8;
+/// Side length, in cells, of the finer grid strokes are plotted on, per cell of [`HASH_GRID`]
+const HASH_SAMPLES: usize = 4;
+
+/// The widest or tallest image, in pixels, that the raster renderers, such as `render_png()`, will create
+pub const MAX_IMAGE_DIMENSION: u32 = 16_384;
+
+/// The default [`ParseOptions::max_strokes`], far more than any real drawing has
+pub const DEFAULT_MAX_STROKES: usize = 10_000;
+
+/// Options that control how a payload is parsed by [`HandwrittenMessage::from_payload_with_options()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// How to treat points with coordinates beyond a limit, which only happen in corrupt payloads
+    pub outliers: OutlierHandling,
+    /// How much space to add to the right and bottom of the canvas
+    pub padding: Padding,
+    /// Fail with [`HandwritingError::UnknownFields`] if the payload has fields this parser does not know about
+    ///
+    /// This is useful to notice when Apple changes the format.
+    pub strict: bool,
+    /// Fail with [`HandwritingError::TooManyStrokes`] once the stroke data has more strokes than this
+    ///
+    /// Every stroke is its own allocation, so this bounds the memory a corrupt payload made of many tiny strokes can use.
+    pub max_strokes: usize,
+    /// Treat points with an `x` and `y` of `0xFFFF` as pen lifts, splitting the stroke there instead of drawing them
+    ///
+    /// No known payload contains such a point, so this is off by default and every parsed point is drawn.
+    pub split_pen_lifts: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            outliers: OutlierHandling::default(),
+            padding: Padding::default(),
+            strict: false,
+            max_strokes: DEFAULT_MAX_STROKES,
+            split_pen_lifts: false,
+        }
+    }
+}
+
+/// A data quality issue that the parser worked around instead of failing, reported by [`HandwrittenMessage::from_payload_with_warnings()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandwritingWarning {
+    /// A stroke had no points left to draw, such as one made only of pen lifts, and was removed
+    EmptyStrokeDropped,
+    /// A point was moved onto the limit by [`OutlierHandling::Clamp`]
+    PointClamped,
+    /// A point was removed by [`OutlierHandling::Discard`]
+    PointDropped,
+    /// A point's raw width, included here, was outside of `1..=`[`MAX_RAW_WIDTH`] and was clamped
+    WidthOutOfRange(u16),
+}
+
+/// How much padding is added to the frame's width and height.
+///
+/// Strokes are fit to the frame, so half of a stroke's width can extend past the frame's edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Add a fixed amount of padding
+    Fixed(u16),
+    /// Add padding equal to the widest stroke, so strokes on the edge are never clipped
+    StrokeWidth,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding::Fixed(FRAME_PADDING)
+    }
+}
+
+/// How points with an `x` or `y` coordinate larger than a limit are handled.
+///
+/// A single corrupt point far outside of the drawing becomes the maximum that every other point is scaled against,
+/// which crushes the real drawing into a corner. [`MAX_COORDINATE`] is a sensible limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutlierHandling {
+    /// Keep every point as parsed
+    #[default]
+    Keep,
+    /// Move coordinates larger than the limit onto the limit
+    Clamp(u16),
+    /// Drop points with a coordinate larger than the limit, and any strokes left empty
+    Discard(u16),
+}
+
+/// Measurements of a single parse, returned by [`HandwrittenMessage::from_payload_traced()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseStats {
+    /// Length of the raw payload
+    pub payload_bytes: usize,
+    /// Length of the stroke data after decompression
+    pub decompressed_bytes: usize,
+    /// Number of strokes parsed
+    pub stroke_count: usize,
+    /// Number of points across every stroke
+    pub total_points: usize,
+    /// Time spent decoding, decompressing, and fitting the payload
+    pub parse_duration: Duration,
 }
 
 impl HandwrittenMessage {
     /// Converts a raw byte payload from the database into a [`HandwrittenMessage`].
     pub fn from_payload(payload: &[u8]) -> Result<Self, HandwritingError> {
-        let msg =
-            BaseMessage::parse_from_bytes(payload).map_err(HandwritingError::ProtobufError)?;
-        let (width, height) = parse_dimensions(&msg)?;
-        let strokes = parse_strokes(&msg)?;
-        let (max_x, max_y, max_width) = get_max_dimension(&strokes);
-        Ok(Self {
-            id: msg.ID.to_string(),
-            created_at: msg.CreatedAt,
-            height: height + 5,
-            width: width + 5,
-            strokes: fit_strokes(&strokes, height, width, max_x, max_y, max_width),
-        })
+        Self::parse(
+            payload,
+            &ParseOptions::default(),
+            &mut |_, _| {},
+            &mut |_| {},
+        )
+        .map(|(message, _)| message)
     }
 
-    /// Renders the handwriting message as an `svg` graphic.
+    /// Reads a raw payload from `reader`, such as a `BLOB` opened with [`GetBlob::get_blob()`](crate::tables::table::GetBlob::get_blob), and converts it into a [`HandwrittenMessage`].
+    ///
+    /// The compressed strokes are a field inside the protobuf, so the whole payload is read before parsing.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, HandwritingError> {
+        let mut payload = vec![];
+        reader
+            .read_to_end(&mut payload)
+            .map_err(HandwritingError::ReadError)?;
+        Self::from_payload(&payload)
+    }
+
+    /// Builds a [`HandwrittenMessage`] from strokes of `(x, y, width)` points in normalized coordinates, such as ones made by a drawing app.
+    ///
+    /// `x` and `y` run from `0.0` to `1.0` across a frame of `width` by `height`, and values outside of that range are clamped
+    /// onto the frame's edge. Point widths are in canvas units. The canvas gets the default [`FRAME_PADDING`] like a parsed
+    /// payload, and the message has an empty [`id`](Self::id), so every renderer can draw synthetic content.
     #[must_use]
-    pub fn render_svg(&self) -> String {
-        let mut svg = String::new();
-        svg.push('\n');
-        svg.push_str(format!(r#"<svg viewBox="0 0 {} {}" preserveAspectRatio="xMidYMid meet" width="100%" height="100%" xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">"#, self.width, self.height).as_str());
-        svg.push('\n');
-        svg.push_str(&format!("<title>{}</title>\n", self.id));
-        svg.push_str("<metadata>\n");
-        svg.push_str(&format!("<id>{}</id>\n", self.id));
-        svg.push_str(&format!("<createdAt>{}</createdAt>\n", self.created_at));
-        svg.push_str("</metadata>\n");
-        svg.push_str("<style>\n");
-        svg.push_str(
-            r"    .line {
-        fill: none;
-        stroke: black;
-        stroke-linecap: round;
-        stroke-linejoin: round;
+    pub fn from_normalized(strokes: Vec<Vec<(f32, f32, f32)>>, width: u16, height: u16) -> Self {
+        let scale =
+            |value: f32, length: u16| (value.clamp(0.0, 1.0) * f32::from(length)).round() as u16;
+        Self {
+            id: String::new(),
+            created_at: 0,
+            origin: (0, 0),
+            height: height.saturating_add(FRAME_PADDING),
+            width: width.saturating_add(FRAME_PADDING),
+            padding: FRAME_PADDING,
+            frame_width: width,
+            frame_height: height,
+            clamped_widths: 0,
+            effect: None,
+            strokes: strokes
+                .into_iter()
+                .map(|stroke| {
+                    stroke
+                        .into_iter()
+                        .map(|(x, y, point_width)| Point {
+                            x: scale(x, width),
+                            y: scale(y, height),
+                            width: point_width.round() as u16,
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
     }
-",
-        );
-        svg.push_str("</style>\n");
-        generate_strokes(&mut svg, &self.strokes);
-        svg.push_str("</svg>\n");
-        svg
+
+    /// Converts a `base64` encoded payload into a [`HandwrittenMessage`].
+    ///
+    /// Whitespace in `text` is ignored and padding is optional, so payloads copied from other tools can be passed as they are.
+    pub fn from_base64(text: &str) -> Result<Self, HandwritingError> {
+        Self::from_payload(&base64::decode(text).ok_or(HandwritingError::InvalidBase64)?)
     }
 
-    /// Renders the handwriting message as an ASCII graphic with a maximum height.
-    #[must_use]
-    pub fn render_ascii(&self, max_height: usize) -> String {
-        // Create a blank canvas filled with spaces
-        let h = max_height.min(self.height as usize);
-        let w = ((self.width as usize) * h)
-            .checked_div(self.height as usize)
-            .unwrap_or(0);
-        let mut canvas = vec![vec![' '; w]; h];
+    /// Converts a raw byte payload from the database into a [`HandwrittenMessage`], measuring the work it took.
+    ///
+    /// This is meant for finding the messages that slow down an export; [`from_payload()`](Self::from_payload)
+    /// does not time anything.
+    pub fn from_payload_traced(payload: &[u8]) -> Result<(Self, ParseStats), HandwritingError> {
+        let start = Instant::now();
+        let (message, decompressed_bytes) = Self::parse(
+            payload,
+            &ParseOptions::default(),
+            &mut |_, _| {},
+            &mut |_| {},
+        )?;
+        let parse_duration = start.elapsed();
+        let stats = ParseStats {
+            payload_bytes: payload.len(),
+            decompressed_bytes,
+            stroke_count: message.strokes.len(),
+            total_points: message.strokes.iter().map(Vec::len).sum(),
+            parse_duration,
+        };
+        Ok((message, stats))
+    }
 
-        // Plot the lines on the canvas
-        // Width is only used when drawing the line on an SVG
-        for line in &fit_strokes(
-            &self.strokes,
-            w as u16,
-            h as u16,
-            self.height,
-            self.width,
-            1,
-        ) {
-            line.windows(2).for_each(|window| {
-                draw_line(&mut canvas, &window[0], &window[1]);
-            });
+    /// Converts a raw byte payload from the database into a [`HandwrittenMessage`], reporting progress.
+    ///
+    /// While the strokes are parsed, `callback` is periodically called with `(points_parsed, points_total_estimate)`.
+    /// The estimate is derived from the length of the decompressed stroke data, so it is an upper bound.
+    pub fn from_payload_with_progress(
+        payload: &[u8],
+        mut callback: impl FnMut(usize, usize),
+    ) -> Result<Self, HandwritingError> {
+        Self::parse(
+            payload,
+            &ParseOptions::default(),
+            &mut callback,
+            &mut |_| {},
+        )
+        .map(|(message, _)| message)
+    }
+
+    /// Converts a raw byte payload from the database into a [`HandwrittenMessage`] using the provided [`ParseOptions`].
+    pub fn from_payload_with_options(
+        payload: &[u8],
+        options: &ParseOptions,
+    ) -> Result<Self, HandwritingError> {
+        Self::parse(payload, options, &mut |_, _| {}, &mut |_| {}).map(|(message, _)| message)
+    }
+
+    /// Converts a raw byte payload into a [`HandwrittenMessage`] using the provided [`ParseOptions`], reporting data quality issues.
+    ///
+    /// `callback` receives a [`HandwritingWarning`] each time the parser changes or drops data instead of failing,
+    /// such as when [`OutlierHandling`] moves a point or a width is clamped into range.
+    pub fn from_payload_with_warnings(
+        payload: &[u8],
+        options: &ParseOptions,
+        mut callback: impl FnMut(HandwritingWarning),
+    ) -> Result<Self, HandwritingError> {
+        Self::parse(payload, options, &mut |_, _| {}, &mut callback).map(|(message, _)| message)
+    }
+
+    /// Converts many raw byte payloads into [`HandwrittenMessage`]s, preserving the order of `payloads`.
+    ///
+    /// With the `parallel` feature enabled, payloads are parsed across threads.
+    pub fn parse_many(payloads: &[Vec<u8>]) -> Vec<Result<Self, HandwritingError>> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            payloads
+                .par_iter()
+                .map(|payload| Self::from_payload(payload))
+                .collect()
         }
+        #[cfg(not(feature = "parallel"))]
+        {
+            payloads
+                .iter()
+                .map(|payload| Self::from_payload(payload))
+                .collect()
+        }
+    }
 
-        // Convert the canvas to a string
-        let mut output = String::with_capacity(h * (w + 1));
-        for row in canvas {
-            for &ch in &row {
-                let _ = write!(output, "{ch}");
-            }
-            output.push('\n');
+    /// Converts a raw byte payload into a [`HandwrittenMessage`] without blocking the async runtime.
+    ///
+    /// XZ decompression can take a while for large drawings, so parsing runs on a blocking task. On targets
+    /// without threads, such as `wasm32`, the payload is parsed in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if parsing panics, or if the runtime shuts down before parsing finishes.
+    #[cfg(feature = "async")]
+    pub async fn from_payload_async(payload: Vec<u8>) -> Result<Self, HandwritingError> {
+        #[cfg(not(target_family = "wasm"))]
+        {
+            tokio::task::spawn_blocking(move || Self::from_payload(&payload))
+                .await
+                .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
         }
+        #[cfg(target_family = "wasm")]
+        {
+            Self::from_payload(&payload)
+        }
+    }
 
-        output
+    /// Decompresses the raw stroke data from a payload without parsing it.
+    ///
+    /// This is useful for inspecting the intermediate stroke format with an external decoder.
+    pub fn decompressed_strokes(payload: &[u8]) -> Result<Vec<u8>, HandwritingError> {
+        decompress_strokes(&parse_message(payload)?)
     }
-}
 
-/// Draws a line on a 2d character grid using Bresenham's line algorithm.
-fn draw_line(canvas: &mut [Vec<char>], start: &Point, end: &Point) {
-    let mut x_curr = i64::from(start.x);
-    let mut y_curr = i64::from(start.y);
-    let x_end = i64::from(end.x);
-    let y_end = i64::from(end.y);
-
-    let dx = (x_end - x_curr).abs();
-    let dy = -(y_end - y_curr).abs();
-    let sx = if x_curr < x_end { 1 } else { -1 };
-    let sy = if y_curr < y_end { 1 } else { -1 };
-    let mut err = dx + dy;
-
-    while x_curr != x_end || y_curr != y_end {
-        draw_point(canvas, x_curr, y_curr);
-        let e2 = 2 * err;
-        if e2 >= dy {
-            err += dy;
-            x_curr += sx;
+    /// Parses a payload, reporting progress to `progress` and changed or dropped data to `warn`.
+    ///
+    /// Returns the message and the length of its decompressed stroke data.
+    fn parse(
+        payload: &[u8],
+        options: &ParseOptions,
+        progress: &mut dyn FnMut(usize, usize),
+        warn: &mut dyn FnMut(HandwritingWarning),
+    ) -> Result<(Self, usize), HandwritingError> {
+        let msg = parse_message(payload)?;
+        if options.strict {
+            check_unknown_fields(&msg)?;
         }
-        if e2 <= dx {
-            err += dx;
-            y_curr += sy;
+        let Frame {
+            origin,
+            width,
+            height,
+        } = parse_frame(&msg)?;
+        let (data, unknown_compression) = match decompress_strokes(&msg) {
+            Ok(data) => (Cow::Owned(data), false),
+            // Many payloads marked `Unknown` are not compressed at all, so try the stroke data as it is
+            Err(HandwritingError::CompressionUnknown) => {
+                (Cow::Borrowed(msg.Handwriting.Strokes.as_slice()), true)
+            }
+            Err(why) => return Err(why),
+        };
+        let mut strokes = match parse_strokes(&data, options, progress, warn) {
+            Ok(strokes) if unknown_compression && strokes.is_empty() => {
+                return Err(HandwritingError::CompressionUnknown);
+            }
+            Ok(strokes) => strokes,
+            Err(_) if unknown_compression => return Err(HandwritingError::CompressionUnknown),
+            Err(why) => return Err(why),
+        };
+        handle_outliers(&mut strokes, options.outliers, warn);
+        let clamped_widths = clamp_widths(&mut strokes, warn);
+        let (max_x, max_y, max_width) = get_max_dimension(&strokes);
+        let strokes = fit_strokes(&strokes, height, width, max_x, max_y, max_width);
+        let padding = match options.padding {
+            Padding::Fixed(padding) => padding,
+            Padding::StrokeWidth => strokes
+                .iter()
+                .flatten()
+                .map(|point| point.width)
+                .max()
+                .unwrap_or_default(),
+        };
+        let message = Self {
+            id: msg.ID.to_string(),
+            created_at: msg.CreatedAt,
+            origin,
+            height: height.saturating_add(padding),
+            width: width.saturating_add(padding),
+            padding,
+            frame_width: width,
+            frame_height: height,
+            clamped_widths,
+            effect: None,
+            strokes,
+        };
+        Ok((message, data.len()))
+    }
+
+    /// Draws the strokes of `other` onto the same canvas as this message's strokes.
+    ///
+    /// The canvas is large enough to hold either message, and the combined strokes are re-fit to it.
+    /// Point widths are kept as they are. The result keeps this message's [`id`](Self::id) and [`created_at`](Self::created_at).
+    #[must_use]
+    pub fn overlay(&self, other: &HandwrittenMessage) -> HandwrittenMessage {
+        let padding = self.padding.max(other.padding);
+        let inner_height = self
+            .height
+            .saturating_sub(self.padding)
+            .max(other.height.saturating_sub(other.padding));
+        let inner_width = self
+            .width
+            .saturating_sub(self.padding)
+            .max(other.width.saturating_sub(other.padding));
+        let (self_x, self_y, _) = get_max_dimension(&self.strokes);
+        let (other_x, other_y, _) = get_max_dimension(&other.strokes);
+        let strokes: Vec<&Vec<Point>> = self.strokes.iter().chain(&other.strokes).collect();
+
+        HandwrittenMessage {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            origin: self.origin,
+            height: inner_height.saturating_add(padding),
+            width: inner_width.saturating_add(padding),
+            padding,
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            clamped_widths: self.clamped_widths + other.clamped_widths,
+            effect: self.effect.clone(),
+            strokes: refit_strokes(
+                &strokes,
+                inner_height,
+                inner_width,
+                self_x.max(other_x),
+                self_y.max(other_y),
+            ),
         }
     }
 
-    draw_point(canvas, x_end, y_end);
-}
+    /// Re-fits the drawing to a canvas of `width` by `height`, including [`padding`](Self::padding).
+    ///
+    /// The strokes are stretched from their current extent to fill the canvas, the same way they are fit to
+    /// the frame when parsing. Point widths are kept as they are.
+    #[must_use]
+    pub fn scale_to(&self, width: u16, height: u16) -> HandwrittenMessage {
+        let (max_x, max_y, _) = get_max_dimension(&self.strokes);
+        let strokes: Vec<&Vec<Point>> = self.strokes.iter().collect();
 
-/// Draws a point on a 2d character grid.
-fn draw_point(canvas: &mut [Vec<char>], x: i64, y: i64) {
-    if x >= 0 && x < canvas[0].len() as i64 && y >= 0 && y < canvas.len() as i64 {
-        canvas[y as usize][x as usize] = '*';
+        HandwrittenMessage {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            origin: self.origin,
+            height,
+            width,
+            padding: self.padding,
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            clamped_widths: self.clamped_widths,
+            effect: self.effect.clone(),
+            strokes: refit_strokes(
+                &strokes,
+                height.saturating_sub(self.padding),
+                width.saturating_sub(self.padding),
+                max_x,
+                max_y,
+            ),
+        }
     }
-}
 
-/// Generates svg lines from an array of strokes.
-fn generate_strokes(svg: &mut String, strokes: &[Vec<Point>]) {
-    for stroke in strokes {
-        let mut segments = String::with_capacity(80 * (stroke.len() - 1));
-        for (width, points) in &group_points(stroke) {
-            let mut points_svg = String::with_capacity(points.len() * 3);
-            for point in points {
-                points_svg.push_str(&format!(" {},{}", point.x, point.y));
-            }
-            segments.push_str(
-                format!(
-                    r#"<polyline class="line" points="{}" stroke-width="{}" />"#,
-                    points_svg.trim_start(),
-                    width
-                )
-                .as_str(),
-            );
-            segments.push('\n');
+    /// Resamples every stroke to at most `target_points_per_stroke` points, keeping each stroke's endpoints.
+    ///
+    /// Replaying strokes point by point makes long strokes take much longer than short ones; resampling
+    /// evens out the number of points so each stroke replays in a similar amount of time.
+    /// See [`resample_stroke()`](crate::message_types::handwriting::geometry::resample_stroke) for details.
+    #[must_use]
+    pub fn resample(&self, target_points_per_stroke: usize) -> HandwrittenMessage {
+        HandwrittenMessage {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            origin: self.origin,
+            height: self.height,
+            width: self.width,
+            padding: self.padding,
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            clamped_widths: self.clamped_widths,
+            effect: self.effect.clone(),
+            strokes: self
+                .strokes
+                .iter()
+                .map(|stroke| resample_stroke(stroke, target_points_per_stroke))
+                .collect(),
         }
-        svg.push_str(segments.as_str());
     }
-}
 
-/// Group points along a stroke together by width
-fn group_points(stroke: &[Point]) -> Vec<(u16, Vec<&Point>)> {
-    let mut groups = vec![];
-    let mut curr = stroke[0].width;
-    let mut segment = vec![];
-
-    for point in stroke {
-        segment.push(point);
-        if curr != point.width {
-            if segment.len() == 1 {
-                segment.push(point);
+    /// Trims the canvas to the ink, leaving [`CROP_MARGIN`] on every side.
+    ///
+    /// The strokes are moved so their [`bounding_box()`](Self::bounding_box) starts at `(CROP_MARGIN, CROP_MARGIN)`, and the
+    /// canvas becomes the size of the box plus the margin on both sides. The trailing margin is kept as the [`padding`](Self::padding).
+    /// Apple's frame is often much larger than the drawing, so this removes the empty space around it.
+    /// A drawing without any points becomes an empty canvas of just the margins.
+    #[must_use]
+    pub fn cropped(&self) -> HandwrittenMessage {
+        let bounds = self.bounding_box().unwrap_or(Bounds {
+            min_x: 0,
+            min_y: 0,
+            max_x: 0,
+            max_y: 0,
+        });
+
+        HandwrittenMessage {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            origin: self.origin,
+            height: (bounds.max_y - bounds.min_y).saturating_add(CROP_MARGIN * 2),
+            width: (bounds.max_x - bounds.min_x).saturating_add(CROP_MARGIN * 2),
+            padding: CROP_MARGIN,
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            clamped_widths: self.clamped_widths,
+            effect: self.effect.clone(),
+            strokes: self
+                .strokes
+                .iter()
+                .map(|stroke| {
+                    stroke
+                        .iter()
+                        .map(|point| Point {
+                            x: (point.x - bounds.min_x).saturating_add(CROP_MARGIN),
+                            y: (point.y - bounds.min_y).saturating_add(CROP_MARGIN),
+                            width: point.width,
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Sets the bubble [`effect`](Self::effect) from the `expressive_send_style_id` of the message that carried the drawing.
+    #[must_use]
+    pub fn with_effect(mut self, effect: Option<&str>) -> HandwrittenMessage {
+        self.effect = effect.map(String::from);
+        self
+    }
+
+    /// Snaps every point width to one of `levels` evenly spaced widths between the thinnest and thickest point.
+    ///
+    /// Each change in width starts a new `polyline` in the `svg` output, so fewer distinct widths means far fewer
+    /// elements, while the thick and thin parts of the drawing stay thick and thin. A single level uses the width halfway
+    /// between the extremes, and `0` levels leaves the widths as they are.
+    pub fn quantize_widths(&mut self, levels: u8) {
+        let Some((thinnest, thickest)) = self
+            .strokes
+            .iter()
+            .flatten()
+            .map(|point| point.width)
+            .fold(None, |range, width| match range {
+                Some((min, max)) => Some((width.min(min), width.max(max))),
+                None => Some((width, width)),
+            })
+        else {
+            return;
+        };
+        let steps = match levels {
+            0 => return,
+            1 => {
+                let middle = thinnest + (thickest - thinnest) / 2;
+                self.strokes
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|point| point.width = middle);
+                return;
             }
-            groups.push((curr, segment.clone()));
-            segment = vec![point];
-            curr = point.width;
+            levels => u32::from(levels - 1),
+        };
+        let range = u32::from(thickest - thinnest);
+        if range == 0 {
+            return;
+        }
+        for point in self.strokes.iter_mut().flatten() {
+            // Round to the nearest level, then map the level back onto the range of widths
+            let step = (u32::from(point.width - thinnest) * steps * 2 + range) / (range * 2);
+            point.width = thinnest + (step * range / steps) as u16;
         }
     }
 
-    if !segment.is_empty() {
-        segment.push(segment[segment.len() - 1]);
-        groups.push((curr, segment));
+    /// Determines if the drawing was sent with the [invisible ink](INVISIBLE_INK_EFFECT) bubble effect.
+    #[must_use]
+    pub fn is_invisible_ink(&self) -> bool {
+        self.effect.as_deref() == Some(INVISIBLE_INK_EFFECT)
     }
-    groups
-}
 
-/// Converts all points from a canvas of `max_x` by `max_y` to a canvas of `height` and `width`.
-fn fit_strokes(
-    strokes: &[Vec<Point>],
-    height: u16,
-    width: u16,
-    max_x: u16,
-    max_y: u16,
-    max_width: u16,
-) -> Vec<Vec<Point>> {
-    strokes
-        .iter()
-        .map(|stroke| -> Vec<Point> {
-            stroke
+    /// Rotates the drawing clockwise by `quarter_turns` multiples of 90 degrees.
+    ///
+    /// For odd turns, [`width`](Self::width) and [`height`](Self::height) are swapped so the rotated strokes
+    /// still fill the canvas. [`padding`](Self::padding) stays on the right and bottom edges, and point widths are kept as they are.
+    #[must_use]
+    pub fn rotate(&self, quarter_turns: u8) -> HandwrittenMessage {
+        let inner_width = self.width.saturating_sub(self.padding);
+        let inner_height = self.height.saturating_sub(self.padding);
+        let turns = quarter_turns % 4;
+        let (width, height) = if turns.is_multiple_of(2) {
+            (self.width, self.height)
+        } else {
+            (self.height, self.width)
+        };
+
+        HandwrittenMessage {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            origin: self.origin,
+            height,
+            width,
+            padding: self.padding,
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            clamped_widths: self.clamped_widths,
+            effect: self.effect.clone(),
+            strokes: self
+                .strokes
                 .iter()
-                .map(|point| -> Point {
-                    Point {
-                        x: resize(point.x, width, max_x),
-                        y: resize(point.y, height, max_y),
-                        width: resize(point.width, 9, max_width) + 1,
-                    }
+                .map(|stroke| {
+                    stroke
+                        .iter()
+                        .map(|point| {
+                            let (x, y) = match turns {
+                                1 => (inner_height.saturating_sub(point.y), point.x),
+                                2 => (
+                                    inner_width.saturating_sub(point.x),
+                                    inner_height.saturating_sub(point.y),
+                                ),
+                                3 => (point.y, inner_width.saturating_sub(point.x)),
+                                _ => (point.x, point.y),
+                            };
+                            Point {
+                                x,
+                                y,
+                                width: point.width,
+                            }
+                        })
+                        .collect()
                 })
-                .collect()
-        })
-        .collect()
-}
+                .collect(),
+        }
+    }
 
-/// Resize converts `v` from a coordinate where `max_v` is the current height/width and `box_size` is the wanted height/width.
-fn resize(v: u16, box_size: u16, max_v: u16) -> u16 {
-    (i64::from(v) * i64::from(box_size))
-        .checked_div(i64::from(max_v))
-        .unwrap_or(0) as u16
-}
+    /// Flips the drawing left to right, moving each point from `x` to `width - x`.
+    ///
+    /// Like [`rotate()`](Self::rotate), the flip is within the canvas without its [`padding`](Self::padding), so the padding
+    /// stays on the right edge. Mirroring twice gives back the original strokes.
+    #[must_use]
+    pub fn mirror_horizontal(&self) -> HandwrittenMessage {
+        self.mirrored(true)
+    }
 
-/// Iterates through each point in each stroke and extracts the maximum `x`, `y`, and `width` values.
-fn get_max_dimension(strokes: &[Vec<Point>]) -> (u16, u16, u16) {
-    strokes.iter().flat_map(|stroke| stroke.iter()).fold(
-        (0, 0, 0),
-        |(max_x, max_y, max_width), point| {
-            (
-                max_x.max(point.x),
-                max_y.max(point.y),
-                max_width.max(point.width - 1),
-            )
-        },
-    )
-}
+    /// Flips the drawing top to bottom, moving each point from `y` to `height - y`.
+    ///
+    /// See [`mirror_horizontal()`](Self::mirror_horizontal).
+    #[must_use]
+    pub fn mirror_vertical(&self) -> HandwrittenMessage {
+        self.mirrored(false)
+    }
 
-/// Parses raw stroke data into an array of strokes.
-fn parse_strokes(msg: &BaseMessage) -> Result<Vec<Vec<Point>>, HandwritingError> {
-    let data = decompress_strokes(msg)?;
+    /// Splits the drawing into one message for each cluster of strokes, such as the words of a written phrase.
+    ///
+    /// Strokes are grouped by their horizontal extent: a new cluster starts wherever there is more than `min_gap`
+    /// units of empty space between the strokes on its left and the next stroke. Each cluster keeps its strokes in stored
+    /// order and is [`cropped()`](Self::cropped) to its own ink. Clusters are ordered from left to right, and strokes without any points are left out.
+    #[must_use]
+    pub fn split_by_gaps(&self, min_gap: u16) -> Vec<HandwrittenMessage> {
+        let mut spans: Vec<(Bounds, usize)> = self
+            .iter_strokes()
+            .enumerate()
+            .filter_map(|(idx, stroke)| Some((stroke.bounds()?, idx)))
+            .collect();
+        spans.sort_by_key(|(bounds, _)| bounds.min_x);
 
-    let mut strokes = vec![];
-    let mut idx = 0;
-    let length = data.len();
-    while idx < length {
-        if idx + 1 >= length {
-            return Err(HandwritingError::InvalidStrokesLength(idx + 1, length));
+        // The right edge of each cluster, and the strokes in it
+        let mut clusters: Vec<(u16, Vec<usize>)> = vec![];
+        for (bounds, idx) in spans {
+            match clusters.last_mut() {
+                Some((right, members)) if bounds.min_x.saturating_sub(*right) <= min_gap => {
+                    *right = (*right).max(bounds.max_x);
+                    members.push(idx);
+                }
+                _ => clusters.push((bounds.max_x, vec![idx])),
+            }
         }
 
-        let num_points = u16::from_le_bytes([data[idx], data[idx + 1]]) as usize;
-        idx += 2;
-        if idx + (num_points * 8) > length {
-            return Err(HandwritingError::InvalidStrokesLength(
-                idx + (num_points * 8),
-                length,
-            ));
+        clusters
+            .into_iter()
+            .map(|(_, mut members)| {
+                members.sort_unstable();
+                HandwrittenMessage {
+                    id: self.id.clone(),
+                    created_at: self.created_at,
+                    origin: self.origin,
+                    height: self.height,
+                    width: self.width,
+                    padding: self.padding,
+                    frame_width: self.frame_width,
+                    frame_height: self.frame_height,
+                    clamped_widths: self.clamped_widths,
+                    effect: self.effect.clone(),
+                    strokes: members
+                        .iter()
+                        .map(|idx| {
+                            self.strokes[*idx]
+                                .iter()
+                                .map(|point| Point {
+                                    x: point.x,
+                                    y: point.y,
+                                    width: point.width,
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                }
+                .cropped()
+            })
+            .collect()
+    }
+
+    /// Flips every point across the middle of the canvas, horizontally if `horizontal` is `true` and vertically otherwise.
+    fn mirrored(&self, horizontal: bool) -> HandwrittenMessage {
+        let inner_width = self.width.saturating_sub(self.padding);
+        let inner_height = self.height.saturating_sub(self.padding);
+
+        HandwrittenMessage {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            origin: self.origin,
+            height: self.height,
+            width: self.width,
+            padding: self.padding,
+            frame_width: self.frame_width,
+            frame_height: self.frame_height,
+            clamped_widths: self.clamped_widths,
+            effect: self.effect.clone(),
+            strokes: self
+                .strokes
+                .iter()
+                .map(|stroke| {
+                    stroke
+                        .iter()
+                        .map(|point| Point {
+                            x: if horizontal {
+                                inner_width.saturating_sub(point.x)
+                            } else {
+                                point.x
+                            },
+                            y: if horizontal {
+                                point.y
+                            } else {
+                                inner_height.saturating_sub(point.y)
+                            },
+                            width: point.width,
+                        })
+                        .collect()
+                })
+                .collect(),
         }
+    }
 
-        let mut stroke = vec![];
-        (0..num_points).try_for_each(|_| -> Result<(), HandwritingError> {
-            let x = parse_coordinates(data[idx], data[idx + 1]);
-            let y = parse_coordinates(data[idx + 2], data[idx + 3]);
-            let width = parse_coordinates(data[idx + 4], data[idx + 5]);
-            idx += 8;
-            stroke.push(Point { x, y, width });
-            Ok(())
-        })?;
-        strokes.push(stroke);
+    /// The total length of every stroke, in canvas units.
+    #[must_use]
+    pub fn ink_length(&self) -> f64 {
+        self.iter_strokes().map(|stroke| stroke.length()).sum()
     }
-    Ok(strokes)
-}
 
-/// Decompresses raw stroke data and verifies length.
-fn decompress_strokes(msg: &BaseMessage) -> Result<Vec<u8>, HandwritingError> {
-    let data = match msg.Handwriting.Compression.enum_value_or_default() {
-        Compression::None => msg.Handwriting.Strokes.clone(),
-        Compression::XZ => {
-            let mut cursor = Cursor::new(&msg.Handwriting.Strokes);
-            let mut buf = Vec::new();
-            lzma_rs::xz_decompress(&mut cursor, &mut buf).map_err(HandwritingError::XZError)?;
-            buf
-        }
-        Compression::Unknown => {
-            return Err(HandwritingError::CompressionUnknown);
+    /// The canvas [`width`](Self::width) divided by its [`height`](Self::height), or `1.0` if the height is `0`.
+    #[must_use]
+    pub fn aspect_ratio(&self) -> f64 {
+        if self.height == 0 {
+            return 1.0;
         }
-    };
+        f64::from(self.width) / f64::from(self.height)
+    }
 
-    let length = match msg.Handwriting.Compression.enum_value_or_default() {
-        Compression::None => data.len(),
-        Compression::XZ => {
-            if let Some(decompress_size) = msg.Handwriting.DecompressedLength {
-                usize::try_from(decompress_size).map_err(|_| HandwritingError::ConversionError)?
+    /// Iterates over [`strokes`](Self::strokes) as [`Stroke`]s, which describe each stroke's length, endpoints, and bounds.
+    pub fn iter_strokes(&self) -> impl Iterator<Item = Stroke<'_>> {
+        self.strokes.iter().map(|stroke| Stroke::new(stroke))
+    }
+
+    /// The smallest rectangle containing every stroke, or `None` if there are no points.
+    ///
+    /// Unlike [`width`](Self::width) and [`height`](Self::height), this does not include the canvas padding.
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<Bounds> {
+        self.iter_strokes()
+            .filter_map(|stroke| stroke.bounds())
+            .reduce(|total, bounds| Bounds {
+                min_x: total.min_x.min(bounds.min_x),
+                min_y: total.min_y.min(bounds.min_y),
+                max_x: total.max_x.max(bounds.max_x),
+                max_y: total.max_y.max(bounds.max_y),
+            })
+    }
+
+    /// Determines if the drawing has no strokes, or so little ink that it was likely an accidental tap.
+    ///
+    /// A drawing is blank if its [`ink_length()`](Self::ink_length) is below [`BLANK_INK_LENGTH`].
+    #[must_use]
+    pub fn is_blank(&self) -> bool {
+        self.strokes.is_empty() || self.ink_length() < BLANK_INK_LENGTH
+    }
+
+    /// Determines if `other` draws the same picture, ignoring [`id`](Self::id), [`created_at`](Self::created_at), and [`origin`](Self::origin).
+    ///
+    /// Only the canvas size and the strokes are compared, so repeated doodles match even when sent as separate messages.
+    #[must_use]
+    pub fn same_drawing(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.strokes == other.strokes
+    }
+
+    /// Computes an average hash of the drawing, so similar drawings can be found by comparing hashes.
+    ///
+    /// The ink is plotted on a small grid, each cell of an 8x8 grid is shaded by how much of it is inked,
+    /// and each bit is set if its cell is darker than the average. Only the [`bounding_box()`](Self::bounding_box)
+    /// is hashed, so moving, resizing, or padding a drawing barely changes it. Drawings without any points hash to `0`.
+    ///
+    /// Two drawings are alike when few bits differ:
+    ///
+    /// ```ignore
+    /// let alike = (first.perceptual_hash() ^ second.perceptual_hash()).count_ones() <= 10;
+    /// ```
+    #[must_use]
+    pub fn perceptual_hash(&self) -> u64 {
+        let Some(bounds) = self.bounding_box() else {
+            return 0;
+        };
+        let side = HASH_GRID * HASH_SAMPLES;
+        let fit = |value: u16, min: u16, max: u16| {
+            if max == min {
+                0
             } else {
-                return Err(HandwritingError::DecompressedNotSet);
+                (usize::from(value - min) * (side - 1) / usize::from(max - min)) as u16
+            }
+        };
+
+        let mut inked = vec![vec![false; side]; side];
+        for stroke in &self.strokes {
+            let points: Vec<Point> = stroke
+                .iter()
+                .map(|point| Point {
+                    x: fit(point.x, bounds.min_x, bounds.max_x),
+                    y: fit(point.y, bounds.min_y, bounds.max_y),
+                    width: 1,
+                })
+                .collect();
+            if let [point] = points.as_slice() {
+                inked[usize::from(point.y)][usize::from(point.x)] = true;
+            }
+            for pair in points.windows(2) {
+                walk_line(&pair[0], &pair[1], |x, y| {
+                    inked[y as usize][x as usize] = true;
+                });
             }
         }
-        Compression::Unknown => {
-            return Err(HandwritingError::CompressionUnknown);
-        }
-    };
 
-    if length != data.len() {
-        return Err(HandwritingError::InvalidDecompressedLength(
-            length,
-            data.len(),
-        ));
+        let mut shades = [0_usize; HASH_GRID * HASH_GRID];
+        for (y, row) in inked.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if *cell {
+                    shades[(y / HASH_SAMPLES) * HASH_GRID + x / HASH_SAMPLES] += 1;
+                }
+            }
+        }
+        // Comparing `shade * cells` to the total avoids dividing to find the average
+        let total: usize = shades.iter().sum();
+        shades
+            .iter()
+            .enumerate()
+            .filter(|(_, shade)| **shade * shades.len() > total)
+            .fold(0, |hash, (idx, _)| hash | (1 << idx))
     }
-    Ok(data)
-}
 
-/// Parses the drawing size from the protobuf message.
-fn parse_dimensions(msg: &BaseMessage) -> Result<(u16, u16), HandwritingError> {
-    let rect = &msg.Handwriting.Frame;
-    if rect.len() != 8 {
-        return Err(HandwritingError::InvalidFrameSize(rect.len()));
+    /// Converts [`created_at`](Self::created_at) into a [`NaiveDateTime`], or `None` if the timestamp is out of range.
+    ///
+    /// Handwriting timestamps are normally stored in milliseconds, but second and nanosecond
+    /// precision values (as used by the `message` table) are detected by magnitude and scaled accordingly.
+    /// The result is shifted by [`APPLE_EPOCH_OFFSET_SECS`] onto the unix epoch.
+    #[must_use]
+    pub fn created_at_datetime(&self) -> Option<NaiveDateTime> {
+        let magnitude = self.created_at.unsigned_abs();
+        let (secs, nanos) = if magnitude < 10_000_000_000 {
+            // Seconds: anything before the year 2317
+            (self.created_at, 0)
+        } else if magnitude < 100_000_000_000_000 {
+            // Milliseconds
+            (
+                self.created_at.div_euclid(1000),
+                self.created_at.rem_euclid(1000) * 1_000_000,
+            )
+        } else {
+            // Nanoseconds
+            (
+                self.created_at.div_euclid(TIMESTAMP_FACTOR),
+                self.created_at.rem_euclid(TIMESTAMP_FACTOR),
+            )
+        };
+        DateTime::from_timestamp(
+            secs.checked_add(APPLE_EPOCH_OFFSET_SECS)?,
+            u32::try_from(nanos).ok()?,
+        )
+        .map(|date| date.naive_utc())
     }
-    Ok((
-        parse_coordinates(rect[4], rect[5]),
-        parse_coordinates(rect[6], rect[7]),
-    ))
-}
 
-/// Converts coordinate bytes to an u16.
-fn parse_coordinates(b1: u8, b2: u8) -> u16 {
-    u16::from_le_bytes([b1, b2]) ^ 0x8000
-}
+    /// Renders the handwriting message as an `svg` graphic.
+    #[must_use]
+    pub fn render_svg(&self) -> String {
+        self.render_svg_with_options(&SvgRenderOptions::default())
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::message_types::handwriting::models::{HandwrittenMessage, Point};
+    /// Builds the [`render_svg()`](Self::render_svg) graphic as an [`SvgNode`] tree, so callers can add classes or
+    /// `data-*` attributes before serializing it.
+    ///
+    /// Serializing the tree gives the same text as [`render_svg()`](Self::render_svg), without its leading line break.
+    #[must_use]
+    pub fn to_svg_tree(&self) -> SvgNode {
+        self.to_svg_tree_with_options(&SvgRenderOptions::default())
+    }
 
-    use std::env::current_dir;
-    use std::fs::File;
-    use std::io::Read;
+    /// Builds the [`render_svg_with_options()`](Self::render_svg_with_options) graphic as an [`SvgNode`] tree.
+    ///
+    /// Serializing the tree gives the same text as [`render_svg_with_options()`](Self::render_svg_with_options), without its leading line break.
+    #[must_use]
+    pub fn to_svg_tree_with_options(&self, options: &SvgRenderOptions) -> SvgNode {
+        let mut root = self.svg_header(options);
+        let mut body = if options.filled_outline {
+            generate_outlines(&self.strokes, options.coordinate_decimals)
+        } else {
+            generate_stroke_nodes(&self.strokes, options)
+        };
+        if let Some((defs, groups)) = self.svg_filters(&self.strokes, options) {
+            root.extend([defs]);
+            for mut group in groups.into_iter().rev() {
+                group.extend(body);
+                body = vec![group];
+            }
+        }
+        root.extend(body);
+        root.extend(self.svg_overlays(&self.strokes, options));
+        root
+    }
+
+    /// Renders only the strokes, as a `<g>` to nest inside another `svg` document, such as one that tiles several drawings.
+    ///
+    /// There is no `<svg>` wrapper or `<style>` block: every line has its own `stroke` and `stroke-width`, and the group
+    /// and each stroke's group have ids that start with `id_prefix`, so fragments with different prefixes do not collide.
+    /// The coordinates are on this message's canvas, so the parent document places and scales the group.
+    #[must_use]
+    pub fn render_svg_fragment(&self, id_prefix: &str) -> String {
+        generate_fragment(&self.strokes, id_prefix).to_string()
+    }
+
+    /// An upper bound on the length of [`render_svg()`](Self::render_svg), computed from the stroke and point counts.
+    ///
+    /// Only the short header is formatted, so this is cheap enough to call before deciding how to store a large drawing.
+    #[must_use]
+    pub fn estimated_svg_bytes(&self) -> usize {
+        self.open_svg(&SvgRenderOptions::default()).len()
+            + estimate_strokes_bytes(&self.strokes)
+            + "</svg>\n".len()
+    }
+
+    /// Renders the handwriting message as an `svg` graphic with each stroke in its own `<g id="stroke-N">` group.
+    ///
+    /// See [`SvgRenderOptions::group_strokes`].
+    #[must_use]
+    pub fn render_svg_grouped(&self) -> String {
+        self.render_svg_with_options(&SvgRenderOptions {
+            group_strokes: true,
+            ..Default::default()
+        })
+    }
+
+    /// Renders the handwriting message as an `svg` graphic using the provided [`SvgRenderOptions`].
+    #[must_use]
+    pub fn render_svg_with_options(&self, options: &SvgRenderOptions) -> String {
+        self.render_svg_strokes(&self.strokes, options)
+    }
+
+    /// Renders only the strokes in `stroke_range` as an `svg` graphic, keeping the full canvas so partial renders line up.
+    ///
+    /// The range is clamped to the number of strokes, so an empty or out-of-range selection renders an empty canvas.
+    #[must_use]
+    pub fn render_svg_partial(&self, stroke_range: Range<usize>) -> String {
+        self.render_svg_strokes(
+            self.select_strokes(stroke_range),
+            &SvgRenderOptions::default(),
+        )
+    }
+
+    /// Builds an `svg` document on this message's canvas that draws `strokes`.
+    ///
+    /// Everything but the strokes is built from the same nodes as [`to_svg_tree_with_options()`](Self::to_svg_tree_with_options),
+    /// and the strokes are split by the same [`SvgSink`](crate::message_types::handwriting::svg::SvgSink), so the two cannot drift apart.
+    fn render_svg_strokes(&self, strokes: &[Vec<Point>], options: &SvgRenderOptions) -> String {
+        let mut svg = self.open_svg(options);
+        let filters = self.svg_filters(strokes, options);
+        if let Some((defs, groups)) = &filters {
+            let _ = write!(svg, "{defs}");
+            for group in groups {
+                let _ = writeln!(svg, "{}", group.start_tag());
+            }
+        }
+        if options.filled_outline {
+            for outline in generate_outlines(strokes, options.coordinate_decimals) {
+                let _ = write!(svg, "{outline}");
+            }
+        } else {
+            generate_strokes(&mut svg, strokes, options);
+        }
+        if let Some((_, groups)) = &filters {
+            for _ in groups {
+                svg.push_str("</g>\n");
+            }
+        }
+        for overlay in self.svg_overlays(strokes, options) {
+            let _ = write!(svg, "{overlay}");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The `<defs>` of the filters applied to `strokes`, and the groups that apply them, outermost first.
+    ///
+    /// Each filter wraps the strokes in its own group, so they apply one after another. There are none if nothing is drawn.
+    fn svg_filters(
+        &self,
+        strokes: &[Vec<Point>],
+        options: &SvgRenderOptions,
+    ) -> Option<(SvgNode, Vec<SvgNode>)> {
+        let mut filters = vec![];
+        if options.drop_shadow {
+            filters.push((
+                DROP_SHADOW_FILTER,
+                SvgNode::element("feDropShadow")
+                    .with_attribute("dx", 1)
+                    .with_attribute("dy", 1)
+                    .with_attribute("stdDeviation", 1)
+                    .with_attribute("flood-color", "white")
+                    .with_attribute("flood-opacity", "0.8"),
+            ));
+        }
+        if options.apply_effects && self.is_invisible_ink() {
+            filters.push((
+                INVISIBLE_INK_FILTER,
+                SvgNode::element("feGaussianBlur").with_attribute("stdDeviation", 4),
+            ));
+        }
+        if filters.is_empty() || strokes.iter().all(Vec::is_empty) {
+            return None;
+        }
+
+        let mut defs = SvgNode::element("defs");
+        let mut groups = vec![];
+        for (id, filter) in filters {
+            defs.extend([SvgNode::element("filter")
+                .with_attribute("id", id)
+                .with_child(filter)]);
+            groups.push(SvgNode::element("g").with_attribute("filter", format!("url(#{id})")));
+        }
+        Some((defs, groups))
+    }
+
+    /// The guides drawn over `strokes`: endpoint markers, the debug frame, and the forensic overlay, if enabled.
+    fn svg_overlays(&self, strokes: &[Vec<Point>], options: &SvgRenderOptions) -> Vec<SvgNode> {
+        let mut overlays = vec![];
+        if options.endpoint_markers {
+            overlays.extend(generate_endpoint_markers(strokes));
+        }
+        if options.debug_frame {
+            overlays.extend(generate_debug_frame(
+                self.width,
+                self.height,
+                self.padding,
+                strokes,
+            ));
+        }
+        if options.forensic_overlay {
+            overlays.push(generate_forensic_overlay(self.width, self.height));
+        }
+        overlays
+    }
+
+    /// The strokes in `stroke_range`, with both ends clamped to the number of strokes.
+    fn select_strokes(&self, stroke_range: Range<usize>) -> &[Vec<Point>] {
+        let end = stroke_range.end.min(self.strokes.len());
+        let start = stroke_range.start.min(end);
+        &self.strokes[start..end]
+    }
+
+    /// Renders the handwriting message as an `svg` graphic that replays the strokes in the order they were stored.
+    ///
+    /// Stored order is usually the order the strokes were drawn in. Each stroke takes [`ANIMATION_STROKE_SECONDS`](crate::message_types::handwriting::svg::ANIMATION_STROKE_SECONDS).
+    #[must_use]
+    pub fn render_svg_animated(&self) -> String {
+        self.render_svg_animated_with_options(&AnimationOptions::default())
+    }
+
+    /// Renders the handwriting message as an `svg` graphic that replays the strokes in stored order, using the provided [`AnimationOptions`].
+    #[must_use]
+    pub fn render_svg_animated_with_options(&self, options: &AnimationOptions) -> String {
+        let strokes: Vec<&Vec<Point>> = self.strokes.iter().collect();
+        self.render_animation(&strokes, options)
+    }
+
+    /// Renders the handwriting message as an `svg` graphic that replays the strokes from left to right.
+    ///
+    /// Strokes are ordered by the `x` coordinate they start at, which approximates reading order for
+    /// payloads that store strokes out of order. Strokes that start at the same `x` keep their stored order.
+    #[must_use]
+    pub fn render_svg_animated_spatial(&self) -> String {
+        let mut strokes: Vec<&Vec<Point>> = self.strokes.iter().collect();
+        strokes.sort_by_key(|stroke| stroke.first().map(|point| point.x));
+        self.render_animation(&strokes, &AnimationOptions::default())
+    }
+
+    /// Builds an animated `svg` document that draws `strokes` in order.
+    fn render_animation(&self, strokes: &[&Vec<Point>], options: &AnimationOptions) -> String {
+        let mut svg = self.open_svg(&SvgRenderOptions::default());
+        generate_animated(&mut svg, strokes, options);
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Builds the start of an `svg` document: the root element, metadata, styles, and background.
+    fn open_svg(&self, options: &SvgRenderOptions) -> String {
+        let header = self.svg_header(options);
+        let mut svg = format!("\n{}\n", header.start_tag());
+        for child in header.children() {
+            let _ = write!(svg, "{child}");
+        }
+        svg
+    }
+
+    /// The root `svg` element, holding the metadata, styles, and background that every rendering starts with.
+    fn svg_header(&self, options: &SvgRenderOptions) -> SvgNode {
+        // Text nodes are escaped when serialized, so IDs from the payload cannot close the element they sit in
+        let text =
+            |name: &str, text: String| SvgNode::element(name).with_child(SvgNode::Text(text));
+        let mut root = SvgNode::element("svg")
+            .with_attribute("viewBox", format!("0 0 {} {}", self.width, self.height))
+            .with_attribute("preserveAspectRatio", "xMidYMid meet")
+            .with_attribute("width", "100%")
+            .with_attribute("height", "100%")
+            .with_attribute("xmlns", "http://www.w3.org/2000/svg")
+            .with_attribute("xmlns:xlink", "http://www.w3.org/1999/xlink")
+            .with_child(text("title", self.id.clone()))
+            .with_child(text("desc", self.describe()))
+            .with_child(
+                SvgNode::element("metadata")
+                    .with_child(text("id", self.id.clone()))
+                    .with_child(text("createdAt", self.created_at.to_string())),
+            )
+            .with_child(text(
+                "style",
+                format!(
+                    "
+    .line {{
+        fill: none;
+        stroke: black;
+        stroke-linecap: {};
+        stroke-linejoin: {};
+    }}
+",
+                    options.linecap, options.linejoin
+                ),
+            ));
+        let background = |fill: &str| {
+            SvgNode::element("rect")
+                .with_attribute("class", "background")
+                .with_attribute("x", 0)
+                .with_attribute("y", 0)
+                .with_attribute("width", self.width)
+                .with_attribute("height", self.height)
+                .with_attribute("fill", fill)
+        };
+        if let Some(color) = &options.background_color {
+            root.extend([background(color)]);
+        }
+        if let Some((top, bottom)) = &options.background_gradient {
+            let gradient = self.element_id(BACKGROUND_GRADIENT);
+            let stop = |offset: u8, color: &str| {
+                SvgNode::element("stop")
+                    .with_attribute("offset", offset)
+                    .with_attribute("stop-color", color)
+            };
+            root.extend([
+                SvgNode::element("defs").with_child(
+                    SvgNode::element("linearGradient")
+                        .with_attribute("id", &gradient)
+                        .with_attribute("x1", 0)
+                        .with_attribute("y1", 0)
+                        .with_attribute("x2", 0)
+                        .with_attribute("y2", 1)
+                        .with_child(stop(0, top))
+                        .with_child(stop(1, bottom)),
+                ),
+                background(&format!("url(#{gradient})")),
+            ]);
+        }
+        if let Some(paper) = options.paper {
+            root.extend(generate_paper(self.width, self.height, paper));
+        }
+        root
+    }
+
+    /// An `id` for the element called `name`, made unique to this message so several drawings can share one document.
+    ///
+    /// Characters of the message ID that cannot appear in a `url(#...)` reference are replaced with `_`.
+    fn element_id(&self, name: &str) -> String {
+        let id: String = self
+            .id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{name}-{id}")
+    }
+
+    /// A short summary of the drawing for screen readers, such as `Handwritten message, 3 strokes, created 2023-05-14`.
+    ///
+    /// The date is left out if [`created_at`](Self::created_at) is out of range.
+    fn describe(&self) -> String {
+        let strokes = self.strokes.len();
+        let mut desc = format!(
+            "Handwritten message, {strokes} {}",
+            if strokes == 1 { "stroke" } else { "strokes" }
+        );
+        if let Some(created_at) = self.created_at_datetime() {
+            let _ = write!(desc, ", created {}", created_at.format("%Y-%m-%d"));
+        }
+        desc
+    }
+
+    /// Renders the handwriting message as an `svg` graphic wrapped in a `base64` data URI.
+    ///
+    /// The result can be used directly as the `src` of an `<img>` or in a CSS `url()`.
+    #[must_use]
+    pub fn render_svg_data_uri(&self) -> String {
+        format!(
+            "data:image/svg+xml;base64,{}",
+            base64::encode(self.render_svg().as_bytes())
+        )
+    }
+
+    /// Renders the handwriting message as a compact `svg` graphic for embedding many drawings in one page.
+    ///
+    /// The title, metadata, and whitespace are left out, and segments of the same width share a CSS class.
+    #[must_use]
+    pub fn render_svg_minified(&self) -> String {
+        generate_minified(self.width, self.height, &self.strokes)
+    }
+
+    /// Renders the handwriting message as a square `svg` thumbnail, `max_dimension` units on each side.
+    ///
+    /// The drawing's [`bounding_box()`](Self::bounding_box) is scaled uniformly and centered with a small
+    /// margin, so the padded canvas does not waste space in tiny previews.
+    #[must_use]
+    pub fn render_thumbnail_svg(&self, max_dimension: u16) -> String {
+        generate_thumbnail(&self.strokes, self.bounding_box(), max_dimension)
+    }
+
+    /// Renders the handwriting message as a single-page `pdf` document the size of the drawing.
+    ///
+    /// Strokes are drawn as vector paths, so the output can be printed at any size.
+    pub fn render_pdf(&self) -> Result<Vec<u8>, HandwritingError> {
+        generate_pdf(&self.strokes, self.width, self.height)
+    }
+
+    /// Renders the handwriting message as a `png` image, `scale` pixels per canvas unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`HandwritingError::InvalidScale`] if `scale` is `0`, or if the scaled image would be wider or taller
+    /// than [`MAX_IMAGE_DIMENSION`]. The same applies to every raster renderer.
+    #[cfg(feature = "png")]
+    pub fn render_png(&self, scale: u32) -> Result<Vec<u8>, HandwritingError> {
+        use crate::message_types::handwriting::raster::{encode_png, rasterize};
+        encode_png(&rasterize(&self.strokes, self.width, self.height, scale)?)
+    }
+
+    /// Rasterizes the handwriting message to an [`image::RgbaImage`], `scale` pixels per canvas unit.
+    ///
+    /// The pixels match [`render_png()`](Self::render_png), but are left unencoded so they can be composited,
+    /// resized, or saved in any format the `image` crate supports. Ink is black and the background is transparent.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self, scale: u32) -> Result<image::RgbaImage, HandwritingError> {
+        use crate::message_types::handwriting::raster::{rasterize, to_rgba_image};
+        to_rgba_image(&rasterize(&self.strokes, self.width, self.height, scale)?)
+    }
+
+    /// Renders the handwriting message as a lossless `webp` image, `scale` pixels per canvas unit.
+    ///
+    /// The drawing is rasterized the same way as [`render_png()`](Self::render_png).
+    #[cfg(feature = "webp")]
+    pub fn render_webp(&self, scale: u32) -> Result<Vec<u8>, HandwritingError> {
+        use crate::message_types::handwriting::raster::{encode_webp, rasterize};
+        encode_webp(&rasterize(&self.strokes, self.width, self.height, scale)?)
+    }
+
+    /// Renders the handwriting message as a looping `gif` animation that draws the strokes in order, `scale` pixels per canvas unit.
+    ///
+    /// The strokes are split evenly across `frames` frames, and the last frame shows the whole drawing.
+    #[cfg(feature = "gif")]
+    pub fn render_gif(&self, frames: u32, scale: u32) -> Result<Vec<u8>, HandwritingError> {
+        use crate::message_types::handwriting::raster::{encode_gif, rasterize};
+
+        let frames = usize::try_from(frames).map_err(|_| HandwritingError::ConversionError)?;
+        let pixmaps = (1..=frames)
+            .map(|frame| {
+                // Round up so the last frame always includes every stroke
+                let strokes = (self.strokes.len() * frame).div_ceil(frames);
+                rasterize(
+                    self.select_strokes(0..strokes),
+                    self.width,
+                    self.height,
+                    scale,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        encode_gif(&pixmaps)
+    }
+
+    /// Walks every stroke once, sending its points to `sink`.
+    ///
+    /// This is how the `svg` and ASCII renderers draw, so new output formats can be added by implementing [`StrokeSink`].
+    pub fn drive(&self, sink: &mut impl StrokeSink) {
+        drive(&self.strokes, sink);
+    }
+
+    /// Lists every point as a line of `stroke_index x y width`, with a blank line between strokes.
+    ///
+    /// This is meant for diffing against other decoders, so coordinates are written exactly as stored in [`strokes`](Self::strokes).
+    #[must_use]
+    pub fn render_coordinates(&self) -> String {
+        let mut text = String::new();
+        for (idx, stroke) in self.strokes.iter().enumerate() {
+            if idx > 0 {
+                text.push('\n');
+            }
+            for point in stroke {
+                let _ = writeln!(text, "{idx} {} {} {}", point.x, point.y, point.width);
+            }
+        }
+        text
+    }
+
+    /// Renders the strokes as a GeoJSON `FeatureCollection`, for plotting and mapping tools.
+    ///
+    /// Each stroke is a `LineString` feature with `[x, y]` coordinates in canvas units, exactly as stored in
+    /// [`strokes`](Self::strokes), and properties holding its index as `stroke` and each point's width as `width`.
+    /// A `LineString` needs at least two positions, so a stroke with a single point repeats it.
+    #[must_use]
+    pub fn render_geojson(&self) -> String {
+        let mut json = String::from(r#"{"type":"FeatureCollection","features":["#);
+        for (idx, stroke) in self.strokes.iter().enumerate() {
+            if idx > 0 {
+                json.push(',');
+            }
+            let mut coordinates: Vec<String> = stroke
+                .iter()
+                .map(|point| format!("[{},{}]", point.x, point.y))
+                .collect();
+            if let [point] = coordinates.as_slice() {
+                coordinates.push(point.clone());
+            }
+            let widths: Vec<String> = stroke.iter().map(|point| point.width.to_string()).collect();
+            let _ = write!(
+                json,
+                r#"{{"type":"Feature","geometry":{{"type":"LineString","coordinates":[{}]}},"properties":{{"stroke":{idx},"width":[{}]}}}}"#,
+                coordinates.join(","),
+                widths.join(",")
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+
+    /// Renders the handwriting message as an ASCII graphic with a maximum height.
+    #[must_use]
+    pub fn render_ascii(&self, max_height: usize) -> String {
+        self.render_ascii_with_char(max_height, '*')
+    }
+
+    /// Renders the handwriting message as an ASCII graphic with a maximum height, drawing ink with `ink`.
+    ///
+    /// Empty space is always drawn with spaces. If `max_height` is `0`, the output is empty.
+    #[must_use]
+    pub fn render_ascii_with_char(&self, max_height: usize, ink: char) -> String {
+        self.render_ascii_strokes(&self.strokes, max_height, ink, 0)
+    }
+
+    /// The `(rows, columns)` of [`render_ascii()`](Self::render_ascii) with the same `max_height`, without rendering it.
+    ///
+    /// The height is limited by [`height`](Self::height) as well as `max_height`, and the width keeps the canvas's aspect ratio.
+    /// A margin from [`render_ascii_with_margin()`](Self::render_ascii_with_margin) adds `2 * margin` to both.
+    #[must_use]
+    pub fn ascii_dimensions(&self, max_height: usize) -> (usize, usize) {
+        let rows = max_height.min(self.height as usize);
+        if rows == 0 {
+            return (0, 0);
+        }
+        let columns = ((self.width as usize) * rows)
+            .checked_div(self.height as usize)
+            .unwrap_or(0);
+        (rows, columns)
+    }
+
+    /// Renders the handwriting message as an ASCII graphic with a maximum height, surrounded by `margin` blank cells on every side.
+    ///
+    /// The margin is added outside of `max_height`, so the drawing is the same size as [`render_ascii()`](Self::render_ascii)
+    /// and the output is `2 * margin` rows taller and columns wider. If `max_height` is `0`, the output is empty.
+    #[must_use]
+    pub fn render_ascii_with_margin(&self, max_height: usize, margin: usize) -> String {
+        self.render_ascii_strokes(&self.strokes, max_height, '*', margin)
+    }
+
+    /// Renders the handwriting message as an ASCII graphic exactly `rows` tall and `columns` wide.
+    ///
+    /// Unlike [`render_ascii()`](Self::render_ascii), the grid size does not have to match the canvas: both axes are scaled
+    /// by the smaller of the two ratios, so circles stay circular, and the drawing is centered along the other axis.
+    /// If either dimension is `0`, the output is empty.
+    #[must_use]
+    pub fn render_ascii_uniform(&self, rows: usize, columns: usize) -> String {
+        let (rows, columns) = (rows.min(u16::MAX.into()), columns.min(u16::MAX.into()));
+        if rows == 0 || columns == 0 {
+            return String::new();
+        }
+        let strokes = fit_strokes_uniform(
+            &self.strokes,
+            rows as u16,
+            columns as u16,
+            self.width,
+            self.height,
+            1,
+        );
+        let mut sink = AsciiSink {
+            canvas: vec![vec![' '; columns]; rows],
+            ink: '*',
+            // The strokes are already on the grid
+            scale_x: (1, 1),
+            scale_y: (1, 1),
+            last: None,
+        };
+        drive(&strokes, &mut sink);
+        write_ascii(sink.canvas, 0)
+    }
+
+    /// Renders only the strokes in `stroke_range` as an ASCII graphic with a maximum height, on the full canvas.
+    ///
+    /// The range is clamped the same way as [`render_svg_partial()`](Self::render_svg_partial).
+    #[must_use]
+    pub fn render_ascii_partial(&self, max_height: usize, stroke_range: Range<usize>) -> String {
+        self.render_ascii_strokes(self.select_strokes(stroke_range), max_height, '*', 0)
+    }
+
+    /// Draws `strokes` as an ASCII graphic on this message's canvas, with `margin` blank cells around it.
+    fn render_ascii_strokes(
+        &self,
+        strokes: &[Vec<Point>],
+        max_height: usize,
+        ink: char,
+        margin: usize,
+    ) -> String {
+        // Create a blank canvas filled with spaces
+        let (h, w) = self.ascii_dimensions(max_height);
+        if h == 0 {
+            return String::new();
+        }
+        let mut sink = AsciiSink {
+            canvas: vec![vec![' '; w]; h],
+            ink,
+            // Both axes are scaled by the same ratio, since `w` is derived from `h`
+            scale_x: (h as u16, self.height),
+            scale_y: (w as u16, self.width),
+            last: None,
+        };
+
+        // Plot the lines on the canvas
+        drive(strokes, &mut sink);
+        write_ascii(sink.canvas, margin)
+    }
+
+    /// Renders the handwriting message as Unicode Braille characters with a maximum height in characters.
+    ///
+    /// Each character holds a 2x4 grid of dots, so the output has the same dimensions as
+    /// [`render_ascii()`](Self::render_ascii) with twice the horizontal and four times the vertical resolution.
+    #[must_use]
+    pub fn render_braille(&self, max_height_cells: usize) -> String {
+        // Create a blank canvas of empty Braille cells, the same size as the ASCII canvas
+        let (h, w) = self.ascii_dimensions(max_height_cells);
+        let mut cells = vec![vec![0u8; w]; h];
+
+        // Plot the lines on the dot grid
+        for line in &fit_strokes(
+            &self.strokes,
+            (h * 4) as u16,
+            (w * 2) as u16,
+            self.width,
+            self.height,
+            1,
+        ) {
+            line.windows(2).for_each(|window| {
+                walk_line(&window[0], &window[1], |x, y| {
+                    draw_braille_dot(&mut cells, x, y);
+                });
+            });
+        }
+
+        // Convert the cells to a string
+        let mut output = String::with_capacity(h * (w * 3 + 1));
+        for row in cells {
+            for dots in row {
+                output.push(char::from_u32(0x2800 + u32::from(dots)).unwrap_or(' '));
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+/// Sets the dot at sub-pixel `(x, y)` on a grid of Braille cells that are each 2 dots wide and 4 dots tall.
+fn draw_braille_dot(cells: &mut [Vec<u8>], x: i64, y: i64) {
+    // Dot numbering for a Braille cell, see https://en.wikipedia.org/wiki/Braille_Patterns
+    const DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    if x < 0 || y < 0 {
+        return;
+    }
+    let (x, y) = (x as usize, y as usize);
+    if let Some(cell) = cells.get_mut(y / 4).and_then(|row| row.get_mut(x / 2)) {
+        *cell |= DOTS[y % 4][x % 2];
+    }
+}
+
+/// Converts a 2d character grid to a string, surrounded by `margin` blank cells on every side.
+fn write_ascii(canvas: Vec<Vec<char>>, margin: usize) -> String {
+    let (h, w) = (canvas.len(), canvas.first().map_or(0, Vec::len));
+    let blank_row = " ".repeat(w + margin * 2);
+    let side = " ".repeat(margin);
+    let mut output = String::with_capacity((h + margin * 2) * (w + margin * 2 + 1));
+    for _ in 0..margin {
+        output.push_str(&blank_row);
+        output.push('\n');
+    }
+    for row in canvas {
+        output.push_str(&side);
+        for &ch in &row {
+            let _ = write!(output, "{ch}");
+        }
+        output.push_str(&side);
+        output.push('\n');
+    }
+    for _ in 0..margin {
+        output.push_str(&blank_row);
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Plots strokes as lines of `ink` on a 2d character grid, ignoring widths.
+struct AsciiSink {
+    canvas: Vec<Vec<char>>,
+    ink: char,
+    /// The `(box_size, max_v)` passed to [`resize()`] for `x` coordinates
+    scale_x: (u16, u16),
+    /// The `(box_size, max_v)` passed to [`resize()`] for `y` coordinates
+    scale_y: (u16, u16),
+    /// The previous point of the current stroke, on the canvas
+    last: Option<Point>,
+}
+
+impl AsciiSink {
+    fn fit(&self, point: &Point) -> Point {
+        Point {
+            x: resize(point.x, self.scale_x.0, self.scale_x.1),
+            y: resize(point.y, self.scale_y.0, self.scale_y.1),
+            width: 1,
+        }
+    }
+}
+
+impl StrokeSink for AsciiSink {
+    fn begin_stroke(&mut self, point: &Point) {
+        self.last = Some(self.fit(point));
+    }
+
+    fn line_to(&mut self, point: &Point) {
+        let point = self.fit(point);
+        if let Some(last) = &self.last {
+            walk_line(last, &point, |x, y| {
+                draw_point(&mut self.canvas, x, y, self.ink);
+            });
+        }
+        self.last = Some(point);
+    }
+
+    fn set_width(&mut self, _width: u16) {}
+
+    fn end_stroke(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Draws a point on a 2d character grid.
+fn draw_point(canvas: &mut [Vec<char>], x: i64, y: i64, ink: char) {
+    let width = canvas.first().map_or(0, Vec::len);
+    if x >= 0 && x < width as i64 && y >= 0 && y < canvas.len() as i64 {
+        canvas[y as usize][x as usize] = ink;
+    }
+}
+
+/// Parses decompressed stroke data into an array of strokes.
+///
+/// `progress` receives `(points_parsed, points_total_estimate)` every [`PROGRESS_INTERVAL`] points and once parsing completes.
+fn parse_strokes(
+    data: &[u8],
+    options: &ParseOptions,
+    progress: &mut dyn FnMut(usize, usize),
+    warn: &mut dyn FnMut(HandwritingWarning),
+) -> Result<Vec<Vec<Point>>, HandwritingError> {
+    let mut strokes = vec![];
+    let mut idx = 0;
+    let length = data.len();
+    let total_estimate = length / 8;
+    let mut points_parsed = 0;
+    while idx < length {
+        if idx + 1 >= length {
+            return Err(HandwritingError::InvalidStrokesLength(idx + 2, length, idx));
+        }
+
+        let num_points = u16::from_le_bytes([data[idx], data[idx + 1]]) as usize;
+        idx += 2;
+        if idx + (num_points * 8) > length {
+            return Err(HandwritingError::InvalidStrokesLength(
+                idx + (num_points * 8),
+                length,
+                idx,
+            ));
+        }
+
+        let mut stroke = vec![];
+        (0..num_points).try_for_each(|_| -> Result<(), HandwritingError> {
+            let x = parse_coordinates(data[idx], data[idx + 1]);
+            let y = parse_coordinates(data[idx + 2], data[idx + 3]);
+            let width = parse_coordinates(data[idx + 4], data[idx + 5]);
+            // The last value is always `0x7FFF` in known payloads, so it is skipped
+            idx += 8;
+            if options.split_pen_lifts && x == PEN_UP_SENTINEL && y == PEN_UP_SENTINEL {
+                // Split the stroke so the points on either side of the lift are not connected
+                if !stroke.is_empty() {
+                    strokes.push(std::mem::take(&mut stroke));
+                    check_stroke_count(strokes.len(), options.max_strokes)?;
+                }
+            } else {
+                stroke.push(Point { x, y, width });
+            }
+            points_parsed += 1;
+            if points_parsed % PROGRESS_INTERVAL == 0 {
+                progress(points_parsed, total_estimate);
+            }
+            Ok(())
+        })?;
+        // Keep empty strokes from the payload, but not ones left empty by a trailing lift
+        if !stroke.is_empty() || num_points == 0 {
+            strokes.push(stroke);
+            check_stroke_count(strokes.len(), options.max_strokes)?;
+        } else {
+            warn(HandwritingWarning::EmptyStrokeDropped);
+        }
+    }
+    progress(points_parsed, total_estimate);
+    Ok(strokes)
+}
+
+/// Fails with [`HandwritingError::TooManyStrokes`] if `count` strokes is more than `max_strokes`
+fn check_stroke_count(count: usize, max_strokes: usize) -> Result<(), HandwritingError> {
+    if count > max_strokes {
+        return Err(HandwritingError::TooManyStrokes(count, max_strokes));
+    }
+    Ok(())
+}
+
+/// Fits already-parsed strokes from a canvas of `max_x` by `max_y` to a canvas of `height` and `width`, keeping point widths.
+fn refit_strokes(
+    strokes: &[&Vec<Point>],
+    height: u16,
+    width: u16,
+    max_x: u16,
+    max_y: u16,
+) -> Vec<Vec<Point>> {
+    // Move widths back into `0..=9` so fitting them again returns the same widths
+    let strokes: Vec<Vec<Point>> = strokes
+        .iter()
+        .map(|stroke| {
+            stroke
+                .iter()
+                .map(|point| Point {
+                    x: point.x,
+                    y: point.y,
+                    width: point.width.saturating_sub(1),
+                })
+                .collect()
+        })
+        .collect();
+    fit_strokes(&strokes, height, width, max_x, max_y, 9)
+}
+
+/// Clamps or discards points with coordinates beyond the limit set in `outliers`.
+fn handle_outliers(
+    strokes: &mut Vec<Vec<Point>>,
+    outliers: OutlierHandling,
+    warn: &mut dyn FnMut(HandwritingWarning),
+) {
+    match outliers {
+        OutlierHandling::Keep => {}
+        OutlierHandling::Clamp(limit) => {
+            strokes.iter_mut().flatten().for_each(|point| {
+                if point.x > limit || point.y > limit {
+                    point.x = point.x.min(limit);
+                    point.y = point.y.min(limit);
+                    warn(HandwritingWarning::PointClamped);
+                }
+            });
+        }
+        OutlierHandling::Discard(limit) => {
+            strokes.iter_mut().for_each(|stroke| {
+                stroke.retain(|point| {
+                    let keep = point.x <= limit && point.y <= limit;
+                    if !keep {
+                        warn(HandwritingWarning::PointDropped);
+                    }
+                    keep
+                });
+            });
+            strokes.retain(|stroke| {
+                if stroke.is_empty() {
+                    warn(HandwritingWarning::EmptyStrokeDropped);
+                }
+                !stroke.is_empty()
+            });
+        }
+    }
+}
+
+/// Clamps raw point widths into `1..=MAX_RAW_WIDTH`, returning the number of points that changed.
+///
+/// Widths are scaled against the widest point, so a single corrupt width would otherwise make every other line hairline thin.
+fn clamp_widths(strokes: &mut [Vec<Point>], warn: &mut dyn FnMut(HandwritingWarning)) -> usize {
+    let mut clamped = 0;
+    for point in strokes.iter_mut().flatten() {
+        let width = point.width.clamp(1, MAX_RAW_WIDTH);
+        if width != point.width {
+            warn(HandwritingWarning::WidthOutOfRange(point.width));
+            point.width = width;
+            clamped += 1;
+        }
+    }
+    clamped
+}
+
+/// Decompresses raw stroke data and verifies length.
+fn decompress_strokes(msg: &BaseMessage) -> Result<Vec<u8>, HandwritingError> {
+    let data = match msg.Handwriting.Compression.enum_value_or_default() {
+        Compression::None => msg.Handwriting.Strokes.clone(),
+        Compression::XZ => xz_decompress(&msg.Handwriting.Strokes)?,
+        Compression::Unknown => {
+            return Err(HandwritingError::CompressionUnknown);
+        }
+    };
+
+    let length = match msg.Handwriting.Compression.enum_value_or_default() {
+        // Uncompressed payloads do not need a length, but one that is set must still match
+        Compression::None => match msg.Handwriting.DecompressedLength {
+            Some(length) => {
+                usize::try_from(length).map_err(|_| HandwritingError::ConversionError)?
+            }
+            None => data.len(),
+        },
+        Compression::XZ => {
+            if let Some(decompress_size) = msg.Handwriting.DecompressedLength {
+                usize::try_from(decompress_size).map_err(|_| HandwritingError::ConversionError)?
+            } else {
+                return Err(HandwritingError::DecompressedNotSet);
+            }
+        }
+        Compression::Unknown => {
+            return Err(HandwritingError::CompressionUnknown);
+        }
+    };
+
+    if length != data.len() {
+        return Err(HandwritingError::InvalidDecompressedLength(
+            length,
+            data.len(),
+        ));
+    }
+    Ok(data)
+}
+
+/// Decompresses XZ data with the pure Rust `lzma-rs`.
+#[cfg(not(feature = "liblzma-backend"))]
+fn xz_decompress(data: &[u8]) -> Result<Vec<u8>, HandwritingError> {
+    let mut buf = Vec::new();
+    lzma_rs::xz_decompress(&mut std::io::Cursor::new(data), &mut buf)
+        .map_err(HandwritingError::XZError)?;
+    Ok(buf)
+}
+
+/// Decompresses XZ data with liblzma, which is faster than `lzma-rs` on large payloads.
+///
+/// Errors are reported the same way as the default backend, so callers cannot tell which one is in use.
+#[cfg(feature = "liblzma-backend")]
+fn xz_decompress(data: &[u8]) -> Result<Vec<u8>, HandwritingError> {
+    let mut buf = Vec::new();
+    xz2::read::XzDecoder::new_multi_decoder(data)
+        .read_to_end(&mut buf)
+        .map_err(|why| HandwritingError::XZError(lzma_rs::error::Error::IoError(why)))?;
+    Ok(buf)
+}
+
+/// Parses the protobuf message, ensuring it contains handwriting data.
+fn parse_message(payload: &[u8]) -> Result<BaseMessage, HandwritingError> {
+    if !starts_like_handwriting(payload) {
+        return Err(HandwritingError::NotHandwritingPayload);
+    }
+    let msg = BaseMessage::parse_from_bytes(payload).map_err(HandwritingError::ProtobufError)?;
+    match msg.Handwriting.as_ref() {
+        Some(handwriting) if *handwriting != Handwriting::default() => Ok(msg),
+        _ => Err(HandwritingError::MissingHandwritingData),
+    }
+}
+
+/// Ensures neither the protobuf message nor its handwriting data contain unknown fields.
+fn check_unknown_fields(msg: &BaseMessage) -> Result<(), HandwritingError> {
+    let mut fields: Vec<u32> = msg
+        .unknown_fields()
+        .iter()
+        .chain(msg.Handwriting.unknown_fields().iter())
+        .map(|(number, _)| number)
+        .collect();
+    if fields.is_empty() {
+        return Ok(());
+    }
+    fields.sort_unstable();
+    fields.dedup();
+    Err(HandwritingError::UnknownFields(fields))
+}
+
+/// The position and size of a drawing, as stored in the protobuf message.
+struct Frame {
+    origin: (i16, i16),
+    width: u16,
+    height: u16,
+}
+
+/// Parses the drawing origin and size from the protobuf message.
+fn parse_frame(msg: &BaseMessage) -> Result<Frame, HandwritingError> {
+    let rect = &msg.Handwriting.Frame;
+    if rect.len() != FRAME_SIZE {
+        return Err(HandwritingError::InvalidFrameSize(FRAME_SIZE, rect.len()));
+    }
+    Ok(Frame {
+        origin: (
+            parse_coordinates(rect[0], rect[1]).cast_signed(),
+            parse_coordinates(rect[2], rect[3]).cast_signed(),
+        ),
+        width: parse_coordinates(rect[4], rect[5]),
+        height: parse_coordinates(rect[6], rect[7]),
+    })
+}
+
+/// Converts coordinate bytes to an u16.
+fn parse_coordinates(b1: u8, b2: u8) -> u16 {
+    u16::from_le_bytes([b1, b2]) ^ 0x8000
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::handwriting::HandwritingError,
+        message_types::handwriting::{
+            geometry::{fit_strokes, get_max_dimension},
+            handwriting_proto::{BaseMessage, Compression},
+            models::{
+                BLANK_INK_LENGTH, CROP_MARGIN, FRAME_PADDING, HandwritingWarning,
+                HandwrittenMessage, INVISIBLE_INK_EFFECT, MAX_COORDINATE, MAX_RAW_WIDTH,
+                OutlierHandling, Padding, ParseOptions, Point, parse_message, xz_decompress,
+            },
+            svg::{
+                ANIMATION_LOOP_PAUSE_SECONDS, ANIMATION_STROKE_SECONDS, AnimationOptions, LineCap,
+                LineJoin, PaperStyle, SvgRenderOptions, SvgSink, group_points,
+            },
+        },
+        util::base64,
+    };
+
+    use chrono::NaiveDate;
+    use protobuf::{EnumOrUnknown, Message};
+
+    use std::env::current_dir;
+    use std::fs::File;
+    use std::io::Read;
+
+    /// Builds an uncompressed handwriting payload from a raw frame and raw stroke data
+    fn build_payload(frame: Vec<u8>, strokes: Vec<u8>) -> Vec<u8> {
+        let mut msg = BaseMessage::new();
+        msg.ID = "test".to_string();
+        msg.Handwriting.mut_or_insert_default().Frame = frame;
+        msg.Handwriting.mut_or_insert_default().Compression = EnumOrUnknown::new(Compression::None);
+        msg.Handwriting.mut_or_insert_default().Strokes = strokes;
+        msg.write_to_bytes().unwrap()
+    }
+
+    #[test]
+    fn test_render_svg_escapes_id() {
+        let mut msg = BaseMessage::new();
+        msg.ID = "</title><script>alert(1)</script>".to_string();
+        msg.Handwriting.mut_or_insert_default().Frame =
+            vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        msg.Handwriting.mut_or_insert_default().Compression = EnumOrUnknown::new(Compression::None);
+        msg.Handwriting.mut_or_insert_default().Strokes =
+            encode_strokes(&[&[(0, 0, 1), (5, 5, 1)]]);
+        let balloon = HandwrittenMessage::from_payload(&msg.write_to_bytes().unwrap()).unwrap();
+
+        let svg = balloon.render_svg();
+        assert!(!svg.contains("<script>"));
+        assert!(svg.contains("<title>&lt;/title>&lt;script>alert(1)&lt;/script></title>"));
+        assert!(svg.contains("<id>&lt;/title>&lt;script>alert(1)&lt;/script></id>"));
+        roxmltree::Document::parse(&svg).unwrap();
+    }
+
+    /// Encodes strokes of `(x, y, width)` points into raw stroke data
+    fn encode_strokes(strokes: &[&[(u16, u16, u16)]]) -> Vec<u8> {
+        let mut data = vec![];
+        for stroke in strokes {
+            data.extend_from_slice(&(stroke.len() as u16).to_le_bytes());
+            for (x, y, width) in *stroke {
+                for value in [*x, *y, *width, 0x7FFF] {
+                    data.extend_from_slice(&(value ^ 0x8000).to_le_bytes());
+                }
+            }
+        }
+        data
+    }
 
     #[test]
     fn test_parse_handwritten_from_payload() {
@@ -361,8 +2020,14 @@ mod tests {
         let expected = HandwrittenMessage {
             id: "e8fae151-5b83-4efa-98c6-b207381f004c".to_string(),
             created_at: 577234961941,
+            origin: (34, 12),
             height: 243,
             width: 753,
+            padding: 5,
+            frame_width: 748,
+            frame_height: 238,
+            clamped_widths: 0,
+            effect: None,
             strokes: vec![
                 vec![
                     Point {
@@ -10984,54 +12649,2156 @@ mod tests {
                         width: 2,
                     },
                     Point {
-                        x: 344,
-                        y: 211,
-                        width: 2,
+                        x: 344,
+                        y: 211,
+                        width: 2,
+                    },
+                    Point {
+                        x: 348,
+                        y: 211,
+                        width: 2,
+                    },
+                    Point {
+                        x: 352,
+                        y: 211,
+                        width: 2,
+                    },
+                    Point {
+                        x: 355,
+                        y: 211,
+                        width: 3,
+                    },
+                    Point {
+                        x: 359,
+                        y: 210,
+                        width: 4,
+                    },
+                    Point {
+                        x: 361,
+                        y: 210,
+                        width: 6,
+                    },
+                    Point {
+                        x: 362,
+                        y: 209,
+                        width: 7,
+                    },
+                    Point {
+                        x: 361,
+                        y: 210,
+                        width: 6,
+                    },
+                ],
+            ],
+        };
+
+        assert_eq!(balloon, expected);
+    }
+
+    #[test]
+    fn test_render_coordinates() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let text = balloon.render_coordinates();
+        let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(
+            lines.len(),
+            balloon.strokes.iter().map(Vec::len).sum::<usize>()
+        );
+        assert_eq!(
+            text.lines().filter(|line| line.is_empty()).count(),
+            balloon.strokes.len() - 1
+        );
+
+        let first = &balloon.strokes[0][0];
+        assert_eq!(
+            lines[0],
+            format!("0 {} {} {}", first.x, first.y, first.width)
+        );
+        let last_stroke = balloon.strokes.len() - 1;
+        assert!(
+            lines
+                .last()
+                .unwrap()
+                .starts_with(&format!("{last_stroke} "))
+        );
+    }
+
+    #[test]
+    fn test_ascii_dimensions() {
+        for name in ["handwriting", "hello", "pollock", "test"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join(format!("test_data/handwritten_message/{name}.bin"));
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+            for max_height in [1, 20, 33, 1000] {
+                let (rows, columns) = balloon.ascii_dimensions(max_height);
+                let ascii = balloon.render_ascii(max_height);
+                assert_eq!(ascii.lines().count(), rows, "{name} at {max_height}");
+                assert!(
+                    ascii.lines().all(|line| line.chars().count() == columns),
+                    "{name} at {max_height}"
+                );
+            }
+            assert_eq!(balloon.ascii_dimensions(0), (0, 0));
+        }
+    }
+
+    #[test]
+    fn test_render_ascii_with_margin() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let plain = balloon.render_ascii(20);
+        assert_eq!(balloon.render_ascii_with_margin(20, 0), plain);
+
+        let framed = balloon.render_ascii_with_margin(20, 2);
+        let plain_lines: Vec<&str> = plain.lines().collect();
+        let lines: Vec<&str> = framed.lines().collect();
+        assert_eq!(lines.len(), plain_lines.len() + 4);
+        let width = plain_lines[0].chars().count() + 4;
+        assert!(lines.iter().all(|line| line.chars().count() == width));
+
+        // Two blank rows above and below, and two blank columns on each side
+        for line in lines[..2].iter().chain(&lines[lines.len() - 2..]) {
+            assert!(line.chars().all(|ch| ch == ' '));
+        }
+        for (line, plain_line) in lines[2..lines.len() - 2].iter().zip(&plain_lines) {
+            assert_eq!(*line, format!("  {plain_line}  "));
+        }
+
+        assert_eq!(balloon.render_ascii_with_margin(0, 2), "");
+    }
+
+    #[test]
+    fn test_render_ascii_uniform_keeps_square() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x64, 0x80, 0x64, 0x80];
+        let square = [
+            (0, 0, 1),
+            (100, 0, 1),
+            (100, 100, 1),
+            (0, 100, 1),
+            (0, 0, 1),
+        ];
+        let strokes = encode_strokes(&[&square]);
+        let balloon = HandwrittenMessage::from_payload(&build_payload(frame, strokes)).unwrap();
+
+        // The ink's `(rows, columns)` and the first column with ink
+        let extent = |ascii: &str| {
+            let lines: Vec<&str> = ascii.lines().collect();
+            let inked: Vec<usize> = lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| line.contains('*'))
+                .map(|(idx, _)| idx)
+                .collect();
+            let left = lines
+                .iter()
+                .filter_map(|line| line.find('*'))
+                .min()
+                .unwrap();
+            let right = lines
+                .iter()
+                .filter_map(|line| line.rfind('*'))
+                .max()
+                .unwrap();
+            (inked.len(), right - left + 1, left)
+        };
+
+        // A wide grid would stretch the square, so it is drawn square and centered instead
+        let ascii = balloon.render_ascii_uniform(10, 40);
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|line| line.chars().count() == 40));
+        let (rows, columns, left) = extent(&ascii);
+        assert!(rows.abs_diff(columns) <= 1, "{rows}x{columns}\n{ascii}");
+        assert!(left.abs_diff((40 - columns) / 2) <= 1, "{left}\n{ascii}");
+
+        // A tall grid centers the square vertically
+        let ascii = balloon.render_ascii_uniform(40, 10);
+        let (rows, columns, _) = extent(&ascii);
+        assert!(rows.abs_diff(columns) <= 1, "{rows}x{columns}\n{ascii}");
+        let top = ascii.lines().position(|line| line.contains('*')).unwrap();
+        assert!(top.abs_diff((40 - rows) / 2) <= 1, "{top}\n{ascii}");
+
+        assert_eq!(balloon.render_ascii_uniform(0, 40), "");
+        assert_eq!(balloon.render_ascii_uniform(10, 0), "");
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.ascii");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_ascii(40), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii_half() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting_half.ascii");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_ascii(20), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii_old() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/test.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/test.ascii");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_ascii(20), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii_builtin() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.ascii");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_ascii(20), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii_pollock() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.ascii");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_ascii(20), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii_custom_char() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let ascii = balloon.render_ascii(20);
+        let blocks = balloon.render_ascii_with_char(20, '█');
+        assert!(blocks.contains('█'));
+        assert!(!blocks.contains('*'));
+        assert_eq!(blocks, ascii.replace('*', "█"));
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_ascii_zero_height() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let mut balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        assert_eq!(balloon.render_ascii(0), "");
+
+        balloon.height = 0;
+        assert_eq!(balloon.render_ascii(10), "");
+    }
+
+    #[test]
+    fn test_drive_matches_legacy_svg() {
+        for name in ["handwriting", "hello", "pollock", "test"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join(format!("test_data/handwritten_message/{name}.bin"));
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+            // Build the lines the way `render_svg()` did before it used a sink
+            let mut expected = String::new();
+            for stroke in &balloon.strokes {
+                if let [point] = stroke.as_slice() {
+                    expected.push_str(&format!(
+                        r#"<circle cx="{}" cy="{}" r="{}" fill="black" />"#,
+                        point.x,
+                        point.y,
+                        f64::from(point.width) / 2.0
+                    ));
+                    expected.push('\n');
+                    continue;
+                }
+                for (width, points) in &group_points(stroke) {
+                    let points: Vec<String> = points
+                        .iter()
+                        .map(|point| format!("{},{}", point.x, point.y))
+                        .collect();
+                    expected.push_str(&format!(
+                        r#"<polyline class="line" points="{}" stroke-width="{width}" />"#,
+                        points.join(" ")
+                    ));
+                    expected.push('\n');
+                }
+            }
+
+            let mut actual = String::new();
+            let options = SvgRenderOptions::default();
+            balloon.drive(&mut SvgSink::new(&mut actual, &options));
+            assert_eq!(actual, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_braille() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let braille = balloon.render_braille(20);
+        let ascii = balloon.render_ascii(20);
+
+        // Same dimensions as the ASCII render
+        assert_eq!(braille.lines().count(), ascii.lines().count());
+        assert!(
+            braille
+                .lines()
+                .zip(ascii.lines())
+                .all(|(b, a)| b.chars().count() == a.chars().count())
+        );
+
+        // Every character is in the Braille block, and some of them have ink
+        assert!(
+            braille
+                .chars()
+                .filter(|ch| *ch != '\n')
+                .all(|ch| ('\u{2800}'..='\u{28FF}').contains(&ch))
+        );
+        assert!(braille.chars().any(|ch| ch > '\u{2800}'));
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_svg() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.svg");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_svg(), expected);
+    }
+
+    #[test]
+    fn test_estimated_svg_bytes() {
+        for name in ["handwriting", "hello", "pollock", "test"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join(format!("test_data/handwritten_message/{name}.bin"));
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+            let estimate = balloon.estimated_svg_bytes();
+            let actual = balloon.render_svg().len();
+            assert!(estimate >= actual, "{name}: {estimate} < {actual}");
+        }
+
+        // Every point is as long as possible and changes width, which is the worst case
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let mut zigzag = HandwrittenMessage::from_payload(&build_payload(frame, vec![])).unwrap();
+        zigzag.strokes = vec![
+            (0..100)
+                .map(|idx| Point {
+                    x: u16::MAX - idx,
+                    y: u16::MAX - idx,
+                    width: if idx % 2 == 0 { u16::MAX } else { 10_000 },
+                })
+                .collect(),
+        ];
+        assert!(zigzag.estimated_svg_bytes() >= zigzag.render_svg().len());
+    }
+
+    #[test]
+    fn test_render_svg_invisible_ink() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        assert_eq!(balloon.effect, None);
+        assert!(!balloon.is_invisible_ink());
+
+        let options = SvgRenderOptions {
+            apply_effects: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            balloon.render_svg_with_options(&options),
+            balloon.render_svg()
+        );
+
+        let hidden = HandwrittenMessage::from_payload(&data)
+            .unwrap()
+            .with_effect(Some("com.apple.MobileSMS.expressivesend.invisibleink"));
+        assert_eq!(hidden.effect.as_deref(), Some(INVISIBLE_INK_EFFECT));
+        assert!(hidden.is_invisible_ink());
+        assert_eq!(hidden.rotate(1).effect, hidden.effect);
+
+        // Effects are only drawn when asked for
+        assert_eq!(hidden.render_svg(), balloon.render_svg());
+        let svg = hidden.render_svg_with_options(&options);
+        roxmltree::Document::parse(&svg).unwrap();
+        assert!(svg.contains("<feGaussianBlur"));
+        assert!(svg.contains(r#"<g filter="url(#invisible-ink)">"#));
+
+        let loud = HandwrittenMessage::from_payload(&data)
+            .unwrap()
+            .with_effect(Some("com.apple.MobileSMS.expressivesend.loud"));
+        assert!(!loud.is_invisible_ink());
+        assert!(!loud.render_svg_with_options(&options).contains("filter"));
+    }
+
+    #[test]
+    fn test_render_svg_drop_shadow() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        assert!(!balloon.render_svg().contains("<filter"));
+
+        let options = SvgRenderOptions {
+            drop_shadow: true,
+            ..Default::default()
+        };
+        let svg = balloon.render_svg_with_options(&options);
+        let document = roxmltree::Document::parse(&svg).unwrap();
+        let filter = document
+            .descendants()
+            .find(|node| node.has_tag_name("filter"))
+            .unwrap();
+        assert_eq!(filter.attribute("id"), Some("drop-shadow"));
+        assert!(
+            filter
+                .children()
+                .any(|node| node.has_tag_name("feDropShadow"))
+        );
+        // The group holds every stroke
+        let group = document
+            .descendants()
+            .find(|node| node.attribute("filter") == Some("url(#drop-shadow)"))
+            .unwrap();
+        assert_eq!(
+            group
+                .descendants()
+                .filter(|node| node.has_tag_name("polyline"))
+                .count(),
+            svg.matches("<polyline").count()
+        );
+
+        // Both filters apply when a drawing with invisible ink is shadowed
+        let hidden = HandwrittenMessage::from_payload(&data)
+            .unwrap()
+            .with_effect(Some(INVISIBLE_INK_EFFECT));
+        let svg = hidden.render_svg_with_options(&SvgRenderOptions {
+            drop_shadow: true,
+            apply_effects: true,
+            ..Default::default()
+        });
+        roxmltree::Document::parse(&svg).unwrap();
+        assert_eq!(svg.matches("<filter ").count(), 2);
+        assert_eq!(svg.matches("<g filter=").count(), 2);
+    }
+
+    #[test]
+    fn test_render_svg_desc() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let mut balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let svg = balloon.render_svg();
+        let document = roxmltree::Document::parse(&svg).unwrap();
+        let desc = document
+            .descendants()
+            .find(|node| node.has_tag_name("desc"))
+            .and_then(|node| node.text())
+            .unwrap();
+        assert_eq!(
+            desc,
+            format!(
+                "Handwritten message, {} strokes, created 2019-04-17",
+                balloon.strokes.len()
+            )
+        );
+        assert!(svg.contains("<metadata>"));
+
+        balloon.strokes.truncate(1);
+        assert!(
+            balloon
+                .render_svg()
+                .contains("<desc>Handwritten message, 1 stroke, created 2019-04-17</desc>")
+        );
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_svg_old() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/test.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/test.svg");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_svg(), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_svg_builtin() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.svg");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_svg(), expected);
+    }
+
+    #[test]
+    fn test_parse_handwritten_as_svg_pollock() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut expected = String::new();
+        let expected_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.svg");
+        let mut expected_data = File::open(expected_path).unwrap();
+        expected_data.read_to_string(&mut expected).unwrap();
+
+        assert_eq!(balloon.render_svg(), expected);
+    }
+
+    #[test]
+    fn test_render_svg_single_point_stroke() {
+        let balloon = HandwrittenMessage {
+            id: "dot".to_string(),
+            created_at: 0,
+            origin: (0, 0),
+            height: 20,
+            width: 20,
+            padding: 5,
+            frame_width: 15,
+            frame_height: 15,
+            clamped_widths: 0,
+            effect: None,
+            strokes: vec![
+                vec![Point {
+                    x: 10,
+                    y: 4,
+                    width: 5,
+                }],
+                vec![
+                    Point {
+                        x: 10,
+                        y: 8,
+                        width: 5,
                     },
                     Point {
-                        x: 348,
-                        y: 211,
-                        width: 2,
+                        x: 10,
+                        y: 18,
+                        width: 5,
                     },
+                ],
+            ],
+        };
+
+        let svg = balloon.render_svg();
+        assert!(svg.contains(r#"<circle cx="10" cy="4" r="2.5" fill="black" />"#));
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert_eq!(svg.matches("<polyline").count(), 1);
+    }
+
+    #[test]
+    fn test_iter_strokes() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let strokes: Vec<_> = balloon.iter_strokes().collect();
+        assert_eq!(strokes.len(), balloon.strokes.len());
+        assert_eq!(strokes[0].start(), balloon.strokes[0].first());
+        assert_eq!(strokes[0].end(), balloon.strokes[0].last());
+        assert_eq!(
+            strokes.iter().map(|stroke| stroke.length()).sum::<f64>(),
+            balloon.ink_length()
+        );
+
+        let (max_x, max_y, _) = get_max_dimension(&balloon.strokes);
+        assert_eq!(
+            strokes
+                .iter()
+                .filter_map(|stroke| stroke.bounds())
+                .map(|bounds| bounds.max_x)
+                .max(),
+            Some(max_x)
+        );
+        assert_eq!(
+            strokes
+                .iter()
+                .filter_map(|stroke| stroke.bounds())
+                .map(|bounds| bounds.max_y)
+                .max(),
+            Some(max_y)
+        );
+    }
+
+    #[test]
+    fn test_is_blank() {
+        let mut balloon = HandwrittenMessage {
+            id: "tap".to_string(),
+            created_at: 0,
+            origin: (0, 0),
+            height: 20,
+            width: 20,
+            padding: 5,
+            frame_width: 15,
+            frame_height: 15,
+            clamped_widths: 0,
+            effect: None,
+            strokes: vec![],
+        };
+        assert!(balloon.is_blank());
+
+        balloon.strokes = vec![vec![
+            Point {
+                x: 10,
+                y: 10,
+                width: 2,
+            },
+            Point {
+                x: 12,
+                y: 11,
+                width: 2,
+            },
+        ]];
+        assert!(balloon.ink_length() < BLANK_INK_LENGTH);
+        assert!(balloon.is_blank());
+
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let drawing = HandwrittenMessage::from_payload(&data).unwrap();
+        assert!(drawing.ink_length() > 100.0);
+        assert!(!drawing.is_blank());
+    }
+
+    #[test]
+    fn test_same_drawing() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let mut resent = HandwrittenMessage::from_payload(&data).unwrap();
+        resent.id = "resent".to_string();
+        resent.created_at += 1000;
+        resent.origin = (10, 10);
+        assert!(balloon.same_drawing(&resent));
+        assert_ne!(balloon, resent);
+
+        let mut edited = HandwrittenMessage::from_payload(&data).unwrap();
+        edited.strokes.pop();
+        assert!(!balloon.same_drawing(&edited));
+
+        let mut resized = resent;
+        resized.width += 1;
+        assert!(!balloon.same_drawing(&resized));
+    }
+
+    #[test]
+    fn test_render_svg_pressure_opacity() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let default = balloon.render_svg();
+        let pressure = balloon.render_svg_with_options(&SvgRenderOptions {
+            pressure_opacity: true,
+            ..Default::default()
+        });
+
+        assert!(!default.contains("stroke-opacity"));
+        assert_eq!(
+            pressure.matches("stroke-opacity").count(),
+            pressure.matches("<polyline").count()
+        );
+        assert!(pressure.contains(r#"stroke-width="10" stroke-opacity="1.00""#));
+        assert!(pressure.contains(r#"stroke-width="1" stroke-opacity="0.25""#));
+    }
+
+    #[test]
+    fn test_render_svg_data_uri() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let uri = balloon.render_svg_data_uri();
+        let encoded = uri.strip_prefix("data:image/svg+xml;base64,").unwrap();
+        assert_eq!(
+            base64::decode(encoded).unwrap(),
+            balloon.render_svg().into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_render_svg_animated() {
+        let balloon = HandwrittenMessage {
+            id: "order".to_string(),
+            created_at: 0,
+            origin: (0, 0),
+            height: 20,
+            width: 20,
+            padding: 5,
+            frame_width: 15,
+            frame_height: 15,
+            clamped_widths: 0,
+            effect: None,
+            // Stored right to left
+            strokes: vec![
+                vec![
                     Point {
-                        x: 352,
-                        y: 211,
+                        x: 15,
+                        y: 0,
                         width: 2,
                     },
                     Point {
-                        x: 355,
-                        y: 211,
-                        width: 3,
-                    },
-                    Point {
-                        x: 359,
-                        y: 210,
-                        width: 4,
-                    },
-                    Point {
-                        x: 361,
-                        y: 210,
-                        width: 6,
-                    },
-                    Point {
-                        x: 362,
-                        y: 209,
-                        width: 7,
-                    },
-                    Point {
-                        x: 361,
-                        y: 210,
-                        width: 6,
+                        x: 15,
+                        y: 10,
+                        width: 2,
                     },
                 ],
+                vec![Point {
+                    x: 5,
+                    y: 5,
+                    width: 2,
+                }],
             ],
         };
+        let begin = |svg: &str, element: &str| {
+            let start = svg.find(element).unwrap();
+            let begin = &svg[start..][svg[start..].find(r#"begin=""#).unwrap() + 7..];
+            begin[..begin.find('"').unwrap()].to_string()
+        };
+
+        let chronological = balloon.render_svg_animated();
+        assert_eq!(begin(&chronological, r#"points="15,0"#), "0s");
+        assert_eq!(begin(&chronological, r#"<circle cx="5""#), "0.5s");
+        assert!(chronological.contains(r#"dur="0.5s""#));
+
+        let spatial = balloon.render_svg_animated_spatial();
+        assert_eq!(begin(&spatial, r#"<circle cx="5""#), "0s");
+        assert_eq!(
+            begin(&spatial, r#"points="15,0"#),
+            format!("{ANIMATION_STROKE_SECONDS}s")
+        );
+    }
+
+    #[test]
+    fn test_render_svg_animated_looping() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let once = balloon.render_svg_animated_with_options(&AnimationOptions { looping: false });
+        assert_eq!(once, balloon.render_svg_animated());
+        assert!(once.contains(r#"repeatCount="1""#));
+        assert!(!once.contains(r#"repeatCount="indefinite""#));
+        assert!(once.contains(r#"fill="freeze""#));
+
+        let looping = balloon.render_svg_animated_with_options(&AnimationOptions { looping: true });
+        roxmltree::Document::parse(&looping).unwrap();
+        assert!(looping.contains(r#"repeatCount="indefinite""#));
+        assert!(!looping.contains(r#"repeatCount="1""#));
+        // Every element shares one cycle so the strokes stay in order on each replay
+        let cycle =
+            balloon.strokes.len() as f64 * ANIMATION_STROKE_SECONDS + ANIMATION_LOOP_PAUSE_SECONDS;
+        assert_eq!(
+            looping.matches(r#"repeatCount="indefinite""#).count(),
+            looping.matches(&format!(r#"dur="{cycle}s""#)).count()
+        );
+        assert!(!looping.contains("begin="));
+    }
+
+    #[test]
+    fn test_render_svg_grouped() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let grouped = balloon.render_svg_grouped();
+        assert_eq!(grouped.matches("<g ").count(), balloon.strokes.len());
+        assert_eq!(grouped.matches("</g>").count(), balloon.strokes.len());
+        let mut position = 0;
+        for idx in 0..balloon.strokes.len() {
+            position += grouped[position..]
+                .find(&format!(r#"<g id="stroke-{idx}">"#))
+                .unwrap();
+        }
+
+        // Removing the groups leaves the ungrouped output
+        let ungrouped: String = grouped
+            .lines()
+            .filter(|line| !line.starts_with("<g ") && *line != "</g>")
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert_eq!(ungrouped, balloon.render_svg());
+    }
+
+    #[test]
+    fn test_render_svg_partial() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        let strokes = balloon.strokes.len();
+
+        // An end past the last stroke is clamped
+        assert_eq!(
+            balloon.render_svg_partial(0..strokes + 10),
+            balloon.render_svg()
+        );
+        assert_eq!(
+            balloon.render_ascii_partial(20, 0..usize::MAX),
+            balloon.render_ascii(20)
+        );
+
+        // A prefix draws a subset on the same canvas
+        let partial = balloon.render_svg_partial(0..2);
+        let full = balloon.render_svg();
+        assert!(partial.matches("<polyline").count() < full.matches("<polyline").count());
+        assert_eq!(partial.lines().nth(1), full.lines().nth(1));
+
+        // An empty range is a valid, empty canvas
+        for range in [3..3, strokes + 1..strokes + 5] {
+            let empty = balloon.render_svg_partial(range.clone());
+            roxmltree::Document::parse(&empty).unwrap();
+            assert!(!empty.contains("<polyline"));
+            assert!(!empty.contains("<circle"));
+            assert_eq!(empty.lines().nth(1), full.lines().nth(1));
+
+            let ascii = balloon.render_ascii_partial(20, range);
+            assert_eq!(ascii.lines().count(), 20);
+            assert!(ascii.chars().all(|ch| ch == ' ' || ch == '\n'));
+        }
+    }
+
+    #[test]
+    fn test_render_svg_minified() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let svg = balloon.render_svg();
+        let minified = balloon.render_svg_minified();
+        assert!(minified.len() < svg.len());
+        assert!(!minified.contains('\n'));
+        assert!(!minified.contains("<metadata>"));
+
+        let document = roxmltree::Document::parse(&minified).unwrap();
+        let count = |name: &str| {
+            document
+                .descendants()
+                .filter(|node| node.has_tag_name(name))
+                .count()
+        };
+        assert_eq!(count("polyline"), svg.matches("<polyline").count());
+        assert_eq!(count("circle"), svg.matches("<circle").count());
+    }
+
+    #[test]
+    fn test_to_svg_tree() {
+        for name in ["handwriting", "hello", "pollock", "test"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join(format!("test_data/handwritten_message/{name}.bin"));
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+            let tree = balloon.to_svg_tree();
+            assert_eq!(format!("\n{tree}"), balloon.render_svg(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_to_svg_tree_with_options() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data)
+            .unwrap()
+            .with_effect(Some(INVISIBLE_INK_EFFECT));
+
+        let everything = SvgRenderOptions {
+            pressure_opacity: true,
+            debug_frame: true,
+            linecap: LineCap::Square,
+            linejoin: LineJoin::Bevel,
+            background_color: Some(r#"red" onload="alert(1)"#.to_string()),
+            background_gradient: Some(("#fdfbfb".to_string(), "<blue>".to_string())),
+            coordinate_decimals: 3,
+            group_strokes: true,
+            apply_effects: true,
+            drop_shadow: true,
+            paper: Some(PaperStyle::Grid { spacing: 20 }),
+            forensic_overlay: true,
+            endpoint_markers: true,
+            ..Default::default()
+        };
+        let all_options = [
+            SvgRenderOptions {
+                background_color: Some("white".to_string()),
+                ..Default::default()
+            },
+            SvgRenderOptions {
+                background_gradient: Some(("#fdfbfb".to_string(), "#ebedee".to_string())),
+                ..Default::default()
+            },
+            SvgRenderOptions {
+                paper: Some(PaperStyle::Ruled { spacing: 20 }),
+                ..Default::default()
+            },
+            SvgRenderOptions {
+                linecap: LineCap::Butt,
+                linejoin: LineJoin::Miter,
+                ..Default::default()
+            },
+            SvgRenderOptions {
+                group_strokes: true,
+                pressure_opacity: true,
+                ..Default::default()
+            },
+            SvgRenderOptions {
+                filled_outline: true,
+                drop_shadow: true,
+                ..Default::default()
+            },
+            SvgRenderOptions {
+                filled_outline: true,
+                ..everything.clone()
+            },
+            everything,
+        ];
+        for options in &all_options {
+            let tree = balloon.to_svg_tree_with_options(options);
+            let svg = balloon.render_svg_with_options(options);
+            assert_eq!(format!("\n{tree}"), svg, "{options:?}");
+            roxmltree::Document::parse(&svg).unwrap();
+        }
+
+        // Nothing is drawn, so there is nothing to filter
+        let empty = HandwrittenMessage {
+            strokes: vec![],
+            ..HandwrittenMessage::from_payload(&data).unwrap()
+        };
+        let options = SvgRenderOptions {
+            drop_shadow: true,
+            ..Default::default()
+        };
+        let svg = empty.render_svg_with_options(&options);
+        assert!(!svg.contains("filter"));
+        assert_eq!(
+            format!("\n{}", empty.to_svg_tree_with_options(&options)),
+            svg
+        );
+    }
+
+    #[test]
+    fn test_to_svg_tree_attributes() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let strokes = encode_strokes(&[&[(0, 0, 1), (10, 10, 1)], &[(5, 5, 1)]]);
+        let balloon = HandwrittenMessage::from_payload(&build_payload(frame, strokes)).unwrap();
+
+        let mut tree = balloon.to_svg_tree();
+        tree.set_attribute("class", "doodle");
+        tree.set_attribute("width", "50%");
+        let children = tree.children_mut().unwrap();
+        let count = children.len();
+        children[count - 2].set_attribute("data-stroke", 0);
+        children[count - 1].set_attribute("data-label", r#"a "dot" & <more>"#);
+
+        assert_eq!(tree.attribute("width"), Some("50%"));
+        assert_eq!(tree.attribute("class"), Some("doodle"));
+        let svg = tree.to_string();
+        assert!(svg.starts_with("<svg viewBox="));
+        assert!(svg.contains(r#" width="50%" "#));
+        assert!(svg.contains(r#" class="doodle">"#));
+        assert!(svg.contains(r#"<polyline class="line" points="#));
+        assert!(svg.contains(r#" data-stroke="0" />"#));
+        assert!(svg.contains(r#"<circle "#));
+        assert!(svg.contains(r#" data-label="a &quot;dot&quot; &amp; &lt;more>" />"#));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn test_aspect_ratio() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        assert_eq!((balloon.width, balloon.height), (753, 243));
+        assert_eq!(balloon.aspect_ratio(), 753.0 / 243.0);
+
+        let flat = HandwrittenMessage {
+            height: 0,
+            ..balloon
+        };
+        assert_eq!(flat.aspect_ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let bounds = balloon.bounding_box().unwrap();
+        for point in balloon.strokes.iter().flatten() {
+            assert!((bounds.min_x..=bounds.max_x).contains(&point.x));
+            assert!((bounds.min_y..=bounds.max_y).contains(&point.y));
+        }
+        // The padding is not part of the drawing
+        assert!(bounds.max_x < balloon.width);
+        assert!(bounds.max_y < balloon.height);
+
+        let empty = HandwrittenMessage {
+            strokes: vec![],
+            ..balloon
+        };
+        assert_eq!(empty.bounding_box(), None);
+    }
+
+    #[test]
+    fn test_render_thumbnail_svg() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let thumbnail = balloon.render_thumbnail_svg(48);
+        assert!(thumbnail.starts_with(r#"<svg viewBox="0 0 48 48" width="48" height="48""#));
+        assert_eq!(
+            thumbnail.matches("<polyline").count() + thumbnail.matches("<circle").count(),
+            balloon
+                .strokes
+                .iter()
+                .map(|stroke| match stroke.len() {
+                    1 => 1,
+                    _ => group_points(stroke).len(),
+                })
+                .sum::<usize>()
+        );
+
+        let mut max = (0.0_f64, 0.0_f64);
+        for points in thumbnail.split(r#"points=""#).skip(1) {
+            let points = &points[..points.find('"').unwrap()];
+            for point in points.split(' ') {
+                let (x, y) = point.split_once(',').unwrap();
+                let (x, y): (f64, f64) = (x.parse().unwrap(), y.parse().unwrap());
+                // Every point is inside the 3 unit margin
+                assert!((3.0..=45.0).contains(&x), "{x}");
+                assert!((3.0..=45.0).contains(&y), "{y}");
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+        // The drawing is wider than it is tall, so it fills the width
+        assert_eq!(max.0, 45.0);
+        assert!(max.1 < 45.0);
+
+        let empty = HandwrittenMessage {
+            strokes: vec![],
+            ..balloon
+        };
+        assert_eq!(
+            empty.render_thumbnail_svg(48).matches("<polyline").count(),
+            0
+        );
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_png() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let png = balloon.render_png(2).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        // The IHDR chunk holds the big endian width and height
+        assert_eq!(&png[16..20], &(u32::from(balloon.width) * 2).to_be_bytes());
+        assert_eq!(&png[20..24], &(u32::from(balloon.height) * 2).to_be_bytes());
+
+        assert!(matches!(
+            balloon.render_png(0),
+            Err(HandwritingError::InvalidScale(0))
+        ));
+        // 405 * 41 is just over the limit
+        assert!(matches!(
+            balloon.render_png(41),
+            Err(HandwritingError::InvalidScale(41))
+        ));
+        assert!(matches!(
+            balloon.render_png(u32::MAX),
+            Err(HandwritingError::InvalidScale(u32::MAX))
+        ));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_to_image() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let image = balloon.to_image(2).unwrap();
+        assert_eq!(image.width(), u32::from(balloon.width) * 2);
+        assert_eq!(image.height(), u32::from(balloon.height) * 2);
+
+        // Every point is inked, and the padding around the drawing is not
+        for point in balloon.strokes.iter().flatten() {
+            let pixel = image.get_pixel(u32::from(point.x) * 2, u32::from(point.y) * 2);
+            assert_eq!(pixel.0[..3], [0, 0, 0]);
+            assert!(pixel.0[3] > 0);
+        }
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+        let inked = image.pixels().filter(|pixel| pixel.0[3] > 0).count();
+        assert!(inked > 0 && inked < image.pixels().len());
+
+        assert!(matches!(
+            balloon.to_image(0),
+            Err(HandwritingError::InvalidScale(0))
+        ));
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn test_render_webp() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let webp = balloon.render_webp(1).unwrap();
+        assert_eq!(&webp[..4], b"RIFF");
+        assert_eq!(&webp[8..12], b"WEBP");
+        // Lossless images use a `VP8L` chunk
+        assert_eq!(&webp[12..16], b"VP8L");
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn test_render_gif() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let gif = balloon.render_gif(4, 1).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(gif.as_slice()).unwrap();
+        assert_eq!(
+            (decoder.width(), decoder.height()),
+            (balloon.width, balloon.height)
+        );
+
+        let mut ink = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            ink.push(frame.buffer.chunks(4).filter(|pixel| pixel[3] > 0).count());
+        }
+        assert_eq!(ink.len(), 4);
+        // Each frame draws more of the drawing than the last
+        assert!(ink.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(ink[3] > 0);
+
+        assert!(matches!(
+            balloon.render_gif(0, 1),
+            Err(HandwritingError::RasterError(_))
+        ));
+    }
+
+    #[test]
+    fn test_render_pdf() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let pdf = balloon.render_pdf().unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+
+        let document = lopdf::Document::load_mem(&pdf).unwrap();
+        let pages = document.get_pages();
+        assert_eq!(pages.len(), 1);
+
+        let page = pages.values().next().unwrap();
+        let content = document.get_page_content(*page).unwrap();
+        let operations = lopdf::content::Content::decode(&content)
+            .unwrap()
+            .operations;
+        let strokes = operations
+            .iter()
+            .filter(|operation| operation.operator == "S")
+            .count();
+        let moves = operations
+            .iter()
+            .filter(|operation| operation.operator == "m")
+            .count();
+        assert!(strokes >= balloon.strokes.len());
+        assert_eq!(strokes, moves);
+    }
+
+    #[test]
+    fn test_render_svg_line_style() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let default = balloon.render_svg();
+        assert!(default.contains("stroke-linecap: round;\n        stroke-linejoin: round;"));
+
+        let styled = balloon.render_svg_with_options(&SvgRenderOptions {
+            linecap: LineCap::Square,
+            linejoin: LineJoin::Miter,
+            ..Default::default()
+        });
+        assert!(styled.contains("stroke-linecap: square;\n        stroke-linejoin: miter;"));
+        assert!(!styled.contains("round"));
+    }
+
+    #[test]
+    fn test_render_svg_background_color() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        assert!(!balloon.render_svg().contains("<rect"));
+
+        let dark = balloon.render_svg_with_options(&SvgRenderOptions {
+            background_color: Some("#1c1c1e".to_string()),
+            ..Default::default()
+        });
+        let background = dark
+            .find(r##"<rect class="background" x="0" y="0""##)
+            .unwrap();
+        assert!(dark.contains(r##"fill="#1c1c1e""##));
+        // The background is drawn underneath every stroke
+        assert!(background < dark.find("<polyline").unwrap());
+
+        // The color is written inside an attribute, so it must not be able to close it
+        let injected = balloon.render_svg_with_options(&SvgRenderOptions {
+            background_color: Some(r#"red" onload="alert(1)"#.to_string()),
+            ..Default::default()
+        });
+        assert!(injected.contains(r#"fill="red&quot; onload=&quot;alert(1)""#));
+        assert!(!injected.contains("onload=\""));
+        roxmltree::Document::parse(&injected).unwrap();
+    }
+
+    #[test]
+    fn test_render_svg_background_gradient() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        assert!(!balloon.render_svg().contains("<linearGradient"));
+
+        let svg = balloon.render_svg_with_options(&SvgRenderOptions {
+            background_gradient: Some(("#fdfbfb".to_string(), "#ebedee".to_string())),
+            ..Default::default()
+        });
+        let id = format!("background-gradient-{}", balloon.id);
+        let gradient = svg.find(&format!(r#"<linearGradient id="{id}""#)).unwrap();
+        assert_eq!(svg.matches("<stop ").count(), 2);
+        assert!(svg.contains(r##"<stop offset="0" stop-color="#fdfbfb" />"##));
+        assert!(svg.contains(r##"<stop offset="1" stop-color="#ebedee" />"##));
+        let background = svg.find(&format!(r#"fill="url(#{id})""#)).unwrap();
+        // The gradient is defined before it is used, and drawn underneath every stroke
+        assert!(gradient < background);
+        assert!(background < svg.find("<polyline").unwrap());
+
+        // Drawings with different IDs can share a page without their gradients colliding
+        let mut other = HandwrittenMessage::from_payload(&data).unwrap();
+        other.id = "other drawing".to_string();
+        let injected = other.render_svg_with_options(&SvgRenderOptions {
+            background_gradient: Some((r#"red"/><script>"#.to_string(), "blue".to_string())),
+            ..Default::default()
+        });
+        assert!(injected.contains(r#"<linearGradient id="background-gradient-other_drawing""#));
+        assert!(injected.contains(r#"fill="url(#background-gradient-other_drawing)""#));
+        assert!(injected.contains(r#"stop-color="red&quot;/>&lt;script>""#));
+        assert!(!injected.contains("<script>"));
+        roxmltree::Document::parse(&injected).unwrap();
+    }
+
+    #[test]
+    fn test_render_svg_paper() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        assert_eq!((balloon.width, balloon.height), (753, 243));
+
+        assert!(!balloon.render_svg().contains("<line "));
+
+        // 12 rows at `y = 20..=240` and 37 columns at `x = 20..=740`
+        let grid = balloon.render_svg_with_options(&SvgRenderOptions {
+            paper: Some(PaperStyle::Grid { spacing: 20 }),
+            ..Default::default()
+        });
+        assert_eq!(grid.matches("<line ").count(), 49);
+        assert!(grid.contains(r#"<line class="paper" x1="0" y1="240" x2="753" y2="240""#));
+        assert!(grid.contains(r#"<line class="paper" x1="740" y1="0" x2="740" y2="243""#));
+        // The paper is drawn underneath every stroke
+        assert!(grid.rfind("<line ").unwrap() < grid.find("<polyline").unwrap());
+
+        let ruled = balloon.render_svg_with_options(&SvgRenderOptions {
+            paper: Some(PaperStyle::Ruled { spacing: 20 }),
+            ..Default::default()
+        });
+        assert_eq!(ruled.matches("<line ").count(), 12);
+
+        let empty = balloon.render_svg_with_options(&SvgRenderOptions {
+            paper: Some(PaperStyle::Grid { spacing: 0 }),
+            ..Default::default()
+        });
+        assert!(!empty.contains("<line "));
+    }
+
+    #[test]
+    fn test_render_svg_forensic_overlay() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        assert_eq!((balloon.width, balloon.height), (753, 243));
+
+        assert!(!balloon.render_svg().contains("forensic-overlay"));
+
+        let svg = balloon.render_svg_with_options(&SvgRenderOptions {
+            forensic_overlay: true,
+            ..Default::default()
+        });
+        // 15 ticks at `x = 50..=750` and 4 at `y = 50..=200`
+        assert_eq!(svg.matches(r#"<line class="tick" "#).count(), 19);
+        assert!(svg.contains(r#"<line class="tick" x1="750" y1="0" x2="750" y2="4" />"#));
+        assert!(svg.contains(r#"<line class="tick" x1="0" y1="200" x2="4" y2="200" />"#));
+
+        let scale_bar = svg.find(r#"<g class="scale-bar">"#).unwrap();
+        assert!(svg[scale_bar..].starts_with(
+            "<g class=\"scale-bar\">\n<line x1=\"4\" y1=\"239\" x2=\"104\" y2=\"239\" />\n<text x=\"4\" y=\"237\" stroke=\"none\">100 units</text>\n</g>"
+        ));
+        // The overlay is drawn over the strokes
+        assert!(svg.find("<polyline").unwrap() < svg.find("forensic-overlay").unwrap());
+    }
+
+    #[test]
+    fn test_render_geojson() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&balloon.render_geojson()).unwrap();
+        assert_eq!(json["type"], "FeatureCollection");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), balloon.strokes.len());
+
+        for (idx, (feature, stroke)) in features.iter().zip(&balloon.strokes).enumerate() {
+            assert_eq!(feature["type"], "Feature");
+            assert_eq!(feature["geometry"]["type"], "LineString");
+            assert_eq!(feature["properties"]["stroke"], idx);
+            let coordinates = feature["geometry"]["coordinates"].as_array().unwrap();
+            assert_eq!(coordinates.len(), stroke.len());
+            assert_eq!(coordinates[0][0], stroke[0].x);
+            assert_eq!(coordinates[0][1], stroke[0].y);
+            assert_eq!(
+                feature["properties"]["width"].as_array().unwrap().len(),
+                stroke.len()
+            );
+        }
+
+        let dot = HandwrittenMessage::from_normalized(vec![vec![(0.5, 0.5, 3.0)]], 10, 10);
+        assert_eq!(
+            dot.render_geojson(),
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","geometry":{"type":"LineString","coordinates":[[5,5],[5,5]]},"properties":{"stroke":0,"width":[3]}}]}"#
+        );
+    }
+
+    #[test]
+    fn test_render_svg_endpoint_markers() {
+        let payload = build_payload(
+            vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80],
+            encode_strokes(&[&[(0, 0, 1), (10, 10, 1)], &[], &[(5, 0, 2)]]),
+        );
+        let balloon = HandwrittenMessage::from_payload(&payload).unwrap();
+        assert_eq!(balloon.strokes.len(), 3);
+
+        assert!(!balloon.render_svg().contains("stroke-start"));
+
+        let svg = balloon.render_svg_with_options(&SvgRenderOptions {
+            endpoint_markers: true,
+            ..Default::default()
+        });
+        // Two markers for each of the two strokes with points
+        assert_eq!(svg.matches(r#"<circle class="stroke-start""#).count(), 2);
+        assert_eq!(svg.matches(r#"<circle class="stroke-end""#).count(), 2);
+        let first = &balloon.strokes[0];
+        assert!(svg.contains(&format!(
+            r#"<circle class="stroke-start" cx="{}" cy="{}" r="1.5" fill="green" />"#,
+            first[0].x, first[0].y
+        )));
+        assert!(svg.contains(&format!(
+            r#"<circle class="stroke-end" cx="{}" cy="{}" r="1.5" fill="red" />"#,
+            first[1].x, first[1].y
+        )));
+        // Markers are drawn over the strokes
+        assert!(svg.rfind("<polyline").unwrap() < svg.find("stroke-start").unwrap());
+    }
+
+    #[test]
+    fn test_render_svg_filled_outline() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let svg = balloon.render_svg_with_options(&SvgRenderOptions {
+            filled_outline: true,
+            ..Default::default()
+        });
+        let shapes =
+            svg.matches(r#"<path class="outline""#).count() + svg.matches("<circle").count();
+        assert_eq!(shapes, balloon.strokes.len());
+        assert_eq!(
+            svg.matches(r#"fill="black""#).count(),
+            balloon.strokes.len()
+        );
+        assert!(!svg.contains("<polyline"));
+        assert!(!svg.contains(" stroke="));
+        assert!(!svg.contains("stroke-width="));
+    }
+
+    #[test]
+    fn test_render_svg_outline_offsets_by_width() {
+        let balloon = HandwrittenMessage {
+            id: "line".to_string(),
+            created_at: 0,
+            origin: (0, 0),
+            height: 20,
+            width: 20,
+            padding: 5,
+            frame_width: 15,
+            frame_height: 15,
+            clamped_widths: 0,
+            effect: None,
+            strokes: vec![vec![
+                Point {
+                    x: 2,
+                    y: 10,
+                    width: 4,
+                },
+                Point {
+                    x: 12,
+                    y: 10,
+                    width: 2,
+                },
+            ]],
+        };
+
+        let svg = balloon.render_svg_with_options(&SvgRenderOptions {
+            filled_outline: true,
+            ..Default::default()
+        });
+        assert!(
+            svg.contains(r#"<path class="outline" d="M2,12 L12,11 L12,9 L2,8 Z" fill="black" />"#)
+        );
+    }
+
+    #[test]
+    fn test_render_svg_coordinate_decimals() {
+        let mut balloon = HandwrittenMessage {
+            id: "diagonal".to_string(),
+            created_at: 0,
+            origin: (0, 0),
+            height: 20,
+            width: 20,
+            padding: 5,
+            frame_width: 15,
+            frame_height: 15,
+            clamped_widths: 0,
+            effect: None,
+            strokes: vec![vec![
+                Point {
+                    x: 0,
+                    y: 0,
+                    width: 3,
+                },
+                Point {
+                    x: 3,
+                    y: 4,
+                    width: 3,
+                },
+            ]],
+        };
+        let render = |balloon: &HandwrittenMessage, decimals| {
+            balloon.render_svg_with_options(&SvgRenderOptions {
+                filled_outline: true,
+                coordinate_decimals: decimals,
+                ..Default::default()
+            })
+        };
+
+        assert!(render(&balloon, 1).contains(r#"d="M-1.2,0.9 L1.8,4.9 L4.2,3.1 L1.2,-0.9 Z""#));
+        assert!(render(&balloon, 0).contains(r#"d="M-1,1 L2,5 L4,3 L1,-1 Z""#));
+
+        // Dots are drawn with a fractional radius
+        balloon.strokes[0].truncate(1);
+        assert!(render(&balloon, 1).contains(r#"r="1.5""#));
+        assert!(render(&balloon, 0).contains(r#"r="2""#));
+    }
+
+    #[test]
+    fn test_render_svg_debug_frame() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let default = balloon.render_svg();
+        let debug = balloon.render_svg_with_options(&SvgRenderOptions {
+            debug_frame: true,
+            ..Default::default()
+        });
+
+        assert!(!default.contains("debug-"));
+        assert!(
+            debug.contains(r#"<rect class="debug-frame" x="0" y="0" width="748" height="238""#)
+        );
+        assert!(
+            debug.contains(r#"<rect class="debug-padding" x="0" y="0" width="753" height="243""#)
+        );
+        assert_eq!(debug.matches("debug-start").count(), balloon.strokes.len());
+        assert!(debug.contains(&format!(
+            r#"<circle class="debug-start" cx="{}" cy="{}""#,
+            balloon.strokes[0][0].x, balloon.strokes[0][0].y
+        )));
+    }
+
+    #[test]
+    fn test_resample_handwritten() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        assert!(balloon.strokes.iter().any(|stroke| stroke.len() > 700));
+
+        let resampled = balloon.resample(50);
+        assert_eq!(resampled.strokes.len(), balloon.strokes.len());
+        for (original, stroke) in balloon.strokes.iter().zip(&resampled.strokes) {
+            assert!(stroke.len() <= 50);
+            assert_eq!(stroke.len(), original.len().min(50));
+            assert_eq!(stroke.first(), original.first());
+            assert_eq!(stroke.last(), original.last());
+        }
+    }
+
+    #[test]
+    fn test_xz_decompress_matches_lzma_rs() {
+        // With the `liblzma-backend` feature, this compares liblzma against `lzma-rs`
+        for name in ["handwriting", "hello", "pollock", "test"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join(format!("test_data/handwritten_message/{name}.bin"));
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            let msg = parse_message(&data).unwrap();
+
+            let mut expected = vec![];
+            lzma_rs::xz_decompress(
+                &mut std::io::Cursor::new(&msg.Handwriting.Strokes),
+                &mut expected,
+            )
+            .unwrap();
+            assert_eq!(
+                xz_decompress(&msg.Handwriting.Strokes).unwrap(),
+                expected,
+                "{name}"
+            );
+        }
+
+        assert!(matches!(
+            xz_decompress(b"not xz"),
+            Err(HandwritingError::XZError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_from_base64() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        let text = base64::encode(&data);
+        assert_eq!(HandwrittenMessage::from_base64(&text).unwrap(), balloon);
+
+        // Wrapped lines, as printed by most tools
+        let wrapped: Vec<&str> = text
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        assert_eq!(
+            HandwrittenMessage::from_base64(&wrapped.join("\n")).unwrap(),
+            balloon
+        );
+
+        assert!(matches!(
+            HandwrittenMessage::from_base64("not base64!"),
+            Err(HandwritingError::InvalidBase64)
+        ));
+        assert!(matches!(
+            HandwrittenMessage::from_base64(&base64::encode(b"hello")),
+            Err(HandwritingError::NotHandwritingPayload)
+        ));
+    }
+
+    #[test]
+    fn test_render_svg_fragment() {
+        let payload = build_payload(
+            vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80],
+            encode_strokes(&[&[(0, 0, 2), (5, 5, 2), (10, 10, 4)], &[(5, 0, 3)]]),
+        );
+        let balloon = HandwrittenMessage::from_payload(&payload).unwrap();
+
+        let fragment = balloon.render_svg_fragment("m1-");
+        assert!(fragment.starts_with(r#"<g id="m1-handwriting">"#));
+        assert!(!fragment.contains("<svg"));
+        assert!(!fragment.contains("<style"));
+        assert!(!fragment.contains("class="));
+        assert!(fragment.contains(r#"<g id="m1-stroke-0">"#));
+        assert!(fragment.contains(r#"<g id="m1-stroke-1">"#));
+        assert_eq!(fragment.matches("<polyline ").count(), 2);
+        assert_eq!(fragment.matches("<circle ").count(), 1);
+        assert_eq!(fragment.matches(r#"fill="none" stroke="black""#).count(), 2);
+        assert!(fragment.trim_end().ends_with("</g>"));
+    }
+
+    #[test]
+    fn test_perceptual_hash() {
+        let read = |name: &str| {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join("test_data/handwritten_message")
+                .join(name);
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            HandwrittenMessage::from_payload(&data).unwrap()
+        };
+        let balloon = read("handwriting.bin");
+        let hash = balloon.perceptual_hash();
+        assert_ne!(hash, 0);
+
+        let resized = balloon.scale_to(balloon.width * 9 / 10, balloon.height * 9 / 10);
+        assert!((hash ^ resized.perceptual_hash()).count_ones() <= 4);
+
+        let other = read("pollock.bin");
+        assert!((hash ^ other.perceptual_hash()).count_ones() > 10);
+
+        let blank = HandwrittenMessage {
+            strokes: vec![],
+            ..balloon
+        };
+        assert_eq!(blank.perceptual_hash(), 0);
+    }
+
+    #[test]
+    fn test_from_normalized() {
+        let balloon = HandwrittenMessage::from_normalized(
+            vec![vec![(0.0, 0.0, 2.0), (1.0, 1.0, 2.0)]],
+            100,
+            100,
+        );
+        assert_eq!((balloon.width, balloon.height), (105, 105));
+        assert_eq!((balloon.frame_width, balloon.frame_height), (100, 100));
+        assert_eq!(
+            balloon.strokes,
+            vec![vec![
+                Point {
+                    x: 0,
+                    y: 0,
+                    width: 2
+                },
+                Point {
+                    x: 100,
+                    y: 100,
+                    width: 2
+                },
+            ]]
+        );
+
+        let svg = balloon.render_svg();
+        assert!(svg.contains(r#"viewBox="0 0 105 105""#));
+        assert!(svg.contains(
+            r#"<polyline class="line" points="0,0 100,100 100,100" stroke-width="2" />"#
+        ));
+
+        // Out of range coordinates land on the edge of the frame
+        let clamped = HandwrittenMessage::from_normalized(vec![vec![(-0.5, 1.5, 1.0)]], 40, 20);
+        assert_eq!((clamped.strokes[0][0].x, clamped.strokes[0][0].y), (0, 20));
+    }
+
+    #[test]
+    fn test_parse_handwritten_from_reader() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(&protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        assert_eq!(
+            HandwrittenMessage::from_reader(File::open(&protobuf_path).unwrap()).unwrap(),
+            balloon
+        );
+        assert_eq!(
+            HandwrittenMessage::from_reader(std::io::Cursor::new(&data)).unwrap(),
+            balloon
+        );
+        assert!(matches!(
+            HandwrittenMessage::from_reader(std::io::Cursor::new(b"hello")),
+            Err(HandwritingError::NotHandwritingPayload)
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_traced() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/pollock.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let msg = parse_message(&data).unwrap();
+        assert_eq!(
+            msg.Handwriting.Compression.enum_value_or_default(),
+            Compression::XZ
+        );
+
+        let (balloon, stats) = HandwrittenMessage::from_payload_traced(&data).unwrap();
+        assert_eq!(balloon, HandwrittenMessage::from_payload(&data).unwrap());
+        assert_eq!(stats.payload_bytes, data.len());
+        assert!(stats.decompressed_bytes >= stats.payload_bytes);
+        assert_eq!(stats.stroke_count, balloon.strokes.len());
+        assert_eq!(
+            stats.total_points,
+            balloon.strokes.iter().map(Vec::len).sum::<usize>()
+        );
+        assert!(stats.total_points > 0);
+    }
+
+    #[test]
+    fn test_crop_handwritten() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/hello.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let mut balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        // Move the drawing away from the corner of a larger canvas
+        balloon.width += 100;
+        balloon.height += 50;
+        balloon.strokes.iter_mut().flatten().for_each(|point| {
+            point.x += 60;
+            point.y += 30;
+        });
+        let bounds = balloon.bounding_box().unwrap();
+
+        let cropped = balloon.cropped();
+        assert_eq!(cropped.width, bounds.max_x - bounds.min_x + CROP_MARGIN * 2);
+        assert_eq!(
+            cropped.height,
+            bounds.max_y - bounds.min_y + CROP_MARGIN * 2
+        );
+        assert_eq!(cropped.padding, CROP_MARGIN);
+
+        let cropped_bounds = cropped.bounding_box().unwrap();
+        assert_eq!(cropped_bounds.min_x, CROP_MARGIN);
+        assert_eq!(cropped_bounds.min_y, CROP_MARGIN);
+        assert_eq!(cropped_bounds.max_x, cropped.width - CROP_MARGIN);
+        assert_eq!(cropped_bounds.max_y, cropped.height - CROP_MARGIN);
+        for (original, point) in balloon
+            .strokes
+            .iter()
+            .flatten()
+            .zip(cropped.strokes.iter().flatten())
+        {
+            assert_eq!(point.x, original.x - bounds.min_x + CROP_MARGIN);
+            assert_eq!(point.y, original.y - bounds.min_y + CROP_MARGIN);
+            assert_eq!(point.width, original.width);
+        }
+
+        let empty = HandwrittenMessage {
+            strokes: vec![],
+            ..balloon
+        }
+        .cropped();
+        assert_eq!(
+            (empty.width, empty.height),
+            (CROP_MARGIN * 2, CROP_MARGIN * 2)
+        );
+    }
+
+    #[test]
+    fn test_quantize_widths() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let mut balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        let widths = |balloon: &HandwrittenMessage| {
+            let mut widths: Vec<u16> = balloon.strokes.iter().flatten().map(|p| p.width).collect();
+            widths.sort_unstable();
+            widths.dedup();
+            widths
+        };
+        let before = widths(&balloon);
+        let segments = balloon.render_svg().matches("<polyline").count();
+        assert!(before.len() > 3);
+
+        balloon.quantize_widths(3);
+        let after = widths(&balloon);
+        assert!(after.len() <= 3);
+        // The thinnest and thickest widths are kept
+        assert_eq!(after.first(), before.first());
+        assert_eq!(after.last(), before.last());
+        assert!(balloon.render_svg().matches("<polyline").count() < segments);
+
+        balloon.quantize_widths(1);
+        assert_eq!(widths(&balloon).len(), 1);
+    }
+
+    #[test]
+    fn test_split_by_gaps() {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/handwritten_message/handwriting.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+
+        // The fixture is two words, between 30 and 40 units apart
+        let words = balloon.split_by_gaps(20);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].strokes.len(), 8);
+        assert_eq!(words[1].strokes.len(), 12);
+        for word in &words {
+            assert!(word.width < balloon.width);
+            let bounds = word.bounding_box().unwrap();
+            assert_eq!((bounds.min_x, bounds.min_y), (CROP_MARGIN, CROP_MARGIN));
+        }
 
-        assert_eq!(balloon, expected);
+        assert_eq!(balloon.split_by_gaps(100).len(), 1);
+        assert_eq!(balloon.split_by_gaps(100)[0], balloon.cropped());
     }
 
     #[test]
-    fn test_parse_handwritten_as_ascii() {
+    fn test_frame_dimensions() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
@@ -11041,19 +14808,28 @@ mod tests {
         proto_data.read_to_end(&mut data).unwrap();
         let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
-            .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/handwriting.ascii");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+        assert_eq!((balloon.frame_width, balloon.frame_height), (748, 238));
+        assert_eq!(balloon.width, balloon.frame_width + FRAME_PADDING);
+        assert_eq!(balloon.height, balloon.frame_height + FRAME_PADDING);
 
-        assert_eq!(balloon.render_ascii(40), expected);
+        let padded = HandwrittenMessage::from_payload_with_options(
+            &data,
+            &ParseOptions {
+                padding: Padding::Fixed(20),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!((padded.frame_width, padded.frame_height), (748, 238));
+
+        // Transforms change the canvas, but not the frame it came from
+        let turned = balloon.rotate(1).cropped();
+        assert_ne!(turned.width, balloon.width);
+        assert_eq!((turned.frame_width, turned.frame_height), (748, 238));
     }
 
     #[test]
-    fn test_parse_handwritten_as_ascii_half() {
+    fn test_mirror_handwritten() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
@@ -11063,151 +14839,766 @@ mod tests {
         proto_data.read_to_end(&mut data).unwrap();
         let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
-            .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/handwriting_half.ascii");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+        let flipped = balloon.mirror_horizontal();
+        assert_eq!(flipped.width, balloon.width);
+        assert_eq!(flipped.height, balloon.height);
+        assert_ne!(flipped.strokes, balloon.strokes);
+        let inner_width = balloon.width - balloon.padding;
+        assert_eq!(
+            flipped.strokes[0][0].x,
+            inner_width - balloon.strokes[0][0].x
+        );
+        assert_eq!(flipped.strokes[0][0].y, balloon.strokes[0][0].y);
+        assert_eq!(flipped.mirror_horizontal(), balloon);
 
-        assert_eq!(balloon.render_ascii(20), expected);
+        let flipped = balloon.mirror_vertical();
+        let inner_height = balloon.height - balloon.padding;
+        assert_eq!(flipped.strokes[0][0].x, balloon.strokes[0][0].x);
+        assert_eq!(
+            flipped.strokes[0][0].y,
+            inner_height - balloon.strokes[0][0].y
+        );
+        assert_eq!(flipped.mirror_vertical(), balloon);
+
+        // Flipping both ways is half a turn
+        assert_eq!(
+            balloon.mirror_horizontal().mirror_vertical(),
+            balloon.rotate(2)
+        );
     }
 
     #[test]
-    fn test_parse_handwritten_as_ascii_old() {
+    fn test_rotate_handwritten() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/test.bin");
+            .join("test_data/handwritten_message/handwriting.bin");
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
         let balloon = HandwrittenMessage::from_payload(&data).unwrap();
+        assert_ne!(balloon.width, balloon.height);
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
-            .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/test.ascii");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+        let turned = balloon.rotate(1);
+        assert_eq!(turned.width, balloon.height);
+        assert_eq!(turned.height, balloon.width);
+        assert_eq!(turned.strokes.len(), balloon.strokes.len());
 
-        assert_eq!(balloon.render_ascii(20), expected);
+        let bounds = balloon.bounding_box().unwrap();
+        let turned_bounds = turned.bounding_box().unwrap();
+        assert_eq!(
+            turned_bounds.max_x - turned_bounds.min_x,
+            bounds.max_y - bounds.min_y
+        );
+        assert_eq!(
+            turned_bounds.max_y - turned_bounds.min_y,
+            bounds.max_x - bounds.min_x
+        );
+
+        let upside_down = balloon.rotate(2);
+        assert_eq!(upside_down.width, balloon.width);
+        assert_eq!(upside_down.height, balloon.height);
+
+        let full_turn = turned.rotate(1).rotate(1).rotate(1);
+        assert_eq!(full_turn.bounding_box(), Some(bounds));
+        assert!(full_turn.same_drawing(&balloon));
+        assert!(balloon.rotate(4).same_drawing(&balloon));
     }
 
     #[test]
-    fn test_parse_handwritten_as_ascii_builtin() {
+    fn test_scale_handwritten() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/hello.bin");
+            .join("test_data/handwritten_message/handwriting.bin");
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
         let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
-            .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/hello.ascii");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+        let large = balloon.scale_to(balloon.width * 3, balloon.height * 2);
+        assert_eq!((large.width, large.height), (2259, 486));
+        let (large_x, large_y, _) = get_max_dimension(&large.strokes);
+        assert_eq!((large_x, large_y), (2259 - 5, 486 - 5));
 
-        assert_eq!(balloon.render_ascii(20), expected);
+        // Scaling back down lands within a unit of every original point
+        let restored = large.scale_to(balloon.width, balloon.height);
+        assert_eq!(
+            (restored.width, restored.height),
+            (balloon.width, balloon.height)
+        );
+        for (original, point) in balloon
+            .strokes
+            .iter()
+            .flatten()
+            .zip(restored.strokes.iter().flatten())
+        {
+            assert!(original.x.abs_diff(point.x) <= 1);
+            assert!(original.y.abs_diff(point.y) <= 1);
+            assert_eq!(original.width, point.width);
+        }
     }
 
     #[test]
-    fn test_parse_handwritten_as_ascii_pollock() {
+    fn test_overlay_handwritten() {
+        let mut messages = vec![];
+        for name in ["handwriting.bin", "hello.bin"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join("test_data/handwritten_message")
+                .join(name);
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            messages.push(HandwrittenMessage::from_payload(&data).unwrap());
+        }
+        let points =
+            |message: &HandwrittenMessage| -> usize { message.strokes.iter().map(Vec::len).sum() };
+
+        let overlaid = messages[0].overlay(&messages[1]);
+        assert_eq!(overlaid.id, messages[0].id);
+        assert_eq!(
+            points(&overlaid),
+            points(&messages[0]) + points(&messages[1])
+        );
+        assert_eq!(
+            overlaid.strokes.len(),
+            messages[0].strokes.len() + messages[1].strokes.len()
+        );
+
+        // The canvas holds both inputs, and every point is on it
+        for message in &messages {
+            assert!(overlaid.width >= message.width);
+            assert!(overlaid.height >= message.height);
+        }
+        assert!(overlaid.strokes.iter().flatten().all(|point| {
+            point.x <= overlaid.width
+                && point.y <= overlaid.height
+                && (1..=10).contains(&point.width)
+        }));
+
+        assert!(overlaid.render_svg().contains("<polyline"));
+        assert!(overlaid.render_ascii(20).contains('*'));
+    }
+
+    #[test]
+    fn test_parse_handwritten_created_at_datetime() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/pollock.bin");
+            .join("test_data/handwritten_message/handwriting.bin");
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
         let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
+        let expected = NaiveDate::from_ymd_opt(2019, 4, 17)
             .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/pollock.ascii");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+            .and_hms_milli_opt(23, 2, 41, 941)
+            .unwrap();
+        assert_eq!(balloon.created_at, 577234961941);
+        assert_eq!(balloon.created_at_datetime(), Some(expected));
+    }
 
-        assert_eq!(balloon.render_ascii(20), expected);
+    #[test]
+    fn test_created_at_datetime_precision() {
+        let mut balloon = HandwrittenMessage {
+            id: String::new(),
+            created_at: 577234961,
+            origin: (0, 0),
+            height: 0,
+            width: 0,
+            padding: 0,
+            frame_width: 0,
+            frame_height: 0,
+            clamped_widths: 0,
+            effect: None,
+            strokes: vec![],
+        };
+        let expected = NaiveDate::from_ymd_opt(2019, 4, 17)
+            .unwrap()
+            .and_hms_opt(23, 2, 41)
+            .unwrap();
+        assert_eq!(balloon.created_at_datetime(), Some(expected));
+
+        balloon.created_at = 577234961000000000;
+        assert_eq!(balloon.created_at_datetime(), Some(expected));
     }
 
     #[test]
-    fn test_parse_handwritten_as_svg() {
+    fn test_parse_handwritten_invalid_frame_size() {
+        let payload = build_payload(vec![0x00, 0x80, 0x00, 0x80], vec![]);
+        let err = HandwrittenMessage::from_payload(&payload).unwrap_err();
+
+        assert!(matches!(err, HandwritingError::InvalidFrameSize(8, 4)));
+        assert_eq!(
+            err.to_string(),
+            "handwriting: expected frame size of 8 bytes, got 4"
+        );
+    }
+
+    #[test]
+    fn test_parse_handwritten_missing_handwriting() {
+        let mut msg = BaseMessage::new();
+        msg.ID = "test".to_string();
+        let payload = msg.write_to_bytes().unwrap();
+
+        let err = HandwrittenMessage::from_payload(&payload).unwrap_err();
+        assert!(matches!(err, HandwritingError::MissingHandwritingData));
+        assert!(matches!(
+            HandwrittenMessage::decompressed_strokes(&payload),
+            Err(HandwritingError::MissingHandwritingData)
+        ));
+
+        // An empty `Handwriting` message is treated the same as a missing one
+        msg.Handwriting.mut_or_insert_default();
+        assert!(matches!(
+            HandwrittenMessage::from_payload(&msg.write_to_bytes().unwrap()),
+            Err(HandwritingError::MissingHandwritingData)
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_not_handwriting() {
+        // Bytes from a JPEG header
+        let err =
+            HandwrittenMessage::from_payload(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46])
+                .unwrap_err();
+        assert!(matches!(err, HandwritingError::NotHandwritingPayload));
+        assert_eq!(
+            err.to_string(),
+            "handwriting: payload is not a handwriting message"
+        );
+
+        assert!(matches!(
+            HandwrittenMessage::from_payload(&[]),
+            Err(HandwritingError::NotHandwritingPayload)
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_truncated() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/handwriting.bin");
+            .join("test_data/handwritten_message/hello.bin");
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
-        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
+        // A real payload that was cut off is corrupt handwriting, not some other kind of data
+        assert!(matches!(
+            HandwrittenMessage::from_payload(&data[..data.len() / 2]),
+            Err(HandwritingError::ProtobufError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_clamps_widths() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let strokes = encode_strokes(&[&[(0, 0, 0), (5, 5, 16), (10, 10, 5000)]]);
+        let balloon = HandwrittenMessage::from_payload(&build_payload(frame, strokes)).unwrap();
+
+        assert_eq!(balloon.clamped_widths, 2);
+        // `0` is raised to `1` and `5000` is lowered to `MAX_RAW_WIDTH`, so the middle width keeps its weight
+        let widths: Vec<u16> = balloon.strokes[0].iter().map(|point| point.width).collect();
+        assert_eq!(widths, vec![1, 16 * 9 / (MAX_RAW_WIDTH - 1) + 1, 10]);
+
+        let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/handwriting.svg");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+            .join("test_data/handwritten_message/pollock.bin");
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        assert_eq!(
+            HandwrittenMessage::from_payload(&data)
+                .unwrap()
+                .clamped_widths,
+            0
+        );
+    }
 
-        assert_eq!(balloon.render_svg(), expected);
+    #[test]
+    fn test_parse_handwritten_pen_up_sentinel() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let strokes = encode_strokes(&[
+            &[
+                (0, 0, 1),
+                (10, 0, 1),
+                (0xFFFF, 0xFFFF, 0),
+                (0, 10, 1),
+                (10, 10, 1),
+                (0xFFFF, 0xFFFF, 0),
+            ],
+            &[(0xFFFF, 0xFFFF, 0), (5, 5, 1)],
+        ]);
+        let payload = build_payload(frame, strokes);
+
+        // By default, sentinels are parsed like any other point
+        let balloon = HandwrittenMessage::from_payload(&payload).unwrap();
+        assert_eq!(
+            balloon
+                .strokes
+                .iter()
+                .map(|stroke| stroke.len())
+                .collect::<Vec<_>>(),
+            vec![6, 2]
+        );
+
+        let split = ParseOptions {
+            split_pen_lifts: true,
+            ..Default::default()
+        };
+        let balloon = HandwrittenMessage::from_payload_with_options(&payload, &split).unwrap();
+
+        // Each lift ends a stroke, and lifts at the start or end of a stroke are dropped
+        let coordinates: Vec<Vec<(u16, u16)>> = balloon
+            .strokes
+            .iter()
+            .map(|stroke| stroke.iter().map(|point| (point.x, point.y)).collect())
+            .collect();
+        assert_eq!(
+            coordinates,
+            vec![vec![(0, 0), (10, 0)], vec![(0, 10), (10, 10)], vec![(5, 5)]]
+        );
     }
 
     #[test]
-    fn test_parse_handwritten_as_svg_old() {
+    fn test_render_svg_zero_width() {
+        let raw = vec![vec![
+            Point {
+                x: 0,
+                y: 0,
+                width: 0,
+            },
+            Point {
+                x: 10,
+                y: 10,
+                width: 19,
+            },
+        ]];
+        let (max_x, max_y, max_width) = get_max_dimension(&raw);
+        assert_eq!(max_width, 18);
+
+        let balloon = HandwrittenMessage {
+            id: "zero".to_string(),
+            created_at: 0,
+            origin: (0, 0),
+            height: 15,
+            width: 15,
+            padding: 5,
+            frame_width: 10,
+            frame_height: 10,
+            clamped_widths: 0,
+            effect: None,
+            strokes: fit_strokes(&raw, 10, 10, max_x, max_y, max_width),
+        };
+        let svg = balloon.render_svg();
+        let widths: Vec<u16> = svg
+            .split(r#"stroke-width=""#)
+            .skip(1)
+            .map(|rest| rest[..rest.find('"').unwrap()].parse().unwrap())
+            .collect();
+        // The zero width point draws as thin as possible and the wide point as wide as possible
+        assert_eq!(widths, vec![1, 10]);
+    }
+
+    #[test]
+    fn test_render_empty_strokes() {
+        // Found by the `from_payload` fuzz target: renderers that group points indexed into empty strokes
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let strokes = encode_strokes(&[&[], &[(0, 0, 1), (10, 10, 2)], &[]]);
+        let balloon = HandwrittenMessage::from_payload(&build_payload(frame, strokes)).unwrap();
+        assert_eq!(balloon.strokes.len(), 3);
+
+        assert_eq!(balloon.render_svg().matches("<polyline").count(), 2);
+        assert_eq!(
+            balloon.render_svg_animated().matches("<polyline").count(),
+            2
+        );
+        assert!(balloon.render_svg_minified().contains("<polyline"));
+        assert!(balloon.render_thumbnail_svg(8).contains("<polyline"));
+        assert!(balloon.render_pdf().is_ok());
+        assert!(balloon.render_ascii(10).contains('*'));
+        assert_eq!(balloon.render_ascii(0), "");
+    }
+
+    #[test]
+    fn test_parse_handwritten_unknown_compression() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let mark_unknown = |payload: Vec<u8>| {
+            let mut msg = BaseMessage::parse_from_bytes(&payload).unwrap();
+            msg.Handwriting.mut_or_insert_default().Compression =
+                EnumOrUnknown::new(Compression::Unknown);
+            msg.write_to_bytes().unwrap()
+        };
+
+        // Strokes that were never compressed are parsed as they are
+        let payload = build_payload(
+            frame.clone(),
+            encode_strokes(&[&[(0, 0, 1), (5, 5, 2)], &[(10, 0, 1)]]),
+        );
+        assert_eq!(
+            HandwrittenMessage::from_payload(&mark_unknown(payload.clone())).unwrap(),
+            HandwrittenMessage::from_payload(&payload).unwrap()
+        );
+
+        // Anything else still fails the same way as before
+        for strokes in [vec![0x05], vec![0x02, 0x00, 0x01], vec![]] {
+            assert!(matches!(
+                HandwrittenMessage::from_payload(&mark_unknown(build_payload(
+                    frame.clone(),
+                    strokes
+                ))),
+                Err(HandwritingError::CompressionUnknown)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_handwritten_max_strokes() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let limited = ParseOptions {
+            max_strokes: 2,
+            split_pen_lifts: true,
+            ..Default::default()
+        };
+
+        let strokes = encode_strokes(&[&[(0, 0, 1)], &[(1, 1, 1)], &[(2, 2, 1)], &[(3, 3, 1)]]);
+        let payload = build_payload(frame.clone(), strokes);
+        assert!(matches!(
+            HandwrittenMessage::from_payload_with_options(&payload, &limited),
+            Err(HandwritingError::TooManyStrokes(3, 2))
+        ));
+        assert_eq!(
+            HandwrittenMessage::from_payload(&payload)
+                .unwrap()
+                .strokes
+                .len(),
+            4
+        );
+
+        // Pen lifts split one stored stroke into several, which also count
+        let lifted = encode_strokes(&[&[
+            (0, 0, 1),
+            (0xFFFF, 0xFFFF, 1),
+            (1, 1, 1),
+            (0xFFFF, 0xFFFF, 1),
+            (2, 2, 1),
+        ]]);
+        assert!(matches!(
+            HandwrittenMessage::from_payload_with_options(&build_payload(frame, lifted), &limited),
+            Err(HandwritingError::TooManyStrokes(3, 2))
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_warnings() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        // The first stroke is only a pen lift, so nothing is left to draw
+        let strokes = encode_strokes(&[&[(0xFFFF, 0xFFFF, 0)], &[(0, 0, 1), (10, 10, 1)]]);
+        let payload = build_payload(frame.clone(), strokes);
+        let split = ParseOptions {
+            split_pen_lifts: true,
+            ..Default::default()
+        };
+
+        let mut warnings = vec![];
+        let balloon = HandwrittenMessage::from_payload_with_warnings(&payload, &split, |warning| {
+            warnings.push(warning);
+        })
+        .unwrap();
+        assert_eq!(warnings, vec![HandwritingWarning::EmptyStrokeDropped]);
+        assert_eq!(
+            balloon,
+            HandwrittenMessage::from_payload_with_options(&payload, &split).unwrap()
+        );
+
+        let strokes = encode_strokes(&[&[(0, 0, 1), (500, 10, 40)], &[(600, 600, 1)]]);
+        let payload = build_payload(frame, strokes);
+        let mut warnings = vec![];
+        HandwrittenMessage::from_payload_with_warnings(
+            &payload,
+            &ParseOptions {
+                outliers: OutlierHandling::Discard(100),
+                ..Default::default()
+            },
+            |warning| warnings.push(warning),
+        )
+        .unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                HandwritingWarning::PointDropped,
+                HandwritingWarning::PointDropped,
+                HandwritingWarning::EmptyStrokeDropped,
+            ]
+        );
+
+        let mut warnings = vec![];
+        HandwrittenMessage::from_payload_with_warnings(
+            &payload,
+            &ParseOptions {
+                outliers: OutlierHandling::Clamp(100),
+                ..Default::default()
+            },
+            |warning| warnings.push(warning),
+        )
+        .unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                HandwritingWarning::PointClamped,
+                HandwritingWarning::PointClamped,
+                HandwritingWarning::WidthOutOfRange(40),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_handwritten_uncompressed_length() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let strokes = encode_strokes(&[&[(0, 0, 1), (10, 10, 1)]]);
+        let length = strokes.len() as i64;
+        let mut msg = BaseMessage::parse_from_bytes(&build_payload(frame, strokes)).unwrap();
+
+        msg.Handwriting.mut_or_insert_default().DecompressedLength = Some(length);
+        assert!(HandwrittenMessage::from_payload(&msg.write_to_bytes().unwrap()).is_ok());
+
+        msg.Handwriting.mut_or_insert_default().DecompressedLength = Some(length + 8);
+        assert!(matches!(
+            HandwrittenMessage::from_payload(&msg.write_to_bytes().unwrap()),
+            Err(HandwritingError::InvalidDecompressedLength(expected, got))
+                if expected == 26 && got == 18
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_strict() {
+        let strict = ParseOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        let strokes = encode_strokes(&[&[(0, 0, 1), (10, 10, 1)]]);
+        let payload = build_payload(frame, strokes);
+        assert!(HandwrittenMessage::from_payload_with_options(&payload, &strict).is_ok());
+
+        let mut msg = BaseMessage::parse_from_bytes(&payload).unwrap();
+        msg.mut_unknown_fields().add_varint(99, 1);
+        msg.Handwriting
+            .mut_or_insert_default()
+            .mut_unknown_fields()
+            .add_length_delimited(12, vec![1, 2, 3]);
+        msg.Handwriting
+            .mut_or_insert_default()
+            .mut_unknown_fields()
+            .add_varint(12, 4);
+        let extended = msg.write_to_bytes().unwrap();
+
+        // Unknown fields are ignored by default
+        assert!(HandwrittenMessage::from_payload(&extended).is_ok());
+
+        let err = HandwrittenMessage::from_payload_with_options(&extended, &strict).unwrap_err();
+        assert!(matches!(&err, HandwritingError::UnknownFields(fields) if fields == &[12, 99]));
+        assert_eq!(err.to_string(), "handwriting: unknown fields: 12, 99");
+    }
+
+    #[test]
+    fn test_parse_handwritten_strict_fixtures() {
+        let strict = ParseOptions {
+            strict: true,
+            ..Default::default()
+        };
+        for name in ["handwriting.bin", "hello.bin", "pollock.bin", "test.bin"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join("test_data/handwritten_message")
+                .join(name);
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            assert!(HandwrittenMessage::from_payload_with_options(&data, &strict).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_parse_handwritten_stroke_width_padding() {
+        // A thick stroke along the right and bottom edges of a 100x50 frame
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x64, 0x80, 0x32, 0x80];
+        let strokes = encode_strokes(&[&[(0, 0x7FFF, 1), (0x7FFF, 0x7FFF, 20)]]);
+        let payload = build_payload(frame, strokes);
+
+        let fixed = HandwrittenMessage::from_payload(&payload).unwrap();
+        let padded = HandwrittenMessage::from_payload_with_options(
+            &payload,
+            &ParseOptions {
+                padding: Padding::StrokeWidth,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let edge = &padded.strokes[0][1];
+        assert_eq!((edge.x, edge.y, edge.width), (100, 50, 10));
+        assert_eq!((fixed.width, fixed.height, fixed.padding), (105, 55, 5));
+        assert_eq!((padded.width, padded.height, padded.padding), (110, 60, 10));
+        // The whole pen width fits on the canvas, not just half of it
+        assert!(edge.x + edge.width <= padded.width);
+        assert!(edge.x + edge.width > fixed.width);
+        assert_eq!(padded.strokes, fixed.strokes);
+    }
+
+    #[test]
+    fn test_parse_handwritten_frame_origin() {
+        let strokes = encode_strokes(&[&[(0, 0, 1), (0x7FFF, 0x7FFF, 5)]]);
+        let at_zero = HandwrittenMessage::from_payload(&build_payload(
+            vec![0x00, 0x80, 0x00, 0x80, 0x64, 0x80, 0x32, 0x80],
+            strokes.clone(),
+        ))
+        .unwrap();
+        // Origin of (20, -3)
+        let moved = HandwrittenMessage::from_payload(&build_payload(
+            vec![0x14, 0x80, 0xfd, 0x7f, 0x64, 0x80, 0x32, 0x80],
+            strokes,
+        ))
+        .unwrap();
+
+        assert_eq!(at_zero.origin, (0, 0));
+        assert_eq!(moved.origin, (20, -3));
+        assert_eq!((moved.width, moved.height), (105, 55));
+        assert_eq!(moved.strokes, at_zero.strokes);
+        assert_eq!((moved.strokes[0][0].x, moved.strokes[0][0].y), (0, 0));
+        assert_eq!(moved.render_svg(), at_zero.render_svg());
+    }
+
+    #[test]
+    fn test_parse_handwritten_truncated_stroke_header() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        // One complete single-point stroke followed by half of a stroke header
+        let strokes = vec![
+            0x01, 0x00, 0x01, 0x80, 0x02, 0x80, 0x03, 0x80, 0xff, 0x7f, 0x01,
+        ];
+        let err = HandwrittenMessage::from_payload(&build_payload(frame, strokes)).unwrap_err();
+
+        assert!(matches!(
+            err,
+            HandwritingError::InvalidStrokesLength(12, 11, 10)
+        ));
+        assert_eq!(
+            err.to_string(),
+            "handwriting: stroke data needs 12 bytes but only has 11, parsing stopped at offset 10"
+        );
+    }
+
+    #[test]
+    fn test_parse_handwritten_truncated_stroke_points() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x0a, 0x80, 0x0a, 0x80];
+        // A stroke that declares two points but only contains one
+        let strokes = vec![0x02, 0x00, 0x01, 0x80, 0x02, 0x80, 0x03, 0x80, 0xff, 0x7f];
+        let err = HandwrittenMessage::from_payload(&build_payload(frame, strokes)).unwrap_err();
+
+        assert!(matches!(
+            err,
+            HandwritingError::InvalidStrokesLength(18, 10, 2)
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_with_outlier() {
+        let frame = vec![0x00, 0x80, 0x00, 0x80, 0x64, 0x80, 0x64, 0x80];
+        let strokes = encode_strokes(&[
+            &[(0, 0, 5), (16000, 16000, 5), (32000, 32000, 5)],
+            &[(65000, 65000, 5), (32000, 0, 5)],
+        ]);
+        let payload = build_payload(frame, strokes);
+        let rightmost = |balloon: &HandwrittenMessage| {
+            balloon.strokes[0]
+                .iter()
+                .map(|point| point.x)
+                .max()
+                .unwrap()
+        };
+
+        // The outlier crushes the drawing into the top left corner
+        let collapsed = HandwrittenMessage::from_payload(&payload).unwrap();
+        assert_eq!(rightmost(&collapsed), 49);
+
+        let discarded = HandwrittenMessage::from_payload_with_options(
+            &payload,
+            &ParseOptions {
+                outliers: OutlierHandling::Discard(MAX_COORDINATE),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(rightmost(&discarded), 100);
+        assert_eq!(discarded.strokes[1].len(), 1);
+
+        let clamped = HandwrittenMessage::from_payload_with_options(
+            &payload,
+            &ParseOptions {
+                outliers: OutlierHandling::Clamp(MAX_COORDINATE),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(rightmost(&clamped), 97);
+        assert_eq!(clamped.strokes[1][0].x, 100);
+    }
+
+    #[test]
+    fn test_decompressed_strokes() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/test.bin");
+            .join("test_data/handwritten_message/handwriting.bin");
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
-        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
+        let strokes = HandwrittenMessage::decompressed_strokes(&data).unwrap();
+        let declared = BaseMessage::parse_from_bytes(&data)
             .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/test.svg");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+            .Handwriting
+            .DecompressedLength
+            .unwrap();
 
-        assert_eq!(balloon.render_svg(), expected);
+        assert_eq!(strokes.len() as i64, declared);
+        assert_eq!(strokes.len(), 17032);
+        // The first stroke has 79 points
+        assert_eq!(&strokes[..2], &[79, 0]);
     }
 
     #[test]
-    fn test_parse_handwritten_as_svg_builtin() {
+    fn test_parse_handwritten_with_progress() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
-            .join("test_data/handwritten_message/hello.bin");
+            .join("test_data/handwritten_message/pollock.bin");
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
-        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
-            .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/hello.svg");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+        let mut updates = vec![];
+        let balloon = HandwrittenMessage::from_payload_with_progress(&data, |parsed, total| {
+            updates.push((parsed, total));
+        })
+        .unwrap();
 
-        assert_eq!(balloon.render_svg(), expected);
+        let total_points: usize = balloon.strokes.iter().map(Vec::len).sum();
+        assert!(updates.len() > 1);
+        assert!(updates.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(updates.last(), Some(&(total_points, 355498 / 8)));
+        assert_eq!(balloon, HandwrittenMessage::from_payload(&data).unwrap());
     }
 
+    #[cfg(feature = "async")]
     #[test]
-    fn test_parse_handwritten_as_svg_pollock() {
+    fn test_parse_handwritten_async() {
         let protobuf_path = current_dir()
             .unwrap()
             .as_path()
@@ -11215,16 +15606,56 @@ mod tests {
         let mut proto_data = File::open(protobuf_path).unwrap();
         let mut data = vec![];
         proto_data.read_to_end(&mut data).unwrap();
-        let balloon = HandwrittenMessage::from_payload(&data).unwrap();
 
-        let mut expected = String::new();
-        let expected_path = current_dir()
-            .unwrap()
-            .as_path()
-            .join("test_data/handwritten_message/pollock.svg");
-        let mut expected_data = File::open(expected_path).unwrap();
-        expected_data.read_to_string(&mut expected).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let balloon = runtime
+            .block_on(HandwrittenMessage::from_payload_async(data.clone()))
+            .unwrap();
+        assert_eq!(balloon, HandwrittenMessage::from_payload(&data).unwrap());
 
-        assert_eq!(balloon.render_svg(), expected);
+        assert!(matches!(
+            runtime.block_on(HandwrittenMessage::from_payload_async(vec![0xFF])),
+            Err(HandwritingError::NotHandwritingPayload)
+        ));
+    }
+
+    #[test]
+    fn test_parse_handwritten_many() {
+        let mut payloads = vec![];
+        for name in ["handwriting.bin", "hello.bin", "pollock.bin", "test.bin"] {
+            let protobuf_path = current_dir()
+                .unwrap()
+                .as_path()
+                .join("test_data/handwritten_message")
+                .join(name);
+            let mut proto_data = File::open(protobuf_path).unwrap();
+            let mut data = vec![];
+            proto_data.read_to_end(&mut data).unwrap();
+            payloads.push(data);
+        }
+        // Repeat the fixtures so batches are large enough to be split, with a bad payload between them
+        let payloads: Vec<Vec<u8>> = payloads
+            .iter()
+            .cycle()
+            .take(16)
+            .cloned()
+            .chain([vec![0xFF]])
+            .chain(payloads.iter().cloned())
+            .collect();
+
+        let parsed = HandwrittenMessage::parse_many(&payloads);
+        assert_eq!(parsed.len(), payloads.len());
+        for (payload, result) in payloads.iter().zip(&parsed) {
+            match (HandwrittenMessage::from_payload(payload), result) {
+                (Ok(expected), Ok(actual)) => assert_eq!(&expected, actual),
+                (Err(expected), Err(actual)) => {
+                    assert_eq!(expected.to_string(), actual.to_string());
+                }
+                _ => panic!("parse_many disagrees with from_payload"),
+            }
+        }
+        assert!(parsed[16].is_err());
     }
 }
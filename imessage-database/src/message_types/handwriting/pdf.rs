@@ -0,0 +1,97 @@
+/*!
+ Builds single-page `pdf` documents from [handwritten](crate::message_types::handwriting) strokes.
+*/
+
+use std::io::Write;
+
+use crate::{
+    error::handwriting::HandwritingError,
+    message_types::handwriting::{geometry::Point, svg::group_points},
+};
+
+/// Builds a `pdf` document with one page of `width` by `height` points that draws `strokes` as vector paths.
+pub(crate) fn generate_pdf(
+    strokes: &[Vec<Point>],
+    width: u16,
+    height: u16,
+) -> Result<Vec<u8>, HandwritingError> {
+    let content = generate_content(strokes, height).map_err(HandwritingError::PdfError)?;
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+        format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width} {height}] /Contents 4 0 R >>")
+            .into_bytes(),
+        [
+            format!("<< /Length {} >>\nstream\n", content.len()).as_bytes(),
+            &content,
+            b"\nendstream",
+        ]
+        .concat(),
+    ];
+
+    write_document(&objects).map_err(HandwritingError::PdfError)
+}
+
+/// Generates the page's content stream.
+///
+/// `pdf` coordinates start at the bottom left of the page, so `y` values are flipped against `height`.
+fn generate_content(strokes: &[Vec<Point>], height: u16) -> std::io::Result<Vec<u8>> {
+    let mut content = vec![];
+    // Round caps and joins, matching the `svg` output
+    writeln!(content, "1 J 1 j")?;
+    for stroke in strokes {
+        // A line with two identical points renders as a dot because of the round caps
+        if let [point] = stroke.as_slice() {
+            let y = height.saturating_sub(point.y);
+            writeln!(
+                content,
+                "{} w {} {y} m {} {y} l S",
+                point.width, point.x, point.x
+            )?;
+            continue;
+        }
+        for (width, points) in &group_points(stroke) {
+            write!(content, "{width} w")?;
+            for (idx, point) in points.iter().enumerate() {
+                let operator = if idx == 0 { "m" } else { "l" };
+                write!(
+                    content,
+                    " {} {} {operator}",
+                    point.x,
+                    height.saturating_sub(point.y)
+                )?;
+            }
+            writeln!(content, " S")?;
+        }
+    }
+    Ok(content)
+}
+
+/// Writes numbered `objects`, the cross-reference table, and the trailer into a document.
+///
+/// The first object is the document catalog.
+fn write_document(objects: &[Vec<u8>]) -> std::io::Result<Vec<u8>> {
+    let mut pdf = vec![];
+    // The binary comment marks the file as binary for transfer tools
+    pdf.write_all(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (idx, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        writeln!(pdf, "{} 0 obj", idx + 1)?;
+        pdf.write_all(object)?;
+        pdf.write_all(b"\nendobj\n")?;
+    }
+
+    let xref = pdf.len();
+    write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1)?;
+    for offset in offsets {
+        writeln!(pdf, "{offset:010} 00000 n ")?;
+    }
+    write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    )?;
+    Ok(pdf)
+}
@@ -0,0 +1,184 @@
+/*!
+ Rasterizes [handwritten](crate::message_types::handwriting) strokes into images.
+
+ Drawing is shared by every image format; only the encoder differs.
+*/
+
+use tiny_skia::{FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+use crate::{
+    error::handwriting::HandwritingError,
+    message_types::handwriting::{geometry::Point, models::MAX_IMAGE_DIMENSION, svg::group_points},
+};
+
+/// Draws `strokes` in black on a transparent canvas of `width` by `height`, scaled by `scale`.
+///
+/// Fails with [`HandwritingError::InvalidScale`] if `scale` is `0` or the image would be larger than [`MAX_IMAGE_DIMENSION`].
+pub(crate) fn rasterize(
+    strokes: &[Vec<Point>],
+    width: u16,
+    height: u16,
+    scale: u32,
+) -> Result<Pixmap, HandwritingError> {
+    if scale == 0 {
+        return Err(HandwritingError::InvalidScale(scale));
+    }
+    let fits = |length: u16| {
+        u32::from(length)
+            .checked_mul(scale)
+            .filter(|pixels| *pixels <= MAX_IMAGE_DIMENSION)
+    };
+    let (Some(image_width), Some(image_height)) = (fits(width), fits(height)) else {
+        return Err(HandwritingError::InvalidScale(scale));
+    };
+    let mut pixmap = Pixmap::new(image_width, image_height).ok_or_else(|| {
+        HandwritingError::RasterError(format!(
+            "cannot create a {image_width}x{image_height} image"
+        ))
+    })?;
+
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(0, 0, 0, 255);
+    paint.anti_alias = true;
+    let transform = Transform::from_scale(scale as f32, scale as f32);
+
+    for stroke in strokes {
+        // Match the `svg` output, where single points are drawn as circles
+        if let [point] = stroke.as_slice() {
+            if let Some(circle) = PathBuilder::from_circle(
+                f32::from(point.x),
+                f32::from(point.y),
+                f32::from(point.width) / 2.0,
+            ) {
+                pixmap.fill_path(&circle, &paint, FillRule::Winding, transform, None);
+            }
+            continue;
+        }
+        for (width, points) in &group_points(stroke) {
+            let mut path = PathBuilder::new();
+            for (idx, point) in points.iter().enumerate() {
+                if idx == 0 {
+                    path.move_to(f32::from(point.x), f32::from(point.y));
+                } else {
+                    path.line_to(f32::from(point.x), f32::from(point.y));
+                }
+            }
+            if let Some(path) = path.finish() {
+                let line = Stroke {
+                    width: f32::from(*width),
+                    line_cap: LineCap::Round,
+                    line_join: LineJoin::Round,
+                    ..Stroke::default()
+                };
+                pixmap.stroke_path(&path, &paint, &line, transform, None);
+            }
+        }
+    }
+
+    Ok(pixmap)
+}
+
+/// Encodes a rasterized drawing as a `png` image.
+pub(crate) fn encode_png(pixmap: &Pixmap) -> Result<Vec<u8>, HandwritingError> {
+    pixmap
+        .encode_png()
+        .map_err(|why| HandwritingError::RasterError(why.to_string()))
+}
+
+/// The `RGBA` bytes of a rasterized drawing.
+///
+/// Pixmaps store premultiplied colors, but encoders and the `image` crate expect straight alpha.
+#[cfg(any(feature = "webp", feature = "image"))]
+fn straight_rgba(pixmap: &Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
+
+/// Converts a rasterized drawing to an [`image::RgbaImage`].
+#[cfg(feature = "image")]
+pub(crate) fn to_rgba_image(pixmap: &Pixmap) -> Result<image::RgbaImage, HandwritingError> {
+    image::RgbaImage::from_raw(pixmap.width(), pixmap.height(), straight_rgba(pixmap)).ok_or_else(
+        || {
+            HandwritingError::RasterError(format!(
+                "cannot create a {}x{} image",
+                pixmap.width(),
+                pixmap.height()
+            ))
+        },
+    )
+}
+
+/// Encodes a rasterized drawing as a lossless `webp` image.
+#[cfg(feature = "webp")]
+pub(crate) fn encode_webp(pixmap: &Pixmap) -> Result<Vec<u8>, HandwritingError> {
+    let data = straight_rgba(pixmap);
+
+    let mut webp = vec![];
+    image_webp::WebPEncoder::new(&mut webp)
+        .encode(
+            &data,
+            pixmap.width(),
+            pixmap.height(),
+            image_webp::ColorType::Rgba8,
+        )
+        .map_err(|why| HandwritingError::RasterError(why.to_string()))?;
+    Ok(webp)
+}
+
+/// Delay between animation frames, in hundredths of a second
+#[cfg(feature = "gif")]
+const GIF_FRAME_DELAY: u16 = 10;
+
+/// Encodes rasterized drawings as the frames of a looping `gif` animation.
+///
+/// Every pixmap must be the same size. Pixels are either black or transparent, so no color quantization is needed.
+#[cfg(feature = "gif")]
+pub(crate) fn encode_gif(pixmaps: &[Pixmap]) -> Result<Vec<u8>, HandwritingError> {
+    use std::borrow::Cow;
+
+    let Some(first) = pixmaps.first() else {
+        return Err(HandwritingError::RasterError(
+            "an animation needs at least one frame".to_string(),
+        ));
+    };
+    let (Ok(width), Ok(height)) = (u16::try_from(first.width()), u16::try_from(first.height()))
+    else {
+        return Err(HandwritingError::RasterError(format!(
+            "{}x{} is too large for a gif",
+            first.width(),
+            first.height()
+        )));
+    };
+    let map_err = |why: gif::EncodingError| HandwritingError::RasterError(why.to_string());
+
+    let mut data = vec![];
+    {
+        // Index 0 is transparent, index 1 is ink
+        let mut encoder = gif::Encoder::new(&mut data, width, height, &[255, 255, 255, 0, 0, 0])
+            .map_err(map_err)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(map_err)?;
+        for pixmap in pixmaps {
+            let indices: Vec<u8> = pixmap
+                .pixels()
+                .iter()
+                .map(|pixel| u8::from(pixel.alpha() >= 128))
+                .collect();
+            let frame = gif::Frame {
+                width,
+                height,
+                delay: GIF_FRAME_DELAY,
+                transparent: Some(0),
+                buffer: Cow::Owned(indices),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).map_err(map_err)?;
+        }
+    }
+    Ok(data)
+}
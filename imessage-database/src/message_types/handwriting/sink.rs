@@ -0,0 +1,107 @@
+/*!
+ A shared walk over [handwritten](crate::message_types::handwriting) strokes for output formats.
+
+ Renderers implement [`StrokeSink`] and are driven by
+ [`HandwrittenMessage::drive()`](crate::message_types::handwriting::HandwrittenMessage::drive), so every
+ format sees the strokes in the same order with the same width changes.
+*/
+
+use crate::message_types::handwriting::geometry::Point;
+
+/// Receives the strokes of a drawing one point at a time.
+///
+/// For each stroke, [`begin_stroke()`](Self::begin_stroke) is called with the first point, then
+/// [`line_to()`](Self::line_to) with each following point, then [`end_stroke()`](Self::end_stroke).
+/// A stroke with a single point is a dot and has no [`line_to()`](Self::line_to) calls.
+pub trait StrokeSink {
+    /// Start a new stroke at `point`, drawing with `point.width`
+    fn begin_stroke(&mut self, point: &Point);
+    /// Continue the current stroke to `point`
+    fn line_to(&mut self, point: &Point);
+    /// Change the width used after the last point, called after [`line_to()`](Self::line_to) reaches a point with a different width
+    fn set_width(&mut self, width: u16);
+    /// Finish the current stroke
+    fn end_stroke(&mut self);
+}
+
+/// Walks `strokes` once, sending each point to `sink`.
+pub(crate) fn drive(strokes: &[Vec<Point>], sink: &mut impl StrokeSink) {
+    for stroke in strokes {
+        let Some((first, rest)) = stroke.split_first() else {
+            continue;
+        };
+        sink.begin_stroke(first);
+        let mut width = first.width;
+        for point in rest {
+            sink.line_to(point);
+            if point.width != width {
+                width = point.width;
+                sink.set_width(width);
+            }
+        }
+        sink.end_stroke();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message_types::handwriting::{
+        geometry::Point,
+        sink::{StrokeSink, drive},
+    };
+
+    /// Records every call as a string
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl StrokeSink for Recorder {
+        fn begin_stroke(&mut self, point: &Point) {
+            self.0
+                .push(format!("begin {},{} {}", point.x, point.y, point.width));
+        }
+
+        fn line_to(&mut self, point: &Point) {
+            self.0.push(format!("line {},{}", point.x, point.y));
+        }
+
+        fn set_width(&mut self, width: u16) {
+            self.0.push(format!("width {width}"));
+        }
+
+        fn end_stroke(&mut self) {
+            self.0.push("end".to_string());
+        }
+    }
+
+    fn stroke(points: &[(u16, u16, u16)]) -> Vec<Point> {
+        points
+            .iter()
+            .map(|&(x, y, width)| Point { x, y, width })
+            .collect()
+    }
+
+    #[test]
+    fn can_drive_sink() {
+        let strokes = vec![
+            stroke(&[(0, 0, 2), (1, 1, 2), (2, 2, 3), (3, 3, 3)]),
+            vec![],
+            stroke(&[(5, 5, 1)]),
+        ];
+        let mut recorder = Recorder::default();
+        drive(&strokes, &mut recorder);
+
+        assert_eq!(
+            recorder.0,
+            vec![
+                "begin 0,0 2",
+                "line 1,1",
+                "line 2,2",
+                "width 3",
+                "line 3,3",
+                "end",
+                "begin 5,5 1",
+                "end",
+            ]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+/*!
+ Views over single [handwritten](crate::message_types::handwriting) strokes.
+
+ [`HandwrittenMessage::strokes`](crate::message_types::handwriting::HandwrittenMessage::strokes) is still a
+ `Vec<Vec<Point>>`; use [`HandwrittenMessage::iter_strokes()`](crate::message_types::handwriting::HandwrittenMessage::iter_strokes)
+ to work with [`Stroke`]s instead.
+*/
+
+use crate::message_types::handwriting::geometry::Point;
+
+/// The smallest rectangle containing every point of a stroke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min_x: u16,
+    pub min_y: u16,
+    pub max_x: u16,
+    pub max_y: u16,
+}
+
+/// A single continuous line in a handwritten message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stroke<'a> {
+    points: &'a [Point],
+}
+
+impl<'a> Stroke<'a> {
+    /// Create a view over a stroke's points
+    #[must_use]
+    pub fn new(points: &'a [Point]) -> Self {
+        Self { points }
+    }
+
+    /// The points that make up the stroke, in drawing order
+    #[must_use]
+    pub fn points(&self) -> &'a [Point] {
+        self.points
+    }
+
+    /// The length of the stroke, in canvas units
+    #[must_use]
+    pub fn length(&self) -> f64 {
+        self.points
+            .windows(2)
+            .map(|pair| {
+                let dx = f64::from(pair[1].x) - f64::from(pair[0].x);
+                let dy = f64::from(pair[1].y) - f64::from(pair[0].y);
+                dx.hypot(dy)
+            })
+            .sum()
+    }
+
+    /// The point the stroke starts at, or `None` if the stroke is empty
+    #[must_use]
+    pub fn start(&self) -> Option<&'a Point> {
+        self.points.first()
+    }
+
+    /// The point the stroke ends at, or `None` if the stroke is empty
+    #[must_use]
+    pub fn end(&self) -> Option<&'a Point> {
+        self.points.last()
+    }
+
+    /// The rectangle containing the stroke, or `None` if the stroke is empty
+    #[must_use]
+    pub fn bounds(&self) -> Option<Bounds> {
+        let first = self.points.first()?;
+        Some(self.points.iter().fold(
+            Bounds {
+                min_x: first.x,
+                min_y: first.y,
+                max_x: first.x,
+                max_y: first.y,
+            },
+            |bounds, point| Bounds {
+                min_x: bounds.min_x.min(point.x),
+                min_y: bounds.min_y.min(point.y),
+                max_x: bounds.max_x.max(point.x),
+                max_y: bounds.max_y.max(point.y),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message_types::handwriting::{
+        geometry::Point,
+        stroke::{Bounds, Stroke},
+    };
+
+    fn points() -> Vec<Point> {
+        [(10, 40, 1), (13, 44, 2), (13, 20, 3)]
+            .iter()
+            .map(|&(x, y, width)| Point { x, y, width })
+            .collect()
+    }
+
+    #[test]
+    fn can_measure_stroke() {
+        let points = points();
+        let stroke = Stroke::new(&points);
+
+        assert_eq!(stroke.points().len(), 3);
+        assert_eq!(stroke.length(), 5.0 + 24.0);
+        assert_eq!(
+            stroke.start().map(|point| (point.x, point.y)),
+            Some((10, 40))
+        );
+        assert_eq!(stroke.end().map(|point| (point.x, point.y)), Some((13, 20)));
+        assert_eq!(
+            stroke.bounds(),
+            Some(Bounds {
+                min_x: 10,
+                min_y: 20,
+                max_x: 13,
+                max_y: 44,
+            })
+        );
+    }
+
+    #[test]
+    fn can_measure_empty_stroke() {
+        let stroke = Stroke::new(&[]);
+
+        assert_eq!(stroke.length(), 0.0);
+        assert_eq!(stroke.start(), None);
+        assert_eq!(stroke.end(), None);
+        assert_eq!(stroke.bounds(), None);
+    }
+
+    #[test]
+    fn can_measure_single_point() {
+        let points = vec![Point {
+            x: 4,
+            y: 2,
+            width: 3,
+        }];
+        let stroke = Stroke::new(&points);
+
+        assert_eq!(stroke.length(), 0.0);
+        assert_eq!(stroke.start(), stroke.end());
+        assert_eq!(
+            stroke.bounds(),
+            Some(Bounds {
+                min_x: 4,
+                min_y: 2,
+                max_x: 4,
+                max_y: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,1010 @@
+/*!
+ Builds `svg` graphics from [handwritten](crate::message_types::handwriting) strokes.
+*/
+
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Result, Write},
+};
+
+use crate::message_types::handwriting::{
+    geometry::Point,
+    sink::{StrokeSink, drive},
+    stroke::Bounds,
+};
+
+/// Seconds taken to draw each stroke of an animated `svg`
+pub const ANIMATION_STROKE_SECONDS: f64 = 0.5;
+/// Seconds a [looping](AnimationOptions::looping) animation shows the finished drawing before starting over
+pub const ANIMATION_LOOP_PAUSE_SECONDS: f64 = 1.0;
+
+/// Options that control how [`render_svg_animated_with_options()`](crate::message_types::handwriting::HandwrittenMessage::render_svg_animated_with_options) replays a handwritten message.
+///
+/// The default options produce the same output as [`render_svg_animated()`](crate::message_types::handwriting::HandwrittenMessage::render_svg_animated).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationOptions {
+    /// Replay the drawing forever with `repeatCount="indefinite"`, pausing for [`ANIMATION_LOOP_PAUSE_SECONDS`] between replays
+    ///
+    /// Otherwise the drawing plays once with `repeatCount="1"` and stays finished, which suits archives better than previews.
+    pub looping: bool,
+}
+
+/// Options that control how [`render_svg_with_options()`](crate::message_types::handwriting::HandwrittenMessage::render_svg_with_options) draws a handwritten message.
+///
+/// The default options produce the same output as [`render_svg()`](crate::message_types::handwriting::HandwrittenMessage::render_svg).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgRenderOptions {
+    /// Scale each segment's `stroke-opacity` with its width so thin, light-pressure segments are lighter
+    pub pressure_opacity: bool,
+    /// Draw guides for debugging alignment: the parsed frame in blue, the padded canvas in red,
+    /// and a green dot at the start of each stroke
+    pub debug_frame: bool,
+    /// The `stroke-linecap` used for every line
+    pub linecap: LineCap,
+    /// The `stroke-linejoin` used for every line
+    pub linejoin: LineJoin,
+    /// A CSS color to fill the canvas with before drawing; the canvas is transparent if `None`
+    pub background_color: Option<String>,
+    /// Two CSS colors to fade between, from the top of the canvas to the bottom, drawn over any [`background_color`](Self::background_color)
+    pub background_gradient: Option<(String, String)>,
+    /// Draw each stroke as a filled outline instead of a line, for cutters and single-color printing
+    ///
+    /// [`pressure_opacity`](Self::pressure_opacity), [`linecap`](Self::linecap), and [`linejoin`](Self::linejoin) do not apply to outlines.
+    pub filled_outline: bool,
+    /// The number of decimal places fractional numbers are rounded to, with trailing zeros trimmed
+    ///
+    /// Coordinates are whole numbers except in outlines and dot radii, so this has no effect on most output.
+    pub coordinate_decimals: u8,
+    /// Wrap the elements of each stroke in a `<g id="stroke-N">`, numbered from `0` in stored order, so vector editors can select strokes
+    ///
+    /// This does not apply to [`filled_outline`](Self::filled_outline)s, which are already one element per stroke.
+    pub group_strokes: bool,
+    /// Blur drawings sent with [invisible ink](crate::message_types::handwriting::models::INVISIBLE_INK_EFFECT), as Messages shows them before they are revealed
+    ///
+    /// Drawings without an [`effect`](crate::message_types::handwriting::HandwrittenMessage::effect) are never blurred.
+    pub apply_effects: bool,
+    /// Draw a faint light shadow behind the strokes so thin black lines stay readable over busy backgrounds, such as photos
+    pub drop_shadow: bool,
+    /// Draw faint ruled or grid lines behind the strokes, like a page from a notebook
+    pub paper: Option<PaperStyle>,
+    /// Draw coordinate ticks along the top and left edges and a labeled scale bar in the bottom left corner, in canvas units,
+    /// so measurements of recovered drawings can be documented
+    ///
+    /// Canvas units are the units of the payload's frame, so they match Apple's coordinates.
+    pub forensic_overlay: bool,
+    /// Draw a green dot at the first point of each stroke and a red dot at its last, to show the direction strokes were drawn in
+    pub endpoint_markers: bool,
+}
+
+impl Default for SvgRenderOptions {
+    fn default() -> Self {
+        Self {
+            pressure_opacity: false,
+            debug_frame: false,
+            linecap: LineCap::default(),
+            linejoin: LineJoin::default(),
+            background_color: None,
+            background_gradient: None,
+            filled_outline: false,
+            coordinate_decimals: 1,
+            group_strokes: false,
+            apply_effects: false,
+            drop_shadow: false,
+            paper: None,
+            forensic_overlay: false,
+            endpoint_markers: false,
+        }
+    }
+}
+
+/// Lines drawn behind the strokes by [`SvgRenderOptions::paper`], in canvas units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaperStyle {
+    /// Horizontal lines every `spacing` units, like ruled paper
+    Ruled { spacing: u16 },
+    /// Horizontal and vertical lines every `spacing` units, like graph paper
+    Grid { spacing: u16 },
+}
+
+/// Shapes drawn at the ends of each line, emitted as `stroke-linecap`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    #[default]
+    Round,
+    Butt,
+    Square,
+}
+
+impl Display for LineCap {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
+        match self {
+            LineCap::Round => write!(fmt, "round"),
+            LineCap::Butt => write!(fmt, "butt"),
+            LineCap::Square => write!(fmt, "square"),
+        }
+    }
+}
+
+/// Shapes drawn where two segments of a line meet, emitted as `stroke-linejoin`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    #[default]
+    Round,
+    Bevel,
+    Miter,
+}
+
+impl Display for LineJoin {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
+        match self {
+            LineJoin::Round => write!(fmt, "round"),
+            LineJoin::Bevel => write!(fmt, "bevel"),
+            LineJoin::Miter => write!(fmt, "miter"),
+        }
+    }
+}
+
+/// An `svg` element or run of text, so callers can add their own attributes before serializing a drawing.
+///
+/// Built by [`to_svg_tree()`](crate::message_types::handwriting::HandwrittenMessage::to_svg_tree) and serialized with [`to_string()`](ToString::to_string).
+/// Each element is written on its own line, in the same layout as [`render_svg()`](crate::message_types::handwriting::HandwrittenMessage::render_svg).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvgNode {
+    /// An element with its attributes, in the order they are written, and its children
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<SvgNode>,
+    },
+    /// Character data inside an element
+    Text(String),
+}
+
+impl SvgNode {
+    /// Creates an element with no attributes or children
+    pub fn element(name: &str) -> Self {
+        SvgNode::Element {
+            name: name.to_string(),
+            attributes: vec![],
+            children: vec![],
+        }
+    }
+
+    /// Adds an attribute to an element, replacing any existing value
+    #[must_use]
+    pub fn with_attribute(mut self, name: &str, value: impl ToString) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+
+    /// Adds a child to the end of an element
+    #[must_use]
+    pub fn with_child(mut self, child: SvgNode) -> Self {
+        if let SvgNode::Element { children, .. } = &mut self {
+            children.push(child);
+        }
+        self
+    }
+
+    /// Sets an attribute on an element, replacing any existing value. Text nodes are unchanged.
+    pub fn set_attribute(&mut self, name: &str, value: impl ToString) {
+        if let SvgNode::Element { attributes, .. } = self {
+            let value = value.to_string();
+            match attributes.iter_mut().find(|(key, _)| key == name) {
+                Some((_, existing)) => *existing = value,
+                None => attributes.push((name.to_string(), value)),
+            }
+        }
+    }
+
+    /// The value of the attribute called `name`, if this is an element that has one
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        match self {
+            SvgNode::Element { attributes, .. } => attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str()),
+            SvgNode::Text(_) => None,
+        }
+    }
+
+    /// The children of an element, or an empty slice for text
+    pub fn children(&self) -> &[SvgNode] {
+        match self {
+            SvgNode::Element { children, .. } => children,
+            SvgNode::Text(_) => &[],
+        }
+    }
+
+    /// The children of an element, for editing, or `None` for text
+    pub fn children_mut(&mut self) -> Option<&mut Vec<SvgNode>> {
+        match self {
+            SvgNode::Element { children, .. } => Some(children),
+            SvgNode::Text(_) => None,
+        }
+    }
+
+    /// Adds `nodes` to the end of an element's children
+    pub(crate) fn extend(&mut self, nodes: impl IntoIterator<Item = SvgNode>) {
+        if let SvgNode::Element { children, .. } = self {
+            children.extend(nodes);
+        }
+    }
+
+    /// The start tag of an element, such as `<g id="stroke-0">`, or an empty string for text
+    pub(crate) fn start_tag(&self) -> String {
+        let mut tag = String::new();
+        if let SvgNode::Element {
+            name, attributes, ..
+        } = self
+        {
+            let _ = write_start_tag(&mut tag, name, attributes);
+            tag.push('>');
+        }
+        tag
+    }
+}
+
+/// Writes `<name` and its attributes, leaving the tag open so it can be closed or self-closed
+fn write_start_tag(out: &mut impl Write, name: &str, attributes: &[(String, String)]) -> Result {
+    write!(out, "<{name}")?;
+    for (key, value) in attributes {
+        write!(out, r#" {key}="{}""#, escape(value, true))?;
+    }
+    Ok(())
+}
+
+impl Display for SvgNode {
+    /// Elements without children are self-closing, elements holding only text stay on one line,
+    /// and elements holding other elements put their children on the lines between their tags.
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
+        match self {
+            SvgNode::Text(text) => write!(fmt, "{}", escape(text, false)),
+            SvgNode::Element {
+                name,
+                attributes,
+                children,
+            } => {
+                write_start_tag(fmt, name, attributes)?;
+                if children.is_empty() {
+                    return writeln!(fmt, " />");
+                }
+                write!(fmt, ">")?;
+                if !children
+                    .iter()
+                    .all(|child| matches!(child, SvgNode::Text(_)))
+                {
+                    writeln!(fmt)?;
+                }
+                for child in children {
+                    write!(fmt, "{child}")?;
+                }
+                writeln!(fmt, "</{name}>")
+            }
+        }
+    }
+}
+
+/// Escapes the characters that would end `text` early, including `"` when it is an attribute value
+pub(crate) fn escape(text: &str, attribute: bool) -> Cow<'_, str> {
+    if !text.contains(['&', '<']) && (!attribute || !text.contains('"')) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = text.replace('&', "&amp;").replace('<', "&lt;");
+    if attribute {
+        escaped = escaped.replace('"', "&quot;");
+    }
+    Cow::Owned(escaped)
+}
+
+/// Formats `value` rounded to `decimals` places, trimming trailing zeros and the decimal point if nothing follows it.
+fn format_number(value: f64, decimals: u8) -> String {
+    let formatted = format!("{value:.*}", usize::from(decimals));
+    let trimmed = if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &formatted
+    };
+    // Small negative numbers can round to `-0`
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// The `stroke-opacity` of a segment of the given width, if enabled.
+///
+/// Widths are fit into `1..=10`, which maps onto opacities of `0.25..=1.00`.
+fn opacity(width: u16, options: &SvgRenderOptions) -> Option<String> {
+    options.pressure_opacity.then(|| {
+        let scale = f64::from(width.clamp(1, 10) - 1) / 9.0;
+        format!("{:.2}", 0.25 + 0.75 * scale)
+    })
+}
+
+/// The longest `polyline` without its points, with the widest possible `stroke-width`
+const POLYLINE_BYTES: usize =
+    r#"<polyline class="line" points="" stroke-width="65535" />"#.len() + 1;
+/// The longest point in a `polyline`, including its separator
+const POINT_BYTES: usize = "65535,65535 ".len();
+
+/// An upper bound on the bytes [`generate_strokes()`] writes for `strokes` with the default [`SvgRenderOptions`].
+///
+/// In the worst case every point changes width, which starts a new `polyline` that repeats the previous point.
+pub(crate) fn estimate_strokes_bytes(strokes: &[Vec<Point>]) -> usize {
+    let points: usize = strokes.iter().map(Vec::len).sum();
+    strokes.len() * POLYLINE_BYTES + points * (POLYLINE_BYTES + 2 * POINT_BYTES)
+}
+
+/// Generates svg lines from an array of strokes.
+pub(crate) fn generate_strokes(
+    svg: &mut String,
+    strokes: &[Vec<Point>],
+    options: &SvgRenderOptions,
+) {
+    // Most points take up to 8 bytes, like `123,45 `
+    svg.reserve(strokes.iter().map(Vec::len).sum::<usize>() * 8);
+    drive(strokes, &mut SvgSink::new(svg, options));
+}
+
+/// Receives the elements drawn by an [`SvgSink`], so the text and [`SvgNode`] outputs split strokes the same way.
+pub(crate) trait SvgWriter {
+    /// Starts the `<g>` that holds the elements of the stroke at `index`
+    fn begin_group(&mut self, index: usize);
+    /// Ends the group started by [`begin_group()`](Self::begin_group)
+    fn end_group(&mut self);
+    /// Draws a run of points with the same width
+    fn polyline(&mut self, points: &[(u16, u16)], width: u16, opacity: Option<&str>);
+    /// Draws a stroke with a single point
+    fn circle(&mut self, x: u16, y: u16, radius: &str, opacity: Option<&str>);
+}
+
+/// Writes the elements drawn by an [`SvgSink`] as text, one per line.
+pub(crate) struct SvgText<'a> {
+    svg: &'a mut String,
+}
+
+impl SvgWriter for SvgText<'_> {
+    fn begin_group(&mut self, index: usize) {
+        let _ = writeln!(self.svg, r#"<g id="stroke-{index}">"#);
+    }
+
+    fn end_group(&mut self) {
+        self.svg.push_str("</g>\n");
+    }
+
+    /// This runs for every width change, so numbers are written straight into the output instead of through `format!`.
+    fn polyline(&mut self, points: &[(u16, u16)], width: u16, opacity: Option<&str>) {
+        let mut number = itoa::Buffer::new();
+        self.svg.push_str(r#"<polyline class="line" points=""#);
+        for (idx, (x, y)) in points.iter().enumerate() {
+            if idx > 0 {
+                self.svg.push(' ');
+            }
+            self.svg.push_str(number.format(*x));
+            self.svg.push(',');
+            self.svg.push_str(number.format(*y));
+        }
+        self.svg.push_str(r#"" stroke-width=""#);
+        self.svg.push_str(number.format(width));
+        self.svg.push('"');
+        if let Some(opacity) = opacity {
+            let _ = write!(self.svg, r#" stroke-opacity="{opacity}""#);
+        }
+        self.svg.push_str(" />\n");
+    }
+
+    fn circle(&mut self, x: u16, y: u16, radius: &str, opacity: Option<&str>) {
+        let _ = write!(
+            self.svg,
+            r#"<circle cx="{x}" cy="{y}" r="{radius}" fill="black""#
+        );
+        if let Some(opacity) = opacity {
+            let _ = write!(self.svg, r#" stroke-opacity="{opacity}""#);
+        }
+        self.svg.push_str(" />\n");
+    }
+}
+
+/// Builds the elements drawn by an [`SvgSink`] as [`SvgNode`]s.
+#[derive(Default)]
+pub(crate) struct SvgTree {
+    /// The elements drawn so far, not counting the open group
+    pub(crate) nodes: Vec<SvgNode>,
+    /// The group of the current stroke, while one is open
+    group: Option<SvgNode>,
+    /// Prepended to the `id` of each stroke's group
+    id_prefix: String,
+    /// Give each `polyline` its own presentation attributes instead of the shared `line` class
+    inline_style: Option<(LineCap, LineJoin)>,
+}
+
+impl SvgTree {
+    /// Adds `node` to the open group, or after the elements drawn so far
+    fn push(&mut self, node: SvgNode) {
+        match &mut self.group {
+            Some(group) => group.extend([node]),
+            None => self.nodes.push(node),
+        }
+    }
+}
+
+impl SvgWriter for SvgTree {
+    fn begin_group(&mut self, index: usize) {
+        self.group = Some(
+            SvgNode::element("g").with_attribute("id", format!("{}stroke-{index}", self.id_prefix)),
+        );
+    }
+
+    fn end_group(&mut self) {
+        self.nodes.extend(self.group.take());
+    }
+
+    fn polyline(&mut self, points: &[(u16, u16)], width: u16, opacity: Option<&str>) {
+        let points: Vec<String> = points.iter().map(|(x, y)| format!("{x},{y}")).collect();
+        let mut polyline = match self.inline_style {
+            Some((linecap, linejoin)) => SvgNode::element("polyline")
+                .with_attribute("points", points.join(" "))
+                .with_attribute("fill", "none")
+                .with_attribute("stroke", "black")
+                .with_attribute("stroke-width", width)
+                .with_attribute("stroke-linecap", linecap)
+                .with_attribute("stroke-linejoin", linejoin),
+            None => SvgNode::element("polyline")
+                .with_attribute("class", "line")
+                .with_attribute("points", points.join(" "))
+                .with_attribute("stroke-width", width),
+        };
+        if let Some(opacity) = opacity {
+            polyline.set_attribute("stroke-opacity", opacity);
+        }
+        self.push(polyline);
+    }
+
+    fn circle(&mut self, x: u16, y: u16, radius: &str, opacity: Option<&str>) {
+        let mut circle = SvgNode::element("circle")
+            .with_attribute("cx", x)
+            .with_attribute("cy", y)
+            .with_attribute("r", radius)
+            .with_attribute("fill", "black");
+        if let Some(opacity) = opacity {
+            circle.set_attribute("stroke-opacity", opacity);
+        }
+        self.push(circle);
+    }
+}
+
+/// Draws strokes as `svg` elements: one `polyline` for each run of points with the same width, or a `circle` for a dot.
+///
+/// The elements are handed to an [`SvgWriter`], which writes them as text by default.
+pub(crate) struct SvgSink<'a, W = SvgText<'a>> {
+    writer: W,
+    options: &'a SvgRenderOptions,
+    /// The width of the current run
+    width: u16,
+    /// The points in the current run
+    points: Vec<(u16, u16)>,
+    /// `true` until the current stroke moves past its first point
+    dot: bool,
+    /// The number of strokes started so far
+    strokes: usize,
+}
+
+impl<'a> SvgSink<'a> {
+    pub(crate) fn new(svg: &'a mut String, options: &'a SvgRenderOptions) -> Self {
+        Self::with_writer(SvgText { svg }, options)
+    }
+}
+
+impl<'a, W: SvgWriter> SvgSink<'a, W> {
+    pub(crate) fn with_writer(writer: W, options: &'a SvgRenderOptions) -> Self {
+        Self {
+            writer,
+            options,
+            width: 0,
+            points: vec![],
+            dot: false,
+            strokes: 0,
+        }
+    }
+
+    /// Draws the current run as a `polyline`
+    fn flush(&mut self) {
+        let opacity = opacity(self.width, self.options);
+        self.writer
+            .polyline(&self.points, self.width, opacity.as_deref());
+    }
+}
+
+impl<W: SvgWriter> StrokeSink for SvgSink<'_, W> {
+    fn begin_stroke(&mut self, point: &Point) {
+        if self.options.group_strokes {
+            self.writer.begin_group(self.strokes);
+        }
+        self.strokes += 1;
+        self.width = point.width;
+        self.points.clear();
+        self.points.push((point.x, point.y));
+        self.dot = true;
+    }
+
+    fn line_to(&mut self, point: &Point) {
+        self.points.push((point.x, point.y));
+        self.dot = false;
+    }
+
+    fn set_width(&mut self, width: u16) {
+        // The new run starts where the last one ended, so the line stays connected
+        self.flush();
+        let last = self.points.last().copied();
+        self.points.clear();
+        self.points.extend(last);
+        self.width = width;
+    }
+
+    fn end_stroke(&mut self) {
+        match self.points.as_slice() {
+            // A polyline with two identical points renders as nothing, so draw dots as circles
+            [(x, y)] if self.dot => {
+                let radius = format_number(
+                    f64::from(self.width) / 2.0,
+                    self.options.coordinate_decimals,
+                );
+                let opacity = opacity(self.width, self.options);
+                self.writer.circle(*x, *y, &radius, opacity.as_deref());
+            }
+            _ => {
+                // Repeat the last point so a run of one segment still has two points
+                if let Some(&last) = self.points.last() {
+                    self.points.push(last);
+                }
+                self.flush();
+            }
+        }
+        self.points.clear();
+        if self.options.group_strokes {
+            self.writer.end_group();
+        }
+    }
+}
+
+/// Builds the elements [`generate_strokes()`] writes, as [`SvgNode`]s.
+pub(crate) fn generate_stroke_nodes(
+    strokes: &[Vec<Point>],
+    options: &SvgRenderOptions,
+) -> Vec<SvgNode> {
+    let mut sink = SvgSink::with_writer(SvgTree::default(), options);
+    drive(strokes, &mut sink);
+    sink.writer.nodes
+}
+
+/// Builds a `<g id="{id_prefix}handwriting">` of `strokes` that can be nested in another `svg` document.
+///
+/// Each stroke is wrapped in a `<g id="{id_prefix}stroke-N">`, and every element carries its own presentation
+/// attributes, so several fragments with different prefixes can share a document without their ids or styles colliding.
+pub(crate) fn generate_fragment(strokes: &[Vec<Point>], id_prefix: &str) -> SvgNode {
+    let options = SvgRenderOptions {
+        group_strokes: true,
+        ..Default::default()
+    };
+    let writer = SvgTree {
+        id_prefix: id_prefix.to_string(),
+        inline_style: Some((options.linecap, options.linejoin)),
+        ..Default::default()
+    };
+    let mut sink = SvgSink::with_writer(writer, &options);
+    drive(strokes, &mut sink);
+
+    let mut fragment =
+        SvgNode::element("g").with_attribute("id", format!("{id_prefix}handwriting"));
+    fragment.extend(sink.writer.nodes);
+    fragment
+}
+
+/// Builds the faint lines of `paper` across a canvas of `width` by `height`.
+///
+/// Lines start one `spacing` from the top or left edge and stop before the far edge. A `spacing` of `0` draws nothing.
+pub(crate) fn generate_paper(width: u16, height: u16, paper: PaperStyle) -> Vec<SvgNode> {
+    let (spacing, vertical) = match paper {
+        PaperStyle::Ruled { spacing } => (spacing, false),
+        PaperStyle::Grid { spacing } => (spacing, true),
+    };
+    let mut lines = vec![];
+    if spacing == 0 {
+        return lines;
+    }
+    let line = |x1: u16, y1: u16, x2: u16, y2: u16| {
+        SvgNode::element("line")
+            .with_attribute("class", "paper")
+            .with_attribute("x1", x1)
+            .with_attribute("y1", y1)
+            .with_attribute("x2", x2)
+            .with_attribute("y2", y2)
+            .with_attribute("stroke", "#c8d7e1")
+            .with_attribute("stroke-width", "0.5")
+    };
+    for y in (spacing..height).step_by(usize::from(spacing)) {
+        lines.push(line(0, y, width, y));
+    }
+    if vertical {
+        for x in (spacing..width).step_by(usize::from(spacing)) {
+            lines.push(line(x, 0, x, height));
+        }
+    }
+    lines
+}
+
+/// Generates svg lines that draw themselves one stroke at a time, in the order of `strokes`.
+///
+/// Each stroke takes [`ANIMATION_STROKE_SECONDS`], split between its segments by point count. Lines are revealed by
+/// animating `stroke-dashoffset` over a normalized `pathLength`, and dots appear when their stroke begins.
+///
+/// A looping animation cannot restart each element on its own, since they would drift apart. Instead, every element
+/// animates over the whole loop and uses `keyTimes` to wait for its turn.
+pub(crate) fn generate_animated(
+    svg: &mut String,
+    strokes: &[&Vec<Point>],
+    options: &AnimationOptions,
+) {
+    let cycle = strokes.len() as f64 * ANIMATION_STROKE_SECONDS + ANIMATION_LOOP_PAUSE_SECONDS;
+    let key_time = |seconds: f64| format_number(seconds / cycle, 3);
+
+    for (idx, stroke) in strokes.iter().enumerate() {
+        let start = idx as f64 * ANIMATION_STROKE_SECONDS;
+        if let [point] = stroke.as_slice() {
+            let reveal = if options.looping {
+                format!(
+                    r#"<animate attributeName="opacity" values="0;1" keyTimes="0;{}" calcMode="discrete" dur="{}s" repeatCount="indefinite" />"#,
+                    key_time(start),
+                    format_number(cycle, 3)
+                )
+            } else {
+                format!(
+                    r#"<set attributeName="opacity" to="1" begin="{}s" fill="freeze" />"#,
+                    format_number(start, 3)
+                )
+            };
+            svg.push_str(&format!(
+                r#"<circle cx="{}" cy="{}" r="{}" fill="black" opacity="0">{reveal}</circle>"#,
+                point.x,
+                point.y,
+                f64::from(point.width) / 2.0,
+            ));
+            svg.push('\n');
+            continue;
+        }
+
+        let groups = group_points(stroke);
+        let total: usize = groups.iter().map(|(_, points)| points.len() - 1).sum();
+        let mut drawn = 0;
+        for (width, points) in &groups {
+            let segments = points.len() - 1;
+            let begin = start + ANIMATION_STROKE_SECONDS * drawn as f64 / total as f64;
+            let duration = ANIMATION_STROKE_SECONDS * segments as f64 / total as f64;
+            drawn += segments;
+
+            let points: Vec<String> = points
+                .iter()
+                .map(|point| format!("{},{}", point.x, point.y))
+                .collect();
+            let reveal = if options.looping {
+                format!(
+                    r#"<animate attributeName="stroke-dashoffset" values="1;1;0;0" keyTimes="0;{};{};1" dur="{}s" repeatCount="indefinite" />"#,
+                    key_time(begin),
+                    key_time(begin + duration),
+                    format_number(cycle, 3)
+                )
+            } else {
+                format!(
+                    r#"<animate attributeName="stroke-dashoffset" from="1" to="0" begin="{}s" dur="{}s" fill="freeze" repeatCount="1" />"#,
+                    format_number(begin, 3),
+                    format_number(duration, 3)
+                )
+            };
+            svg.push_str(&format!(
+                r#"<polyline class="line" points="{}" stroke-width="{width}" pathLength="1" stroke-dasharray="1" stroke-dashoffset="1">{reveal}</polyline>"#,
+                points.join(" "),
+            ));
+            svg.push('\n');
+        }
+    }
+}
+
+/// Builds svg paths that fill the area covered by each stroke.
+///
+/// Each point is offset by half of its width along the stroke's normal on both sides, and the two sides are joined into one polygon.
+pub(crate) fn generate_outlines(strokes: &[Vec<Point>], decimals: u8) -> Vec<SvgNode> {
+    let mut outlines = Vec::with_capacity(strokes.len());
+    for stroke in strokes {
+        if let [point] = stroke.as_slice() {
+            outlines.push(
+                SvgNode::element("circle")
+                    .with_attribute("cx", point.x)
+                    .with_attribute("cy", point.y)
+                    .with_attribute("r", format_number(f64::from(point.width) / 2.0, decimals))
+                    .with_attribute("fill", "black"),
+            );
+            continue;
+        }
+
+        let mut left = Vec::with_capacity(stroke.len());
+        let mut right = Vec::with_capacity(stroke.len());
+        let mut normal = (0.0, -1.0);
+        for (idx, point) in stroke.iter().enumerate() {
+            // Use the direction between the neighbors so corners are mitered evenly
+            let before = &stroke[idx.saturating_sub(1)];
+            let after = &stroke[(idx + 1).min(stroke.len() - 1)];
+            let dx = f64::from(after.x) - f64::from(before.x);
+            let dy = f64::from(after.y) - f64::from(before.y);
+            let length = dx.hypot(dy);
+            // Repeated points have no direction, so keep the previous normal
+            if length > 0.0 {
+                normal = (-dy / length, dx / length);
+            }
+
+            let offset = f64::from(point.width) / 2.0;
+            let (x, y) = (f64::from(point.x), f64::from(point.y));
+            left.push((x + normal.0 * offset, y + normal.1 * offset));
+            right.push((x - normal.0 * offset, y - normal.1 * offset));
+        }
+
+        let mut path = String::with_capacity(24 * stroke.len());
+        for (idx, (x, y)) in left.iter().chain(right.iter().rev()).enumerate() {
+            path.push_str(&format!(
+                "{}{},{}",
+                if idx == 0 { "M" } else { " L" },
+                format_number(*x, decimals),
+                format_number(*y, decimals)
+            ));
+        }
+        path.push_str(" Z");
+        outlines.push(
+            SvgNode::element("path")
+                .with_attribute("class", "outline")
+                .with_attribute("d", path)
+                .with_attribute("fill", "black"),
+        );
+    }
+    outlines
+}
+
+/// Generates a compact `svg` document from an array of strokes.
+///
+/// Unlike [`generate_strokes()`], there is no whitespace between elements and each stroke width is
+/// written once as a CSS class shared by every segment of that width.
+pub(crate) fn generate_minified(width: u16, height: u16, strokes: &[Vec<Point>]) -> String {
+    let mut body = String::new();
+    let mut widths = BTreeSet::new();
+    for stroke in strokes {
+        if let [point] = stroke.as_slice() {
+            body.push_str(&format!(
+                r#"<circle cx="{}" cy="{}" r="{}"/>"#,
+                point.x,
+                point.y,
+                f64::from(point.width) / 2.0
+            ));
+            continue;
+        }
+        for (width, points) in &group_points(stroke) {
+            widths.insert(*width);
+            body.push_str(&format!(r#"<polyline class="w{width}" points=""#));
+            for (idx, point) in points.iter().enumerate() {
+                if idx > 0 {
+                    body.push(' ');
+                }
+                body.push_str(&format!("{},{}", point.x, point.y));
+            }
+            body.push_str(r#""/>"#);
+        }
+    }
+
+    let mut svg = format!(
+        r#"<svg viewBox="0 0 {width} {height}" xmlns="http://www.w3.org/2000/svg"><style>polyline{{fill:none;stroke:#000;stroke-linecap:round;stroke-linejoin:round}}"#
+    );
+    for width in widths {
+        svg.push_str(&format!(".w{width}{{stroke-width:{width}}}"));
+    }
+    svg.push_str("</style>");
+    svg.push_str(&body);
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Generates a square `svg` document, `size` units on each side, with the area inside `bounds` fit to its center.
+///
+/// A margin of 1/16 of `size` is left on every side. Stroke widths shrink with the drawing, but are never
+/// thinner than one unit, so tiny thumbnails stay legible.
+pub(crate) fn generate_thumbnail(
+    strokes: &[Vec<Point>],
+    bounds: Option<Bounds>,
+    size: u16,
+) -> String {
+    let mut svg = format!(
+        r#"<svg viewBox="0 0 {size} {size}" width="{size}" height="{size}" xmlns="http://www.w3.org/2000/svg"><style>.line{{fill:none;stroke:black;stroke-linecap:round;stroke-linejoin:round}}</style>"#
+    );
+    let Some(bounds) = bounds else {
+        svg.push_str("</svg>");
+        return svg;
+    };
+
+    let size = f64::from(size);
+    let margin = size / 16.0;
+    let available = size - 2.0 * margin;
+    let content_width = f64::from(bounds.max_x - bounds.min_x);
+    let content_height = f64::from(bounds.max_y - bounds.min_y);
+    // A straight line has no extent in one direction, so avoid dividing by zero
+    let scale = available / content_width.max(content_height).max(1.0);
+    let offset_x = margin + (available - content_width * scale) / 2.0;
+    let offset_y = margin + (available - content_height * scale) / 2.0;
+
+    let x = |point: &Point| format_number(offset_x + f64::from(point.x - bounds.min_x) * scale, 1);
+    let y = |point: &Point| format_number(offset_y + f64::from(point.y - bounds.min_y) * scale, 1);
+    let width = |width: u16| (f64::from(width) * scale).max(1.0);
+
+    for stroke in strokes {
+        if let [point] = stroke.as_slice() {
+            svg.push_str(&format!(
+                r#"<circle cx="{}" cy="{}" r="{}"/>"#,
+                x(point),
+                y(point),
+                format_number(width(point.width) / 2.0, 1)
+            ));
+            continue;
+        }
+        for (stroke_width, points) in &group_points(stroke) {
+            let points: Vec<String> = points
+                .iter()
+                .map(|point| format!("{},{}", x(point), y(point)))
+                .collect();
+            svg.push_str(&format!(
+                r#"<polyline class="line" points="{}" stroke-width="{}"/>"#,
+                points.join(" "),
+                format_number(width(*stroke_width), 1)
+            ));
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Builds the guide elements drawn when [`SvgRenderOptions::debug_frame`] is set.
+pub(crate) fn generate_debug_frame(
+    width: u16,
+    height: u16,
+    padding: u16,
+    strokes: &[Vec<Point>],
+) -> Vec<SvgNode> {
+    let rect = |class: &str, width: u16, height: u16, color: &str| {
+        SvgNode::element("rect")
+            .with_attribute("class", class)
+            .with_attribute("x", 0)
+            .with_attribute("y", 0)
+            .with_attribute("width", width)
+            .with_attribute("height", height)
+            .with_attribute("fill", "none")
+            .with_attribute("stroke", color)
+            .with_attribute("stroke-width", "0.5")
+    };
+    let mut guides = vec![
+        rect(
+            "debug-frame",
+            width.saturating_sub(padding),
+            height.saturating_sub(padding),
+            "blue",
+        ),
+        rect("debug-padding", width, height, "red"),
+    ];
+    for point in strokes.iter().filter_map(|stroke| stroke.first()) {
+        guides.push(dot("debug-start", point, "1", "green"));
+    }
+    guides
+}
+
+/// Builds the dots drawn when [`SvgRenderOptions::endpoint_markers`] is set, a start and an end for each stroke with points.
+///
+/// The end is drawn after the start, so a stroke with a single point shows only its red end dot.
+pub(crate) fn generate_endpoint_markers(strokes: &[Vec<Point>]) -> Vec<SvgNode> {
+    let mut markers = vec![];
+    for stroke in strokes {
+        let (Some(first), Some(last)) = (stroke.first(), stroke.last()) else {
+            continue;
+        };
+        markers.push(dot("stroke-start", first, "1.5", "green"));
+        markers.push(dot("stroke-end", last, "1.5", "red"));
+    }
+    markers
+}
+
+/// A `circle` of the given class, radius, and color centered on `point`
+fn dot(class: &str, point: &Point, radius: &str, color: &str) -> SvgNode {
+    SvgNode::element("circle")
+        .with_attribute("class", class)
+        .with_attribute("cx", point.x)
+        .with_attribute("cy", point.y)
+        .with_attribute("r", radius)
+        .with_attribute("fill", color)
+}
+
+/// Distance, in canvas units, between the coordinate ticks of a [forensic overlay](SvgRenderOptions::forensic_overlay)
+pub const FORENSIC_TICK_SPACING: u16 = 50;
+/// Length, in canvas units, of the scale bar of a [forensic overlay](SvgRenderOptions::forensic_overlay), unless the canvas is narrower
+pub const FORENSIC_SCALE_BAR: u16 = 100;
+/// Length, in canvas units, of each coordinate tick
+const FORENSIC_TICK_LENGTH: u16 = 4;
+
+/// Builds the ticks and scale bar drawn when [`SvgRenderOptions::forensic_overlay`] is set.
+///
+/// Ticks are drawn every [`FORENSIC_TICK_SPACING`] units along the top and left edges. The scale bar is [`FORENSIC_SCALE_BAR`]
+/// units long, or half the canvas width if that is shorter, and is labeled with its length.
+pub(crate) fn generate_forensic_overlay(width: u16, height: u16) -> SvgNode {
+    let line = |line: SvgNode, x1: u16, y1: u16, x2: u16, y2: u16| {
+        line.with_attribute("x1", x1)
+            .with_attribute("y1", y1)
+            .with_attribute("x2", x2)
+            .with_attribute("y2", y2)
+    };
+    let tick = SvgNode::element("line").with_attribute("class", "tick");
+    let mut overlay = SvgNode::element("g")
+        .with_attribute("class", "forensic-overlay")
+        .with_attribute("stroke", "#d0021b")
+        .with_attribute("stroke-width", "0.5")
+        .with_attribute("fill", "#d0021b")
+        .with_attribute("font-family", "monospace")
+        .with_attribute("font-size", "6");
+    for x in (FORENSIC_TICK_SPACING..width).step_by(usize::from(FORENSIC_TICK_SPACING)) {
+        overlay.extend([line(tick.clone(), x, 0, x, FORENSIC_TICK_LENGTH)]);
+    }
+    for y in (FORENSIC_TICK_SPACING..height).step_by(usize::from(FORENSIC_TICK_SPACING)) {
+        overlay.extend([line(tick.clone(), 0, y, FORENSIC_TICK_LENGTH, y)]);
+    }
+
+    let length = FORENSIC_SCALE_BAR.min(width / 2);
+    let y = height.saturating_sub(FORENSIC_TICK_LENGTH);
+    let label = SvgNode::element("text")
+        .with_attribute("x", FORENSIC_TICK_LENGTH)
+        .with_attribute("y", y.saturating_sub(2))
+        .with_attribute("stroke", "none")
+        .with_child(SvgNode::Text(format!("{length} units")));
+    overlay.with_child(
+        SvgNode::element("g")
+            .with_attribute("class", "scale-bar")
+            .with_child(line(
+                SvgNode::element("line"),
+                FORENSIC_TICK_LENGTH,
+                y,
+                FORENSIC_TICK_LENGTH + length,
+                y,
+            ))
+            .with_child(label),
+    )
+}
+
+/// Group points along a stroke together by width, returning no groups for an empty stroke
+pub(crate) fn group_points(stroke: &[Point]) -> Vec<(u16, Vec<&Point>)> {
+    let mut groups = vec![];
+    let Some(first) = stroke.first() else {
+        return groups;
+    };
+    let mut curr = first.width;
+    let mut segment = vec![];
+
+    for point in stroke {
+        segment.push(point);
+        if curr != point.width {
+            if segment.len() == 1 {
+                segment.push(point);
+            }
+            groups.push((curr, segment.clone()));
+            segment = vec![point];
+            curr = point.width;
+        }
+    }
+
+    if !segment.is_empty() {
+        segment.push(segment[segment.len() - 1]);
+        groups.push((curr, segment));
+    }
+    groups
+}
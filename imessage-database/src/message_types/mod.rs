@@ -1,17 +1,33 @@
 /*!
  iMessages can represent different kinds of data. This module defines types for those data.
+
+ Without the default `std` feature, only the [`handwriting`] stroke geometry is available.
 */
 
+#[cfg(feature = "std")]
 pub mod app;
+#[cfg(feature = "std")]
 pub mod app_store;
+#[cfg(feature = "std")]
 pub mod collaboration;
+#[cfg(feature = "std")]
 pub mod digital_touch;
+#[cfg(feature = "std")]
 pub mod edited;
+#[cfg(feature = "std")]
 pub mod expressives;
 pub mod handwriting;
+#[cfg(feature = "std")]
 pub mod music;
+#[cfg(feature = "std")]
+pub mod payload;
+#[cfg(feature = "std")]
 pub mod placemark;
+#[cfg(feature = "std")]
 pub mod sticker;
+#[cfg(feature = "std")]
 pub mod text_effects;
+#[cfg(feature = "std")]
 pub mod url;
+#[cfg(feature = "std")]
 pub mod variants;
@@ -0,0 +1,212 @@
+/*!
+ Cheap detection of the format of a message payload, without parsing it.
+
+ Use [`classify()`] to decide which parser to call instead of trying each one in turn.
+*/
+
+/// Bytes for `bplist00`
+const PLIST_MAGIC: &[u8] = b"bplist00";
+/// The `typedstream` header: version `4`, then the length-prefixed signature `streamtyped`
+const TYPEDSTREAM_MAGIC: &[u8] = b"\x04\x0bstreamtyped";
+/// Bytes for `ftyp`, which follow the 4 byte box size at the start of `heic` images
+const FTYP: &[u8] = b"ftyp";
+/// `ftyp` brands used by sticker images
+const STICKER_BRANDS: [&[u8]; 3] = [b"heic", b"heix", b"mif1"];
+
+/// `(field number, wire type)` of the top level fields a handwriting payload may contain
+const HANDWRITING_FIELDS: [(u64, u8); 3] = [
+    // `CreatedAt`, fixed 64 bit
+    (2, 1),
+    // `ID`, length delimited
+    (3, 2),
+    // `Handwriting`, length delimited
+    (4, 2),
+];
+
+/// The format of a message payload, as guessed from its first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// A [handwritten](crate::message_types::handwriting) message protobuf
+    Handwriting,
+    /// A [Digital Touch](crate::message_types::digital_touch) message protobuf
+    DigitalTouch,
+    /// A [sticker](crate::message_types::sticker) image
+    Sticker,
+    /// A binary property list, as used by [app](crate::message_types::app) and [URL](crate::message_types::url) messages
+    Plist,
+    /// A [`typedstream`](crate::util::typedstream), as used by `attributedBody`
+    TypedStream,
+    /// Any other data
+    Unknown,
+}
+
+/// Guess the format of `payload` from its first bytes and, for protobufs, the shape of its top level fields.
+///
+/// This never decompresses or decodes the payload, so a payload that is classified may still fail to parse.
+///
+/// # Example:
+///
+/// ```
+/// use imessage_database::message_types::payload::{PayloadKind, classify};
+///
+/// assert_eq!(classify(b"bplist00..."), PayloadKind::Plist);
+/// assert_eq!(classify(&[0xFF, 0x00]), PayloadKind::Unknown);
+/// ```
+#[must_use]
+pub fn classify(payload: &[u8]) -> PayloadKind {
+    if payload.starts_with(PLIST_MAGIC) {
+        return PayloadKind::Plist;
+    }
+    if payload.starts_with(TYPEDSTREAM_MAGIC) {
+        return PayloadKind::TypedStream;
+    }
+    if payload.get(4..8) == Some(FTYP)
+        && payload
+            .get(8..12)
+            .is_some_and(|brand| STICKER_BRANDS.contains(&brand))
+    {
+        return PayloadKind::Sticker;
+    }
+
+    let Some(fields) = protobuf_fields(payload) else {
+        return PayloadKind::Unknown;
+    };
+    if fields.contains(&(4, 2))
+        && fields
+            .iter()
+            .all(|field| HANDWRITING_FIELDS.contains(field))
+    {
+        PayloadKind::Handwriting
+    } else if fields.first() == Some(&(1, 0)) && fields.contains(&(3, 2)) {
+        // `TouchKind`, then the `TouchPayload`
+        PayloadKind::DigitalTouch
+    } else {
+        PayloadKind::Unknown
+    }
+}
+
+/// Check whether `payload` could be a handwriting message by looking only at its first field tag.
+///
+/// Payloads that fail this check, including empty ones, are not handwriting at all; payloads that pass it but fail to
+/// parse are corrupt. Unlike [`classify()`], this does not walk the rest of the message, so parsers can still report
+/// missing or unknown fields on their own.
+pub(crate) fn starts_like_handwriting(payload: &[u8]) -> bool {
+    read_varint(payload, &mut 0)
+        .is_some_and(|tag| HANDWRITING_FIELDS.contains(&(tag >> 3, (tag & 0x7) as u8)))
+}
+
+/// Walk the top level fields of a protobuf message, returning each `(field number, wire type)`.
+///
+/// Returns `None` if `payload` is empty or is not a well formed protobuf message.
+fn protobuf_fields(payload: &[u8]) -> Option<Vec<(u64, u8)>> {
+    let mut fields = vec![];
+    let mut idx = 0;
+    while idx < payload.len() {
+        let tag = read_varint(payload, &mut idx)?;
+        let wire_type = (tag & 0x7) as u8;
+        let length = match wire_type {
+            0 => {
+                read_varint(payload, &mut idx)?;
+                0
+            }
+            1 => 8,
+            2 => usize::try_from(read_varint(payload, &mut idx)?).ok()?,
+            5 => 4,
+            // Groups are deprecated and never used by message payloads
+            _ => return None,
+        };
+        idx = idx
+            .checked_add(length)
+            .filter(|end| *end <= payload.len())?;
+        fields.push((tag >> 3, wire_type));
+    }
+    (!fields.is_empty()).then_some(fields)
+}
+
+/// Read a base 128 varint starting at `idx`, moving `idx` past it
+fn read_varint(payload: &[u8], idx: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *payload.get(*idx)?;
+        *idx += 1;
+        value |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::current_dir, fs::File, io::Read};
+
+    use crate::message_types::payload::{PayloadKind, classify, starts_like_handwriting};
+
+    fn read(path: &str) -> Vec<u8> {
+        let mut file = File::open(current_dir().unwrap().as_path().join(path)).unwrap();
+        let mut data = vec![];
+        file.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn can_classify_handwriting() {
+        for name in ["handwriting", "hello", "pollock", "test"] {
+            let data = read(&format!("test_data/handwritten_message/{name}.bin"));
+            assert_eq!(classify(&data), PayloadKind::Handwriting, "{name}");
+            assert!(starts_like_handwriting(&data), "{name}");
+        }
+    }
+
+    #[test]
+    fn cant_start_like_handwriting() {
+        assert!(!starts_like_handwriting(&[]));
+        // Bytes from a JPEG header
+        assert!(!starts_like_handwriting(&[0xFF, 0xD8, 0xFF, 0xE0]));
+        // `TouchKind`, the first field of a Digital Touch message
+        assert!(!starts_like_handwriting(&[0x08, 0x01]));
+        assert!(!starts_like_handwriting(&read(
+            "test_data/digital_touch_message/tap.bin"
+        )));
+    }
+
+    #[test]
+    fn can_classify_digital_touch() {
+        for name in ["fireball", "heartbeat", "kiss", "sketch", "tap"] {
+            let data = read(&format!("test_data/digital_touch_message/{name}.bin"));
+            assert_eq!(classify(&data), PayloadKind::DigitalTouch, "{name}");
+        }
+    }
+
+    #[test]
+    fn can_classify_other_formats() {
+        assert_eq!(
+            classify(&read("test_data/stickers/puffy.heic")),
+            PayloadKind::Sticker
+        );
+        assert_eq!(
+            classify(&read("test_data/app_message/Game.plist")),
+            PayloadKind::Plist
+        );
+        assert_eq!(
+            classify(&read("test_data/typedstream/Array")),
+            PayloadKind::TypedStream
+        );
+    }
+
+    #[test]
+    fn cant_classify_random_bytes() {
+        assert_eq!(classify(&[]), PayloadKind::Unknown);
+        assert_eq!(classify(&[0xFF, 0x00, 0x01]), PayloadKind::Unknown);
+        assert_eq!(
+            classify(&[0x9E, 0x3B, 0x71, 0x04, 0xC2, 0x5A, 0xD8, 0x16]),
+            PayloadKind::Unknown
+        );
+        // Starts like a handwriting message, but the length runs past the end
+        assert_eq!(
+            classify(&[0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0x22, 0x40]),
+            PayloadKind::Unknown
+        );
+    }
+}
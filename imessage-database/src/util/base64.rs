@@ -0,0 +1,118 @@
+/*!
+Contains logic for converting data to and from standard `base64` text.
+*/
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PADDING: u8 = b'=';
+
+/// Encode bytes as padded `base64` text using the standard alphabet
+///
+// # Example:
+///
+/// ```
+/// use imessage_database::util::base64::encode;
+///
+/// let text: String = encode(b"hello");
+/// println!("{text}"); // aGVsbG8=
+/// ```
+#[must_use]
+pub fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (idx, byte)| {
+            group | (u32::from(*byte) << (16 - 8 * idx))
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                text.push(ALPHABET[((group >> (18 - 6 * idx)) & 0x3F) as usize] as char);
+            } else {
+                text.push(PADDING as char);
+            }
+        }
+    }
+    text
+}
+
+/// Decode standard `base64` text into bytes, or `None` if the text is not valid `base64`
+///
+/// Whitespace is ignored and padding is optional.
+///
+// # Example:
+///
+/// ```
+/// use imessage_database::util::base64::decode;
+///
+/// let bytes: Option<Vec<u8>> = decode("aGVsbG8=");
+/// println!("{bytes:?}"); // Some([104, 101, 108, 108, 111])
+/// ```
+#[must_use]
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let mut values = vec![];
+    let mut padding = 0;
+    for byte in text.bytes().filter(|byte| !byte.is_ascii_whitespace()) {
+        if byte == PADDING {
+            padding += 1;
+            continue;
+        }
+        // Data after padding is malformed
+        if padding > 0 {
+            return None;
+        }
+        values.push(ALPHABET.iter().position(|symbol| *symbol == byte)? as u32);
+    }
+    if padding > 2 || values.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(values.len() * 3 / 4);
+    for chunk in values.chunks(4) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (idx, value)| {
+            group | (value << (18 - 6 * idx))
+        });
+        for idx in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * idx)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::base64::{decode, encode};
+
+    #[test]
+    fn can_encode_padding() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"h"), "aA==");
+        assert_eq!(encode(b"he"), "aGU=");
+        assert_eq!(encode(b"hel"), "aGVs");
+        assert_eq!(encode(b"hello"), "aGVsbG8=");
+    }
+
+    #[test]
+    fn can_encode_all_symbols() {
+        assert_eq!(encode(&[0xFB, 0xEF, 0xFF]), "++//");
+    }
+
+    #[test]
+    fn can_decode() {
+        assert_eq!(decode("aGVsbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode("aGVsbG8"), Some(b"hello".to_vec()));
+        assert_eq!(decode("aGVs\nbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode(""), Some(vec![]));
+    }
+
+    #[test]
+    fn can_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&bytes)), Some(bytes));
+    }
+
+    #[test]
+    fn cant_decode_invalid() {
+        assert_eq!(decode("aGV*"), None);
+        assert_eq!(decode("a"), None);
+        assert_eq!(decode("aG=V"), None);
+        assert_eq!(decode("aA==="), None);
+    }
+}
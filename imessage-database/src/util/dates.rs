@@ -11,6 +11,20 @@ use crate::error::message::MessageError;
 const SEPARATOR: &str = ", ";
 pub const TIMESTAMP_FACTOR: i64 = 1000000000;
 
+/// Seconds between the unix epoch and Apple's epoch of `2001-01-01 00:00:00` UTC
+///
+/// # Example
+///
+/// ```
+/// use imessage_database::util::dates::APPLE_EPOCH_OFFSET_SECS;
+///
+/// // `created_at` of a handwritten message, in milliseconds since the Apple epoch
+/// let created_at: i64 = 577_234_961_941;
+/// let unix_seconds = created_at / 1000 + APPLE_EPOCH_OFFSET_SECS;
+/// assert_eq!(unix_seconds, 1_555_542_161);
+/// ```
+pub const APPLE_EPOCH_OFFSET_SECS: i64 = 978_307_200;
+
 /// Get the date offset for the iMessage Database
 ///
 /// This offset is used to adjust the unix timestamps stored in the iMessage database
@@ -149,10 +163,15 @@ pub fn readable_diff(
 mod tests {
     use crate::{
         error::message::MessageError,
-        util::dates::{format, readable_diff},
+        util::dates::{APPLE_EPOCH_OFFSET_SECS, format, get_offset, readable_diff},
     };
     use chrono::prelude::*;
 
+    #[test]
+    fn can_get_apple_epoch_offset() {
+        assert_eq!(get_offset(), APPLE_EPOCH_OFFSET_SECS);
+    }
+
     #[test]
     fn can_format_date_single_digit() {
         let date = Local
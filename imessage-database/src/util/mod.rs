@@ -2,6 +2,7 @@
  This module defines common utilities used across table queries.
 */
 
+pub mod base64;
 pub mod bundle_id;
 pub mod dates;
 pub mod dirs;
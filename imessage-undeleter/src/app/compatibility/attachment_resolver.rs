@@ -0,0 +1,120 @@
+/*!
+ Hooks used to relink media to recovered messages whose attachment rows were also deleted.
+*/
+
+use std::path::PathBuf;
+
+use imessage_database::tables::messages::Message;
+
+/// Locates media for a recovered message when its attachments were not saved before it was deleted.
+///
+/// Downstream tools can implement this over a filesystem index of orphaned attachments on disk.
+pub trait AttachmentResolver {
+    /// Find the file on disk for the provided `guid`, if one is known
+    fn resolve(&self, guid: &str) -> Option<PathBuf>;
+}
+
+/// The default [`AttachmentResolver`], which never finds anything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopResolver;
+
+impl AttachmentResolver for NoopResolver {
+    fn resolve(&self, _guid: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Ask `resolver` for a file if `message` has attachments but none of them were `saved`
+pub fn resolve_missing_attachment(
+    resolver: &dyn AttachmentResolver,
+    message: &Message,
+    saved: &[PathBuf],
+) -> Option<PathBuf> {
+    if message.has_attachments() && saved.is_empty() {
+        return resolver.resolve(&message.guid);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::app::{
+        compatibility::attachment_resolver::{
+            AttachmentResolver, NoopResolver, resolve_missing_attachment,
+        },
+        runtime::Config,
+    };
+
+    struct StubResolver;
+
+    impl AttachmentResolver for StubResolver {
+        fn resolve(&self, guid: &str) -> Option<PathBuf> {
+            (guid == "known-guid").then(|| PathBuf::from("/orphaned/known-guid.jpg"))
+        }
+    }
+
+    #[test]
+    fn can_resolve_missing_attachment() {
+        let mut message = Config::fake_message();
+        message.guid = "known-guid".to_string();
+        message.num_attachments = 1;
+
+        assert_eq!(
+            resolve_missing_attachment(&StubResolver, &message, &[]),
+            Some(PathBuf::from("/orphaned/known-guid.jpg"))
+        );
+    }
+
+    #[test]
+    fn cant_resolve_unknown_guid() {
+        let mut message = Config::fake_message();
+        message.guid = "other-guid".to_string();
+        message.num_attachments = 1;
+
+        assert_eq!(
+            resolve_missing_attachment(&StubResolver, &message, &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn cant_resolve_saved_attachment() {
+        let mut message = Config::fake_message();
+        message.guid = "known-guid".to_string();
+        message.num_attachments = 1;
+
+        assert_eq!(
+            resolve_missing_attachment(
+                &StubResolver,
+                &message,
+                &[PathBuf::from("/export/attachments/0")]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn cant_resolve_without_attachments() {
+        let mut message = Config::fake_message();
+        message.guid = "known-guid".to_string();
+
+        assert_eq!(
+            resolve_missing_attachment(&StubResolver, &message, &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn noop_resolver_finds_nothing() {
+        let mut message = Config::fake_message();
+        message.guid = "known-guid".to_string();
+        message.num_attachments = 1;
+
+        assert_eq!(
+            resolve_missing_attachment(&NoopResolver, &message, &[]),
+            None
+        );
+    }
+}
@@ -3,6 +3,7 @@
 */
 
 pub mod attachment_manager;
+pub mod attachment_resolver;
 pub mod backup;
 pub mod converters;
 pub mod models;
@@ -18,6 +18,7 @@ pub enum RuntimeError {
     DatabaseError(TableError),
     BackupError(BackupError),
     FileNameError,
+    InvalidWal(String),
 }
 
 impl Display for RuntimeError {
@@ -28,6 +29,7 @@ impl Display for RuntimeError {
             RuntimeError::DatabaseError(why) => write!(fmt, "{why}"),
             RuntimeError::BackupError(why) => write!(fmt, "{why}"),
             RuntimeError::FileNameError => write!(fmt, "Invalid file name!"),
+            RuntimeError::InvalidWal(why) => write!(fmt, "Invalid WAL file!\n{why}"),
         }
     }
 }
@@ -1,5 +1,6 @@
 pub mod compatibility;
 pub mod error;
 pub mod options;
+pub mod recovery;
 pub mod runtime;
 pub mod sanitizers;
\ No newline at end of file
@@ -18,6 +18,7 @@ use imessage_database::{
 use crate::app::{
     compatibility::attachment_manager::{AttachmentManager, AttachmentManagerMode},
     error::RuntimeError,
+    recovery::scan::ScanCursor,
 };
 
 /// Default export directory name
@@ -33,6 +34,14 @@ pub const OPTION_PLATFORM: &str = "platform";
 pub const OPTION_USE_CALLER_ID: &str = "use-caller-id";
 pub const OPTION_CONVERSATION_FILTER: &str = "conversation-filter";
 pub const OPTION_CLEARTEXT_PASSWORD: &str = "cleartext-password";
+pub const OPTION_SCAN_WAL: &str = "scan-wal";
+pub const OPTION_WAL_PATH: &str = "wal-path";
+pub const OPTION_WAL_BATCH: &str = "wal-batch";
+pub const OPTION_WAL_CURSOR: &str = "wal-cursor";
+pub const OPTION_WAL_CSV: &str = "wal-csv";
+pub const OPTION_WAL_HTML: &str = "wal-html";
+pub const OPTION_WAL_KEEP_DUPLICATES: &str = "wal-keep-duplicates";
+pub const OPTION_WAL_REDACT: &str = "wal-redact";
 
 // Other CLI Text
 pub const SUPPORTED_PLATFORMS: &str = "macOS, iOS";
@@ -60,6 +69,22 @@ pub struct Options {
     pub conversation_filter: Option<String>,
     /// An optional password for encrypted backups
     pub cleartext_password: Option<String>,
+    /// If true, list messages that only exist in the WAL file instead of watching for deletions
+    pub scan_wal: bool,
+    /// Custom path to the database's WAL file
+    pub wal_path: Option<PathBuf>,
+    /// The maximum number of WAL messages to list before stopping
+    pub wal_batch: Option<usize>,
+    /// Where to resume a WAL scan that was stopped after a batch
+    pub wal_cursor: Option<ScanCursor>,
+    /// Where to write a CSV summary of the WAL messages
+    pub wal_csv: Option<PathBuf>,
+    /// Where to write an HTML transcript of the WAL messages
+    pub wal_html: Option<PathBuf>,
+    /// If true, also list WAL messages that still exist in the `message` table
+    pub wal_keep_duplicates: bool,
+    /// If true, replace senders, emails, and phone numbers in WAL exports with pseudonyms
+    pub wal_redact: bool,
 }
 
 impl Options {
@@ -73,6 +98,14 @@ impl Options {
         let platform_type: Option<&String> = args.get_one(OPTION_PLATFORM);
         let conversation_filter: Option<&String> = args.get_one(OPTION_CONVERSATION_FILTER);
         let cleartext_password: Option<&String> = args.get_one(OPTION_CLEARTEXT_PASSWORD);
+        let scan_wal = args.get_flag(OPTION_SCAN_WAL);
+        let wal_path: Option<&String> = args.get_one(OPTION_WAL_PATH);
+        let wal_batch_string: Option<&String> = args.get_one(OPTION_WAL_BATCH);
+        let wal_cursor_string: Option<&String> = args.get_one(OPTION_WAL_CURSOR);
+        let wal_csv: Option<&String> = args.get_one(OPTION_WAL_CSV);
+        let wal_html: Option<&String> = args.get_one(OPTION_WAL_HTML);
+        let wal_keep_duplicates = args.get_flag(OPTION_WAL_KEEP_DUPLICATES);
+        let wal_redact = args.get_flag(OPTION_WAL_REDACT);
 
         let check_last_n_messages: Option<i32> = check_last_n_messages_string.map(|s| s.parse::<i32>().ok()).flatten();
 
@@ -130,6 +163,52 @@ impl Options {
             );
         }
 
+        // Prevent wal_path from being set without scan_wal
+        if wal_path.is_some() && !scan_wal {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "--{OPTION_WAL_PATH} is enabled; it can only be used with --{OPTION_SCAN_WAL}"
+            )));
+        }
+
+        // Prevent batched scans without scan_wal
+        for (option, value) in [
+            (OPTION_WAL_BATCH, wal_batch_string),
+            (OPTION_WAL_CURSOR, wal_cursor_string),
+            (OPTION_WAL_CSV, wal_csv),
+            (OPTION_WAL_HTML, wal_html),
+        ] {
+            if value.is_some() && !scan_wal {
+                return Err(RuntimeError::InvalidOptions(format!(
+                    "--{option} is enabled; it can only be used with --{OPTION_SCAN_WAL}"
+                )));
+            }
+        }
+        for (option, enabled) in [
+            (OPTION_WAL_KEEP_DUPLICATES, wal_keep_duplicates),
+            (OPTION_WAL_REDACT, wal_redact),
+        ] {
+            if enabled && !scan_wal {
+                return Err(RuntimeError::InvalidOptions(format!(
+                    "--{option} is enabled; it can only be used with --{OPTION_SCAN_WAL}"
+                )));
+            }
+        }
+
+        let wal_batch = match wal_batch_string {
+            Some(batch) => match batch.parse::<usize>() {
+                Ok(batch) if batch > 0 => Some(batch),
+                _ => {
+                    return Err(RuntimeError::InvalidOptions(format!(
+                        "--{OPTION_WAL_BATCH} must be a positive number, got `{batch}`"
+                    )));
+                }
+            },
+            None => None,
+        };
+        let wal_cursor = wal_cursor_string
+            .map(|cursor| cursor.parse::<ScanCursor>())
+            .transpose()?;
+
         // Determine the attachment manager mode
         let attachment_manager_mode = AttachmentManagerMode::default();
 
@@ -147,6 +226,14 @@ impl Options {
             platform,
             conversation_filter: conversation_filter.cloned(),
             cleartext_password: cleartext_password.cloned(),
+            scan_wal,
+            wal_path: wal_path.map(PathBuf::from),
+            wal_batch,
+            wal_cursor,
+            wal_csv: wal_csv.map(PathBuf::from),
+            wal_html: wal_html.map(PathBuf::from),
+            wal_keep_duplicates,
+            wal_redact,
         })
     }
 
@@ -240,6 +327,71 @@ fn get_command() -> Command {
                 .display_order(14)
                 .value_name("password"),
         )
+        .arg(
+            Arg::new(OPTION_SCAN_WAL)
+                .short('w')
+                .long(OPTION_SCAN_WAL)
+                .help("List messages that were deleted before the database's WAL file was checkpointed, then exit\n")
+                .action(ArgAction::SetTrue)
+                .display_order(15),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_PATH)
+                .long(OPTION_WAL_PATH)
+                .help(format!("Specify an optional custom path to the database's WAL file\nOnly used with --{OPTION_SCAN_WAL}\nIf omitted, the `-wal` file next to the database is used\n"))
+                .display_order(16)
+                .value_name("path/to/chat.db-wal"),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_BATCH)
+                .long(OPTION_WAL_BATCH)
+                .help(format!("Stop after listing this many WAL messages and print a cursor to resume from\nOnly used with --{OPTION_SCAN_WAL}\n"))
+                .display_order(17)
+                .value_name("count"),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_CURSOR)
+                .long(OPTION_WAL_CURSOR)
+                .help(format!("Resume a WAL scan from a cursor printed by --{OPTION_WAL_BATCH}\nOnly used with --{OPTION_SCAN_WAL}\n"))
+                .display_order(18)
+                .value_name("cursor"),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_CSV)
+                .long(OPTION_WAL_CSV)
+                .help(format!("Also write a CSV summary of the WAL messages to this file
+Only used with --{OPTION_SCAN_WAL}
+"))
+                .display_order(19)
+                .value_name("path/to/deleted.csv"),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_HTML)
+                .long(OPTION_WAL_HTML)
+                .help(format!("Also write an HTML transcript of the WAL messages to this file
+Only used with --{OPTION_SCAN_WAL}
+"))
+                .display_order(20)
+                .value_name("path/to/deleted.html"),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_KEEP_DUPLICATES)
+                .long(OPTION_WAL_KEEP_DUPLICATES)
+                .help(format!("Also list WAL messages that still exist in the database, such as older versions of edited messages
+Only used with --{OPTION_SCAN_WAL}
+"))
+                .action(ArgAction::SetTrue)
+                .display_order(21),
+        )
+        .arg(
+            Arg::new(OPTION_WAL_REDACT)
+                .long(OPTION_WAL_REDACT)
+                .help(format!("Replace senders, emails, and phone numbers in the --{OPTION_WAL_CSV} and --{OPTION_WAL_HTML} exports with pseudonyms such as \"Contact A\"
+Only used with --{OPTION_SCAN_WAL}
+"))
+                .action(ArgAction::SetTrue)
+                .display_order(22),
+        )
 }
 
 /// Parse arguments from the command line
@@ -0,0 +1,57 @@
+/*!
+ Reads the integers SQLite stores in its database and WAL files.
+
+ The formats are documented [here](https://www.sqlite.org/fileformat2.html).
+*/
+
+/// Read a big-endian `u16` at `offset`, if the slice is long enough
+pub fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+/// Read a big-endian `u32` at `offset`, if the slice is long enough
+pub fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Read a SQLite variable-length integer, returning it and the number of bytes it used
+pub fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value: u64 = 0;
+    for (idx, byte) in bytes.iter().enumerate().take(9) {
+        // The ninth byte contributes all 8 of its bits
+        if idx == 8 {
+            return Some(((value << 8) | u64::from(*byte), 9));
+        }
+        value = (value << 7) | u64::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            return Some((value, idx + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::recovery::bytes::{read_u16, read_u32, read_varint};
+
+    #[test]
+    fn can_read_varint() {
+        assert_eq!(read_varint(&[0x05]), Some((5, 1)));
+        assert_eq!(read_varint(&[0x81, 0x00]), Some((128, 2)));
+        assert_eq!(read_varint(&[0xFF; 9]), Some((u64::MAX, 9)));
+        assert_eq!(read_varint(&[0x81]), None);
+    }
+
+    #[test]
+    fn can_read_integers() {
+        let bytes = [0x01, 0x02, 0x03, 0x04, 0x05];
+        assert_eq!(read_u16(&bytes, 3), Some(0x0405));
+        assert_eq!(read_u32(&bytes, 1), Some(0x0203_0405));
+        assert_eq!(read_u16(&bytes, 4), None);
+        assert_eq!(read_u32(&bytes, 2), None);
+    }
+}
@@ -0,0 +1,50 @@
+/*!
+ Reads raw pages from the database file, preferring the newer versions stored in its WAL.
+*/
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::app::{error::RuntimeError, recovery::wal::Wal};
+
+/// Reads pages from a database file that uses the page size of its [`Wal`]
+pub struct DatabasePages {
+    file: File,
+    page_size: usize,
+}
+
+impl DatabasePages {
+    /// Open the database file at `db_path`
+    pub fn open(db_path: &Path, wal: &Wal) -> Result<Self, RuntimeError> {
+        Ok(DatabasePages {
+            file: File::open(db_path)?,
+            page_size: wal.page_size,
+        })
+    }
+
+    /// The committed version of `page_number`, read from `wal` if it was changed since the last checkpoint
+    pub fn committed(&mut self, wal: &mut Wal, page_number: u32) -> Option<Vec<u8>> {
+        wal.committed_page(page_number)
+            .or_else(|| self.stored(page_number))
+    }
+
+    /// The version of `page_number` that was current when the transaction holding WAL frame `idx` ended.
+    ///
+    /// Pages that were not written to `wal` by then are read from the database file.
+    pub fn as_of(&mut self, wal: &mut Wal, idx: usize, page_number: u32) -> Option<Vec<u8>> {
+        wal.page_as_of(idx, page_number)
+            .or_else(|| self.stored(page_number))
+    }
+
+    /// `page_number` as stored in the database file, or `None` if the file is too short
+    fn stored(&mut self, page_number: u32) -> Option<Vec<u8>> {
+        let offset = u64::from(page_number.checked_sub(1)?) * self.page_size as u64;
+        let mut page = vec![0; self.page_size];
+        self.file.seek(SeekFrom::Start(offset)).ok()?;
+        self.file.read_exact(&mut page).ok()?;
+        Some(page)
+    }
+}
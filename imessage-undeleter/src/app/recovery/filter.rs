@@ -0,0 +1,133 @@
+/*!
+ Filters that limit which deleted messages a scan recovers.
+*/
+
+use std::collections::BTreeSet;
+
+use rusqlite::{Connection, params_from_iter};
+
+use imessage_database::error::table::TableError;
+
+use crate::app::error::RuntimeError;
+
+/// Represents filter configurations for a recovery scan.
+///
+/// Dates use the same format as the `date` column of the `message` table.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecoveryFilter {
+    /// Only recover messages written at or after this date
+    pub after: Option<i64>,
+    /// Only recover messages written before this date
+    pub before: Option<i64>,
+    /// Only recover messages sent by these `handle` table `ROWID`s
+    pub handle_ids: Option<BTreeSet<i32>>,
+    /// Only recover messages sent by these phone numbers or emails
+    ///
+    /// These are resolved using the `handle` table, so they cannot match senders whose `handle` row was also deleted.
+    pub handles: Option<BTreeSet<String>>,
+}
+
+impl RecoveryFilter {
+    /// Build a SQL `WHERE` clause that applies the date bounds to the `message` table
+    pub fn date_clause(&self) -> String {
+        let conditions: Vec<String> = [
+            self.after.map(|after| format!("date >= {after}")),
+            self.before.map(|before| format!("date < {before}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        }
+    }
+
+    /// Resolve [`RecoveryFilter::handles`] into [`RecoveryFilter::handle_ids`] using the `handle` table
+    pub fn resolve(&self, db: &Connection) -> Result<RecoveryFilter, RuntimeError> {
+        let Some(handles) = &self.handles else {
+            return Ok(self.clone());
+        };
+
+        let placeholders = vec!["?"; handles.len()].join(", ");
+        let resolved = db
+            .prepare(&format!(
+                "SELECT ROWID FROM handle WHERE id IN ({placeholders})"
+            ))
+            .and_then(|mut statement| {
+                statement
+                    .query_map(params_from_iter(handles.iter()), |row| row.get(0))?
+                    .collect::<Result<BTreeSet<i32>, _>>()
+            })
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Handle(err)))?;
+
+        // If no handles matched, the filter must still exclude everything
+        let mut handle_ids = self.handle_ids.clone().unwrap_or_default();
+        handle_ids.extend(resolved);
+
+        Ok(RecoveryFilter {
+            after: self.after,
+            before: self.before,
+            handle_ids: Some(handle_ids),
+            handles: None,
+        })
+    }
+
+    /// Determine if a message with the given `date` is inside the date bounds
+    pub fn matches_date(&self, date: i64) -> bool {
+        self.after.is_none_or(|after| date >= after)
+            && self.before.is_none_or(|before| date < before)
+    }
+
+    /// Determine if a message from `handle_id` is allowed by [`RecoveryFilter::handle_ids`]
+    pub fn matches_handle(&self, handle_id: Option<i32>) -> bool {
+        match &self.handle_ids {
+            Some(handle_ids) => handle_id.is_some_and(|id| handle_ids.contains(&id)),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::app::recovery::filter::RecoveryFilter;
+
+    #[test]
+    fn can_match_everything() {
+        let filter = RecoveryFilter::default();
+        assert!(filter.matches_date(i64::MIN));
+        assert!(filter.matches_handle(None));
+        assert_eq!(filter.date_clause(), "");
+    }
+
+    #[test]
+    fn can_match_date_bounds() {
+        let filter = RecoveryFilter {
+            after: Some(100),
+            before: Some(200),
+            ..Default::default()
+        };
+
+        assert!(!filter.matches_date(99));
+        assert!(filter.matches_date(100));
+        assert!(filter.matches_date(199));
+        assert!(!filter.matches_date(200));
+        assert_eq!(filter.date_clause(), " WHERE date >= 100 AND date < 200");
+    }
+
+    #[test]
+    fn can_match_handles() {
+        let filter = RecoveryFilter {
+            handle_ids: Some(BTreeSet::from([1, 2])),
+            ..Default::default()
+        };
+
+        assert!(filter.matches_handle(Some(2)));
+        assert!(!filter.matches_handle(Some(3)));
+        assert!(!filter.matches_handle(None));
+    }
+}
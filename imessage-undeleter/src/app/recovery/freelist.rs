@@ -0,0 +1,44 @@
+/*!
+ Finds the pages on a database's freelist, where SQLite keeps pages that no table uses anymore.
+
+ Freeing a page does not erase it, so rows on a freelist page were deleted along with the rest of the page.
+
+ The format is documented [here](https://www.sqlite.org/fileformat2.html#the_freelist).
+*/
+
+use std::collections::HashSet;
+
+use crate::app::recovery::{bytes::read_u32, database::DatabasePages, wal::Wal};
+
+/// Offset in the database header of the first freelist trunk page number
+const FIRST_TRUNK_OFFSET: usize = 32;
+/// Offset in the database header of the number of freelist pages
+const FREELIST_COUNT_OFFSET: usize = 36;
+
+/// Find every freelist page in the committed database, including the trunk pages.
+///
+/// Pages are read from `wal` if they were changed since the last checkpoint, otherwise from the database file.
+/// A freelist that cannot be read, such as one in a truncated file, stops the walk early instead of failing.
+pub fn freelist_pages(db: &mut DatabasePages, wal: &mut Wal) -> HashSet<u32> {
+    let mut pages = HashSet::new();
+    let Some(header) = db.committed(wal, 1) else {
+        return pages;
+    };
+    let (Some(mut trunk), Some(count)) = (
+        read_u32(&header, FIRST_TRUNK_OFFSET),
+        read_u32(&header, FREELIST_COUNT_OFFSET),
+    ) else {
+        return pages;
+    };
+
+    // A damaged freelist could point back at a trunk that was already read
+    while trunk != 0 && pages.len() < count as usize && pages.insert(trunk) {
+        let Some(page) = db.committed(wal, trunk) else {
+            break;
+        };
+        let leaves = read_u32(&page, 4).unwrap_or_default() as usize;
+        pages.extend((0..leaves).map_while(|idx| read_u32(&page, 8 + idx * 4)));
+        trunk = read_u32(&page, 0).unwrap_or_default();
+    }
+    pages
+}
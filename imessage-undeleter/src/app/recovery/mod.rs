@@ -0,0 +1,13 @@
+/*!
+ Contains data structures and routines used to find messages that were deleted from the database.
+*/
+
+pub mod bytes;
+pub mod database;
+pub mod filter;
+pub mod freelist;
+pub mod models;
+pub mod page;
+pub mod report;
+pub mod scan;
+pub mod wal;
@@ -0,0 +1,678 @@
+/*!
+ Data structures for messages recovered from outside of the live `message` table.
+*/
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use rusqlite::Connection;
+
+use imessage_database::{
+    error::table::TableError,
+    tables::table::ME,
+    util::{
+        bundle_id::parse_balloon_bundle_id, streamtyped, typedstream::parser::TypedStreamReader,
+    },
+};
+
+use crate::app::{
+    error::RuntimeError,
+    recovery::{
+        filter::RecoveryFilter,
+        page::{Row, Value},
+    },
+};
+
+/// A message that no longer exists in the live `message` table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveredMessage {
+    /// The message's `ROWID`
+    pub rowid: i64,
+    /// The message's unique identifier
+    pub guid: String,
+    /// The message body, read from `attributedBody` if `text` is empty
+    pub text: Option<String>,
+    /// The `ROWID` of the sender in the `handle` table
+    pub handle_id: Option<i32>,
+    /// Who sent the message, resolved from [`RecoveredMessage::handle_id`] when the `handle` row still exists
+    pub sender: Sender,
+    /// The date the message was written to the database
+    pub date: i64,
+    /// `true` if the database owner sent the message
+    pub is_from_me: bool,
+    /// The `ROWID` of the chat the message belonged to, if it could be found
+    pub chat_id: Option<i32>,
+    /// The raw `associated_message_guid` of a reaction, sticker, or edit, pointing at its target message
+    pub associated_message_guid: Option<String>,
+    /// The kind of association, such as `2000` for a "Loved" tapback
+    pub associated_message_type: Option<i32>,
+    /// The message [`RecoveredMessage::associated_message_guid`] points at, if it could be found
+    pub parent: Option<ParentMessage>,
+    /// Why the message is considered deleted, which hints at how confident the recovery is
+    pub reason: DeletionReason,
+    /// The `ROWID` of the live message with the same `ROWID` or `guid`, if the message was not actually deleted
+    ///
+    /// These duplicates are only recovered when [`ScanOptions::keep_duplicates`](crate::app::recovery::scan::ScanOptions::keep_duplicates) is set.
+    pub live_rowid: Option<i64>,
+    /// The group chat change this message announces, if it is a system message
+    pub group_change: Option<GroupChange>,
+    /// The participant [`RecoveredMessage::group_change`] adds or removes, resolved like [`RecoveredMessage::sender`]
+    pub participant: Option<Sender>,
+    /// `true` if the message had attachments, from `cache_has_attachments`
+    pub has_attachments: bool,
+    /// The app that sent the message, if it is an app message
+    pub balloon_bundle_id: Option<String>,
+    /// The drawing of a handwritten message; other apps' payloads are not kept
+    pub payload_data: Option<Vec<u8>>,
+    /// `true` if the end of the row was on overflow pages that could not be read, so later columns, such as the
+    /// text or drawing, may be cut short or missing
+    pub truncated: bool,
+}
+
+/// The bundle ID of the app that sends handwritten messages
+const HANDWRITING_BUNDLE_ID: &str = "com.apple.Handwriting.HandwritingProvider";
+
+impl RecoveredMessage {
+    /// `true` if the message is a handwritten message, whether or not its drawing was recovered
+    pub fn is_handwriting(&self) -> bool {
+        is_handwriting(self.balloon_bundle_id.as_deref())
+    }
+
+    /// The `guid` of the message this one is associated with, without the part index prefix.
+    ///
+    /// See [`Message::clean_associated_guid`](imessage_database::tables::messages::Message::clean_associated_guid)
+    /// for the formats this field can take.
+    pub fn associated_guid(&self) -> Option<&str> {
+        let guid = self.associated_message_guid.as_deref()?;
+        if let Some(rest) = guid.strip_prefix("p:") {
+            rest.split_once('/').map(|(_, guid)| guid)
+        } else if let Some(rest) = guid.strip_prefix("bp:") {
+            Some(rest)
+        } else {
+            Some(guid)
+        }
+        .filter(|guid| !guid.is_empty())
+    }
+}
+
+/// Where the target of a recovered message's association was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParentMessage {
+    /// The target still exists in the `message` table, with this `ROWID`
+    Live(i64),
+    /// The target was also deleted, and was recovered with this `ROWID`
+    Recovered(i64),
+}
+
+/// Who sent a recovered message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sender {
+    /// The database owner
+    Me,
+    /// The phone number or email of the sender's `handle` row
+    Known(String),
+    /// A sender whose `handle` row could not be found, such as one that was also deleted, by `handle` `ROWID`
+    Unknown(i64),
+}
+
+impl Display for Sender {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Sender::Me => write!(fmt, "{ME}"),
+            Sender::Known(id) => write!(fmt, "{id}"),
+            Sender::Unknown(handle_id) => write!(fmt, "Handle {handle_id}"),
+        }
+    }
+}
+
+/// Why a recovered message is considered deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletionReason {
+    /// A committed version of the row exists, but the row is gone from the live `message` table
+    AbsentFromMessages,
+    /// The row was only written by a transaction that has not committed, so it may still reappear in the `message` table
+    FoundInWalOnly,
+    /// The newest version of the row is on a page the database has freed for reuse
+    FreelistPage,
+    /// The message's `guid` was recorded in a table of deleted messages
+    Tombstoned,
+}
+
+impl Display for DeletionReason {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            DeletionReason::AbsentFromMessages => write!(fmt, "absent_from_messages"),
+            DeletionReason::FoundInWalOnly => write!(fmt, "found_in_wal_only"),
+            DeletionReason::FreelistPage => write!(fmt, "freelist_page"),
+            DeletionReason::Tombstoned => write!(fmt, "tombstoned"),
+        }
+    }
+}
+
+/// A change to a group chat announced by a system message, such as "X added Y"
+///
+/// This mirrors [`GroupAction`](imessage_database::tables::messages::models::GroupAction), but owns its data
+/// because recovered rows are not [`Message`](imessage_database::tables::messages::Message)s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupChange {
+    /// The sender added the participant with this `handle` `ROWID`
+    ParticipantAdded(i32),
+    /// The sender removed the participant with this `handle` `ROWID`
+    ParticipantRemoved(i32),
+    /// The sender renamed the group
+    NameChange(String),
+    /// The sender left the group
+    ParticipantLeft,
+    /// The sender set a new group photo
+    GroupIconChanged,
+    /// The sender removed the group photo
+    GroupIconRemoved,
+}
+
+impl GroupChange {
+    /// Determine the change from a row's `item_type`, `group_action_type`, `other_handle`, and `group_title`
+    fn from_columns(
+        item_type: i64,
+        group_action_type: i64,
+        other_handle: Option<i32>,
+        group_title: Option<&str>,
+    ) -> Option<Self> {
+        match (item_type, group_action_type, other_handle, group_title) {
+            (1, 0, Some(who), _) => Some(Self::ParticipantAdded(who)),
+            (1, 1, Some(who), _) => Some(Self::ParticipantRemoved(who)),
+            (2, _, _, Some(name)) => Some(Self::NameChange(name.to_string())),
+            (3, 0, _, _) => Some(Self::ParticipantLeft),
+            (3, 1, _, _) => Some(Self::GroupIconChanged),
+            (3, 2, _, _) => Some(Self::GroupIconRemoved),
+            _ => None,
+        }
+    }
+
+    /// Describe the change as a system line, given the name of the sender and a way to name other participants
+    pub fn describe<'a>(&self, actor: &str, name: impl Fn(i32) -> &'a str) -> String {
+        match self {
+            GroupChange::ParticipantAdded(who) => {
+                format!("{actor} added {} to the conversation.", name(*who))
+            }
+            GroupChange::ParticipantRemoved(who) => {
+                format!("{actor} removed {} from the conversation.", name(*who))
+            }
+            GroupChange::NameChange(title) => format!("{actor} named the conversation {title}"),
+            GroupChange::ParticipantLeft => format!("{actor} left the conversation."),
+            GroupChange::GroupIconChanged => format!("{actor} changed the group photo."),
+            GroupChange::GroupIconRemoved => format!("{actor} removed the group photo."),
+        }
+    }
+}
+
+/// Positions of the `message` table columns that are read from recovered rows
+#[derive(Debug, PartialEq, Eq)]
+pub struct MessageColumns {
+    /// Total number of columns in the table
+    pub count: usize,
+    pub guid: usize,
+    pub text: usize,
+    pub handle_id: usize,
+    pub attributed_body: usize,
+    pub date: usize,
+    pub is_from_me: usize,
+    /// Older databases do not have association columns
+    pub associated_message_guid: Option<usize>,
+    pub associated_message_type: Option<usize>,
+    /// Older databases do not have group change columns
+    pub item_type: Option<usize>,
+    pub group_action_type: Option<usize>,
+    pub other_handle: Option<usize>,
+    pub group_title: Option<usize>,
+    /// Older databases do not have app message columns
+    pub cache_has_attachments: Option<usize>,
+    pub balloon_bundle_id: Option<usize>,
+    pub payload_data: Option<usize>,
+}
+
+impl MessageColumns {
+    /// Read the `message` table layout from the database
+    pub fn from_connection(db: &Connection) -> Result<Self, RuntimeError> {
+        let mut statement = db
+            .prepare("SELECT name FROM pragma_table_info('message')")
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+        let names = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        let position = |column: &str| {
+            names.iter().position(|name| name == column).ok_or_else(|| {
+                RuntimeError::InvalidOptions(format!(
+                    "The `message` table has no `{column}` column!"
+                ))
+            })
+        };
+
+        Ok(MessageColumns {
+            count: names.len(),
+            guid: position("guid")?,
+            text: position("text")?,
+            handle_id: position("handle_id")?,
+            attributed_body: position("attributedBody")?,
+            date: position("date")?,
+            is_from_me: position("is_from_me")?,
+            associated_message_guid: position("associated_message_guid").ok(),
+            associated_message_type: position("associated_message_type").ok(),
+            item_type: position("item_type").ok(),
+            group_action_type: position("group_action_type").ok(),
+            other_handle: position("other_handle").ok(),
+            group_title: position("group_title").ok(),
+            cache_has_attachments: position("cache_has_attachments").ok(),
+            balloon_bundle_id: position("balloon_bundle_id").ok(),
+            payload_data: position("payload_data").ok(),
+        })
+    }
+
+    /// The highest column position that is read from a row
+    fn last(&self) -> usize {
+        [
+            self.guid,
+            self.text,
+            self.handle_id,
+            self.attributed_body,
+            self.date,
+            self.is_from_me,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Build a message from a raw row, if the row's layout matches the `message` table and it passes `filter`.
+    ///
+    /// Pages do not record which table they belong to, so rows are matched by shape: the row
+    /// must have enough columns and each column read must hold the expected type.
+    ///
+    /// `filter` is checked before the message text is decoded, and its `handles` must already be resolved.
+    pub fn read(&self, row: &Row, filter: &RecoveryFilter) -> Option<RecoveredMessage> {
+        let values = &row.values;
+        // Rows written before a column was added have fewer values than the table
+        if values.len() <= self.last() || values.len() > self.count {
+            return None;
+        }
+
+        let Value::Text(guid) = &values[self.guid] else {
+            return None;
+        };
+        let Value::Integer(date) = values[self.date] else {
+            return None;
+        };
+        let Value::Integer(is_from_me) = values[self.is_from_me] else {
+            return None;
+        };
+        let handle_id = match values[self.handle_id] {
+            Value::Integer(handle_id) => i32::try_from(handle_id).ok(),
+            Value::Null => None,
+            _ => return None,
+        };
+
+        if guid.is_empty() || !filter.matches_date(date) || !filter.matches_handle(handle_id) {
+            return None;
+        }
+
+        let text = match &values[self.text] {
+            Value::Text(text) if !text.is_empty() => Some(text.clone()),
+            Value::Text(_) | Value::Null => match &values[self.attributed_body] {
+                Value::Blob(body) => body_text(body),
+                _ => None,
+            },
+            _ => return None,
+        };
+
+        // Rows written before a column was added do not store it, so it is read as `NULL`
+        let optional = |column: Option<usize>| column.and_then(|column| values.get(column));
+        let associated_message_guid = match optional(self.associated_message_guid) {
+            Some(Value::Text(guid)) if !guid.is_empty() => Some(guid.clone()),
+            Some(Value::Text(_) | Value::Null) | None => None,
+            Some(_) => return None,
+        };
+        let associated_message_type = match optional(self.associated_message_type) {
+            Some(Value::Integer(kind)) => i32::try_from(*kind).ok(),
+            Some(Value::Null) | None => None,
+            Some(_) => return None,
+        };
+        let integer = |column: Option<usize>| match optional(column) {
+            Some(Value::Integer(value)) => Some(Some(*value)),
+            Some(Value::Null) | None => Some(None),
+            Some(_) => None,
+        };
+        let other_handle = integer(self.other_handle)?.and_then(|id| i32::try_from(id).ok());
+        let group_title = match optional(self.group_title) {
+            Some(Value::Text(title)) => Some(title.as_str()),
+            Some(Value::Null) | None => None,
+            Some(_) => return None,
+        };
+        let group_change = GroupChange::from_columns(
+            integer(self.item_type)?.unwrap_or_default(),
+            integer(self.group_action_type)?.unwrap_or_default(),
+            other_handle,
+            group_title,
+        );
+        // Set once the scan has looked up the `handle` row
+        let participant = match group_change {
+            Some(GroupChange::ParticipantAdded(who) | GroupChange::ParticipantRemoved(who)) => {
+                Some(Sender::Unknown(i64::from(who)))
+            }
+            _ => None,
+        };
+        let has_attachments = integer(self.cache_has_attachments)?.is_some_and(|flag| flag != 0);
+        let balloon_bundle_id = match optional(self.balloon_bundle_id) {
+            Some(Value::Text(id)) if !id.is_empty() => Some(id.clone()),
+            Some(Value::Text(_) | Value::Null) | None => None,
+            Some(_) => return None,
+        };
+        let payload_data = match optional(self.payload_data) {
+            Some(Value::Blob(payload)) if is_handwriting(balloon_bundle_id.as_deref()) => {
+                Some(payload.clone())
+            }
+            Some(Value::Blob(_) | Value::Null) | None => None,
+            Some(_) => return None,
+        };
+
+        Some(RecoveredMessage {
+            rowid: row.rowid,
+            guid: guid.clone(),
+            text,
+            handle_id,
+            // Set once the scan has looked up the `handle` row
+            sender: if is_from_me != 0 {
+                Sender::Me
+            } else {
+                Sender::Unknown(handle_id.map(i64::from).unwrap_or_default())
+            },
+            date,
+            is_from_me: is_from_me != 0,
+            chat_id: None,
+            associated_message_guid,
+            associated_message_type,
+            parent: None,
+            // Set once the scan knows where the row was found
+            reason: DeletionReason::AbsentFromMessages,
+            live_rowid: None,
+            group_change,
+            participant,
+            has_attachments,
+            balloon_bundle_id,
+            payload_data,
+            truncated: row.truncated,
+        })
+    }
+}
+
+/// `true` if `balloon_bundle_id` belongs to the handwriting app
+fn is_handwriting(balloon_bundle_id: Option<&str>) -> bool {
+    parse_balloon_bundle_id(balloon_bundle_id) == Some(HANDWRITING_BUNDLE_ID)
+}
+
+/// Extract the message text from `attributedBody` data
+fn body_text(body: &[u8]) -> Option<String> {
+    let mut typedstream = TypedStreamReader::from(body);
+    typedstream
+        .parse()
+        .ok()
+        .and_then(|items| {
+            items
+                .first()
+                .and_then(|item| item.as_nsstring())
+                .map(String::from)
+        })
+        .or_else(|| streamtyped::parse(body.to_vec()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::recovery::{
+        filter::RecoveryFilter,
+        models::{DeletionReason, GroupChange, MessageColumns, RecoveredMessage, Sender},
+        page::{Row, Value},
+    };
+
+    fn columns() -> MessageColumns {
+        MessageColumns {
+            count: 7,
+            guid: 1,
+            text: 2,
+            handle_id: 3,
+            attributed_body: 4,
+            date: 5,
+            is_from_me: 6,
+            associated_message_guid: None,
+            associated_message_type: None,
+            item_type: None,
+            group_action_type: None,
+            other_handle: None,
+            group_title: None,
+            cache_has_attachments: None,
+            balloon_bundle_id: None,
+            payload_data: None,
+        }
+    }
+
+    #[test]
+    fn can_read_message_row() {
+        let row = Row {
+            rowid: 3,
+            values: vec![
+                Value::Null,
+                Value::Text("guid".to_string()),
+                Value::Text("hello".to_string()),
+                Value::Integer(2),
+                Value::Null,
+                Value::Integer(100),
+                Value::Integer(1),
+            ],
+            truncated: false,
+        };
+
+        assert_eq!(
+            columns().read(&row, &RecoveryFilter::default()),
+            Some(RecoveredMessage {
+                rowid: 3,
+                guid: "guid".to_string(),
+                text: Some("hello".to_string()),
+                handle_id: Some(2),
+                sender: Sender::Me,
+                date: 100,
+                is_from_me: true,
+                chat_id: None,
+                associated_message_guid: None,
+                associated_message_type: None,
+                parent: None,
+                reason: DeletionReason::AbsentFromMessages,
+                live_rowid: None,
+                group_change: None,
+                participant: None,
+                has_attachments: false,
+                balloon_bundle_id: None,
+                payload_data: None,
+                truncated: false,
+            })
+        );
+    }
+
+    #[test]
+    fn can_read_associated_message() {
+        let columns = MessageColumns {
+            count: 9,
+            associated_message_guid: Some(7),
+            associated_message_type: Some(8),
+            ..columns()
+        };
+        let mut row = Row {
+            rowid: 4,
+            values: vec![
+                Value::Null,
+                Value::Text("guid".to_string()),
+                Value::Text("Liked “hello”".to_string()),
+                Value::Integer(2),
+                Value::Null,
+                Value::Integer(100),
+                Value::Integer(0),
+                Value::Text("p:0/parent".to_string()),
+                Value::Integer(2001),
+            ],
+            truncated: false,
+        };
+
+        let message = columns.read(&row, &RecoveryFilter::default()).unwrap();
+        assert_eq!(
+            message.associated_message_guid.as_deref(),
+            Some("p:0/parent")
+        );
+        assert_eq!(message.associated_message_type, Some(2001));
+        assert_eq!(message.associated_guid(), Some("parent"));
+
+        // Rows written before the columns were added are shorter than the table
+        row.values.truncate(7);
+        let message = columns.read(&row, &RecoveryFilter::default()).unwrap();
+        assert_eq!(message.associated_message_guid, None);
+        assert_eq!(message.associated_message_type, None);
+    }
+
+    #[test]
+    fn can_read_group_change() {
+        let columns = MessageColumns {
+            count: 11,
+            item_type: Some(7),
+            group_action_type: Some(8),
+            other_handle: Some(9),
+            group_title: Some(10),
+            ..columns()
+        };
+        let row = |item_type: i64, group_action_type: i64, other_handle: Value, title: Value| Row {
+            rowid: 5,
+            values: vec![
+                Value::Null,
+                Value::Text("guid".to_string()),
+                Value::Null,
+                Value::Integer(1),
+                Value::Null,
+                Value::Integer(100),
+                Value::Integer(0),
+                Value::Integer(item_type),
+                Value::Integer(group_action_type),
+                other_handle,
+                title,
+            ],
+            truncated: false,
+        };
+        let read = |row: Row| {
+            columns
+                .read(&row, &RecoveryFilter::default())
+                .unwrap()
+                .group_change
+        };
+
+        assert_eq!(
+            read(row(1, 0, Value::Integer(2), Value::Null)),
+            Some(GroupChange::ParticipantAdded(2))
+        );
+        assert_eq!(
+            read(row(1, 1, Value::Integer(2), Value::Null)),
+            Some(GroupChange::ParticipantRemoved(2))
+        );
+        assert_eq!(
+            read(row(2, 0, Value::Null, Value::Text("Friends".to_string()))),
+            Some(GroupChange::NameChange("Friends".to_string()))
+        );
+        assert_eq!(
+            read(row(3, 0, Value::Null, Value::Null)),
+            Some(GroupChange::ParticipantLeft)
+        );
+        assert_eq!(read(row(0, 0, Value::Integer(0), Value::Null)), None);
+    }
+
+    #[test]
+    fn can_describe_group_change() {
+        let name = |handle_id| if handle_id == 2 { "Bob" } else { "Unknown" };
+
+        assert_eq!(
+            GroupChange::ParticipantAdded(2).describe("Alice", name),
+            "Alice added Bob to the conversation."
+        );
+        assert_eq!(
+            GroupChange::ParticipantRemoved(2).describe("Alice", name),
+            "Alice removed Bob from the conversation."
+        );
+        assert_eq!(
+            GroupChange::NameChange("Friends".to_string()).describe("Alice", name),
+            "Alice named the conversation Friends"
+        );
+        assert_eq!(
+            GroupChange::GroupIconRemoved.describe("Alice", name),
+            "Alice removed the group photo."
+        );
+    }
+
+    #[test]
+    fn can_clean_associated_guid() {
+        let mut message = RecoveredMessage {
+            rowid: 1,
+            guid: "guid".to_string(),
+            text: None,
+            handle_id: None,
+            sender: Sender::Unknown(0),
+            date: 0,
+            is_from_me: false,
+            chat_id: None,
+            associated_message_guid: None,
+            associated_message_type: None,
+            parent: None,
+            reason: DeletionReason::AbsentFromMessages,
+            live_rowid: None,
+            group_change: None,
+            participant: None,
+            has_attachments: false,
+            balloon_bundle_id: None,
+            payload_data: None,
+            truncated: false,
+        };
+        assert_eq!(message.associated_guid(), None);
+
+        for (raw, clean) in [
+            ("p:2/parent", Some("parent")),
+            ("bp:parent", Some("parent")),
+            ("parent", Some("parent")),
+            ("p:2", None),
+        ] {
+            message.associated_message_guid = Some(raw.to_string());
+            assert_eq!(message.associated_guid(), clean);
+        }
+    }
+
+    #[test]
+    fn cant_read_short_row() {
+        let row = Row {
+            rowid: 3,
+            values: vec![Value::Null, Value::Text("guid".to_string())],
+            truncated: false,
+        };
+
+        assert_eq!(columns().read(&row, &RecoveryFilter::default()), None);
+    }
+
+    #[test]
+    fn cant_read_mismatched_row() {
+        let row = Row {
+            rowid: 3,
+            values: vec![
+                Value::Null,
+                Value::Integer(5),
+                Value::Text("hello".to_string()),
+                Value::Integer(2),
+                Value::Null,
+                Value::Integer(100),
+                Value::Integer(1),
+            ],
+            truncated: false,
+        };
+
+        assert_eq!(columns().read(&row, &RecoveryFilter::default()), None);
+    }
+}
@@ -0,0 +1,371 @@
+/*!
+ Decodes rows stored in SQLite b-tree pages.
+
+ The format is documented [here](https://www.sqlite.org/fileformat2.html#b_tree_pages).
+*/
+
+use std::collections::HashSet;
+
+use crate::app::recovery::bytes::{read_u16, read_u32, read_varint};
+
+/// Size of the database header that precedes the b-tree header on page 1
+const DB_HEADER_SIZE: usize = 100;
+/// Page type flag for leaf pages of a table b-tree
+const TABLE_LEAF: u8 = 0x0D;
+/// Page type flag for interior pages of a table b-tree
+const TABLE_INTERIOR: u8 = 0x05;
+/// Size of the b-tree header on leaf pages
+const LEAF_HEADER_SIZE: usize = 8;
+/// Size of the b-tree header on interior pages, which ends with the right-most child pointer
+const INTERIOR_HEADER_SIZE: usize = 12;
+/// Serial types used by integers that are `1`, `2`, `3`, `4`, `6`, and `8` bytes long
+const INTEGER_SIZES: [usize; 6] = [1, 2, 3, 4, 6, 8];
+
+/// A single column value in a SQLite record
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// A row stored in a table b-tree leaf page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// The row's `ROWID`
+    pub rowid: i64,
+    /// The row's column values, in table order
+    pub values: Vec<Value>,
+    /// `true` if the end of the row was on overflow pages that could not be read, so the value that crosses onto them
+    /// is cut short and every later value is `NULL`
+    pub truncated: bool,
+}
+
+/// Decode every row referenced by the cell pointer array of a table leaf page.
+///
+/// Rows too large to fit on the page continue on a chain of overflow pages, which are fetched with `read_page`.
+/// If the chain cannot be read, the part of the row stored on the page is kept and the row is marked as
+/// [`truncated`](Row::truncated).
+///
+/// Pages that are not table leaves yield no rows, and cells that point outside of the page are skipped.
+pub fn table_leaf_rows(
+    page: &[u8],
+    page_number: u32,
+    mut read_page: impl FnMut(u32) -> Option<Vec<u8>>,
+) -> Vec<Row> {
+    let header = if page_number == 1 { DB_HEADER_SIZE } else { 0 };
+    if page.get(header) != Some(&TABLE_LEAF) {
+        return vec![];
+    }
+
+    let cell_count = read_u16(page, header + 3).unwrap_or(0);
+    (0..usize::from(cell_count))
+        .filter_map(|idx| read_u16(page, header + LEAF_HEADER_SIZE + 2 * idx))
+        .filter_map(|pointer| read_row(page, usize::from(pointer), &mut read_page))
+        .collect()
+}
+
+/// List the pages an interior page of a table b-tree points at, or nothing if it is not a table interior page
+pub fn table_child_pages(page: &[u8], page_number: u32) -> Vec<u32> {
+    let header = if page_number == 1 { DB_HEADER_SIZE } else { 0 };
+    if page.get(header) != Some(&TABLE_INTERIOR) {
+        return vec![];
+    }
+
+    let cell_count = read_u16(page, header + 3).unwrap_or(0);
+    // Each cell starts with the page number of its left child
+    (0..usize::from(cell_count))
+        .filter_map(|idx| read_u16(page, header + INTERIOR_HEADER_SIZE + 2 * idx))
+        .filter_map(|pointer| read_u32(page, usize::from(pointer)))
+        .chain(read_u32(page, header + 8))
+        .collect()
+}
+
+/// Decode the table leaf cell at `offset`
+fn read_row(
+    page: &[u8],
+    offset: usize,
+    read_page: &mut impl FnMut(u32) -> Option<Vec<u8>>,
+) -> Option<Row> {
+    let (payload_size, size_len) = read_varint(page.get(offset..)?)?;
+    let (rowid, rowid_len) = read_varint(page.get(offset + size_len..)?)?;
+    let start = offset + size_len + rowid_len;
+
+    let payload_size = usize::try_from(payload_size).ok()?;
+    let local_size = local_payload_size(page.len(), payload_size)?;
+    let local = page.get(start..start + local_size)?;
+    if local_size == payload_size {
+        return Some(Row {
+            rowid: rowid as i64,
+            values: decode_record(local)?,
+            truncated: false,
+        });
+    }
+
+    // The rest of the payload is on a chain of overflow pages, starting with the one after the local part
+    let overflow = read_u32(page, start + local_size)
+        .and_then(|first| read_overflow(local, payload_size, first, read_page));
+    Some(match overflow {
+        Some(payload) => Row {
+            rowid: rowid as i64,
+            values: decode_record(&payload)?,
+            truncated: false,
+        },
+        None => Row {
+            rowid: rowid as i64,
+            values: decode_values(local, true)?,
+            truncated: true,
+        },
+    })
+}
+
+/// The number of bytes of a `payload_size` byte payload that are stored on a table leaf page of `page_size` bytes
+fn local_payload_size(page_size: usize, payload_size: usize) -> Option<usize> {
+    let max_local = page_size.checked_sub(35)?;
+    if payload_size <= max_local {
+        return Some(payload_size);
+    }
+    let min_local = ((page_size - 12) * 32 / 255).checked_sub(23)?;
+    let local = min_local + (payload_size - min_local) % (page_size - 4);
+    Some(if local <= max_local { local } else { min_local })
+}
+
+/// Append the overflow chain starting at `next` to `local`, or `None` if a page in the chain cannot be read
+fn read_overflow(
+    local: &[u8],
+    payload_size: usize,
+    mut next: u32,
+    read_page: &mut impl FnMut(u32) -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let mut payload = local.to_vec();
+    // A damaged chain could point back at a page that was already read
+    let mut seen = HashSet::new();
+    while payload.len() < payload_size {
+        if next == 0 || !seen.insert(next) {
+            return None;
+        }
+        // Each overflow page starts with the number of the next one
+        let page = read_page(next)?;
+        let content = page.get(4..).filter(|content| !content.is_empty())?;
+        let length = content.len().min(payload_size - payload.len());
+        payload.extend_from_slice(&content[..length]);
+        next = read_u32(&page, 0)?;
+    }
+    Some(payload)
+}
+
+/// Decode a record into its column values
+pub fn decode_record(payload: &[u8]) -> Option<Vec<Value>> {
+    decode_values(payload, false)
+}
+
+/// Decode a record into its column values.
+///
+/// If `truncated`, the body may end early: a text or blob value that crosses the end is cut short, and every value
+/// after it is `NULL`. The header must still be complete.
+fn decode_values(payload: &[u8], truncated: bool) -> Option<Vec<Value>> {
+    let (header_size, mut header_pos) = read_varint(payload)?;
+    let header_size = usize::try_from(header_size).ok()?;
+    let mut body_pos = header_size;
+    let mut values = vec![];
+
+    while header_pos < header_size {
+        let (serial_type, serial_len) = read_varint(payload.get(header_pos..header_size)?)?;
+        header_pos += serial_len;
+
+        let body = payload.get(body_pos..).unwrap_or_default();
+        match decode_value(serial_type, body) {
+            Some((value, value_len)) => {
+                body_pos += value_len;
+                values.push(value);
+            }
+            None if truncated && !matches!(serial_type, 10 | 11) => {
+                body_pos = payload.len();
+                values.push(match serial_type {
+                    _ if body.is_empty() => Value::Null,
+                    blob if blob >= 12 && blob % 2 == 0 => Value::Blob(body.to_vec()),
+                    text if text >= 13 => Value::Text(String::from_utf8_lossy(body).to_string()),
+                    _ => Value::Null,
+                });
+            }
+            None => return None,
+        }
+    }
+
+    Some(values)
+}
+
+/// Decode a single value from the start of `body`, returning it and the number of bytes it used
+fn decode_value(serial_type: u64, body: &[u8]) -> Option<(Value, usize)> {
+    match serial_type {
+        0 => Some((Value::Null, 0)),
+        1..=6 => {
+            let len = INTEGER_SIZES[serial_type as usize - 1];
+            let bytes = body.get(..len)?;
+            // Big-endian two's complement, so sign extend from the first byte
+            let value = bytes[1..]
+                .iter()
+                .fold(i64::from(bytes[0] as i8), |acc, byte| {
+                    (acc << 8) | i64::from(*byte)
+                });
+            Some((Value::Integer(value), len))
+        }
+        7 => Some((
+            Value::Real(f64::from_be_bytes(body.get(..8)?.try_into().ok()?)),
+            8,
+        )),
+        8 => Some((Value::Integer(0), 0)),
+        9 => Some((Value::Integer(1), 0)),
+        // 10 and 11 are reserved for internal use and never appear in well-formed records
+        10 | 11 => None,
+        blob if blob % 2 == 0 => {
+            let len = usize::try_from((blob - 12) / 2).ok()?;
+            Some((Value::Blob(body.get(..len)?.to_vec()), len))
+        }
+        text => {
+            let len = usize::try_from((text - 13) / 2).ok()?;
+            Some((
+                Value::Text(String::from_utf8_lossy(body.get(..len)?).to_string()),
+                len,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::recovery::page::{Value, decode_record, table_child_pages, table_leaf_rows};
+
+    /// A 512 byte table leaf holding one 994 byte text value under `ROWID` 7, whose overflow chain starts on page 3
+    fn overflowing_page() -> (Vec<u8>, Vec<u8>) {
+        let text: Vec<u8> = (0..994).map(|idx| b'a' + (idx % 26) as u8).collect();
+        // Header: size 3, then serial type 13 + 2 * 994 as a 2 byte varint
+        let mut payload = vec![3, 0x8F, 0x51];
+        payload.extend_from_slice(&text);
+
+        // 997 bytes do not fit in the 477 allowed on the page, so only the minimum of 39 is kept
+        let mut page = vec![0; 512];
+        page[0] = 0x0D;
+        page[3..5].copy_from_slice(&1u16.to_be_bytes());
+        page[8..10].copy_from_slice(&400u16.to_be_bytes());
+        // Payload size, then `ROWID`
+        page[400..403].copy_from_slice(&[0x87, 0x65, 7]);
+        page[403..442].copy_from_slice(&payload[..39]);
+        page[442..446].copy_from_slice(&3u32.to_be_bytes());
+        (page, payload)
+    }
+
+    #[test]
+    fn can_decode_record() {
+        // Header: size 5, NULL, 1 byte int, literal 1, 3 byte text
+        let payload = [5, 0, 1, 9, 19, 0xFE, b'a', b'b', b'c'];
+        assert_eq!(
+            decode_record(&payload),
+            Some(vec![
+                Value::Null,
+                Value::Integer(-2),
+                Value::Integer(1),
+                Value::Text("abc".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn cant_decode_truncated_record() {
+        let payload = [3, 0, 19, b'a'];
+        assert_eq!(decode_record(&payload), None);
+    }
+
+    #[test]
+    fn can_read_leaf_rows() {
+        let mut page = vec![0; 512];
+        page[0] = 0x0D;
+        page[3..5].copy_from_slice(&1u16.to_be_bytes());
+        page[8..10].copy_from_slice(&500u16.to_be_bytes());
+        // Payload size 4, rowid 7, header size 2, 2 byte int
+        page[500..506].copy_from_slice(&[4, 7, 2, 2, 0x01, 0x00]);
+
+        let rows = table_leaf_rows(&page, 2, |_| None);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].rowid, 7);
+        assert_eq!(rows[0].values, vec![Value::Integer(256)]);
+        assert!(!rows[0].truncated);
+    }
+
+    #[test]
+    fn can_read_overflowing_rows() {
+        let (page, payload) = overflowing_page();
+        // The remaining 958 bytes fill page 3 and continue on page 4
+        let mut overflow = vec![0; 512];
+        overflow[..4].copy_from_slice(&4u32.to_be_bytes());
+        overflow[4..].copy_from_slice(&payload[39..547]);
+        let mut last = vec![0; 512];
+        last[4..454].copy_from_slice(&payload[547..]);
+
+        let rows = table_leaf_rows(&page, 2, |page_number| match page_number {
+            3 => Some(overflow.clone()),
+            4 => Some(last.clone()),
+            _ => None,
+        });
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].rowid, 7);
+        assert_eq!(
+            rows[0].values,
+            vec![Value::Text(
+                String::from_utf8(payload[3..].to_vec()).unwrap()
+            )]
+        );
+        assert!(!rows[0].truncated);
+    }
+
+    #[test]
+    fn can_read_local_part_of_overflowing_rows() {
+        let (page, payload) = overflowing_page();
+
+        let rows = table_leaf_rows(&page, 2, |_| None);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0].values,
+            vec![Value::Text(
+                String::from_utf8(payload[3..39].to_vec()).unwrap()
+            )]
+        );
+        assert!(rows[0].truncated);
+    }
+
+    #[test]
+    fn cant_follow_looping_overflow_chain() {
+        let (page, _) = overflowing_page();
+        // Page 3 points at itself
+        let mut overflow = vec![0; 512];
+        overflow[..4].copy_from_slice(&3u32.to_be_bytes());
+
+        let rows = table_leaf_rows(&page, 2, |_| Some(overflow.clone()));
+        assert!(rows[0].truncated);
+    }
+
+    #[test]
+    fn cant_read_interior_page() {
+        let mut page = vec![0; 512];
+        page[0] = 0x05;
+        assert!(table_leaf_rows(&page, 2, |_| None).is_empty());
+    }
+
+    #[test]
+    fn can_list_child_pages() {
+        let mut page = vec![0; 512];
+        page[0] = 0x05;
+        page[3..5].copy_from_slice(&2u16.to_be_bytes());
+        page[8..12].copy_from_slice(&9u32.to_be_bytes());
+        page[12..16].copy_from_slice(&[0x01, 0xF0, 0x01, 0xF8]);
+        // Left child, then the largest key in it
+        page[496..501].copy_from_slice(&[0, 0, 0, 4, 10]);
+        page[504..509].copy_from_slice(&[0, 0, 0, 6, 20]);
+
+        assert_eq!(table_child_pages(&page, 2), vec![4, 6, 9]);
+        page[0] = 0x0D;
+        assert!(table_child_pages(&page, 2).is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+/*!
+ Summaries of recoverable messages that can be shown before anything is exported.
+*/
+
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+use imessage_database::message_types::handwriting::HandwrittenMessage;
+
+use crate::app::recovery::models::{
+    DeletionReason, GroupChange, ParentMessage, RecoveredMessage, Sender,
+};
+
+/// Number of characters of message text kept in a [`MessagePreview`]
+pub const PREVIEW_LENGTH: usize = 50;
+
+/// What a recoverable message contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// A regular message, which may have attachments
+    Text,
+    /// A handwritten message
+    Handwriting,
+    /// A message sent by any other app
+    App,
+    /// A tapback, sticker, or other message attached to another message
+    Reaction,
+    /// A change to a group chat's participants or details
+    GroupChange,
+}
+
+impl MessageKind {
+    /// Determine what `message` contains, checking the most specific kinds first
+    pub fn from_message(message: &RecoveredMessage) -> Self {
+        if message.group_change.is_some() {
+            MessageKind::GroupChange
+        } else if message.is_handwriting() {
+            MessageKind::Handwriting
+        } else if message.balloon_bundle_id.is_some() {
+            MessageKind::App
+        } else if message.associated_guid().is_some() {
+            MessageKind::Reaction
+        } else {
+            MessageKind::Text
+        }
+    }
+}
+
+impl Display for MessageKind {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            MessageKind::Text => write!(fmt, "text"),
+            MessageKind::Handwriting => write!(fmt, "handwriting"),
+            MessageKind::App => write!(fmt, "app"),
+            MessageKind::Reaction => write!(fmt, "reaction"),
+            MessageKind::GroupChange => write!(fmt, "group_change"),
+        }
+    }
+}
+
+/// A short description of a single recoverable message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessagePreview {
+    /// The message's `ROWID`
+    pub rowid: i64,
+    /// The message's unique identifier
+    pub guid: String,
+    /// The `ROWID` of the chat the message belonged to, if it could be found
+    pub chat_id: Option<i32>,
+    /// The `ROWID` of the sender in the `handle` table
+    pub handle_id: Option<i32>,
+    /// Who sent the message
+    pub sender: Sender,
+    /// `true` if the database owner sent the message
+    pub is_from_me: bool,
+    /// The date the message was written to the database
+    pub date: i64,
+    /// What the message contains
+    pub kind: MessageKind,
+    /// `true` if the message had attachments
+    pub has_attachments: bool,
+    /// The start of the message text, or the number of strokes in a handwritten message
+    pub preview: String,
+    /// The message this one reacts to or edits, if it could be found
+    pub parent: Option<ParentMessage>,
+    /// Why the message is considered deleted
+    pub reason: DeletionReason,
+    /// The `ROWID` of the live message this one duplicates, if it was not actually deleted
+    pub live_rowid: Option<i64>,
+    /// The group chat change this message announces, shown instead of the text
+    pub group_change: Option<GroupChange>,
+    /// `true` if part of the message could not be recovered from its overflow pages
+    pub truncated: bool,
+}
+
+impl MessagePreview {
+    fn from_message(message: &RecoveredMessage) -> Self {
+        MessagePreview {
+            rowid: message.rowid,
+            guid: message.guid.clone(),
+            chat_id: message.chat_id,
+            handle_id: message.handle_id,
+            sender: message.sender.clone(),
+            is_from_me: message.is_from_me,
+            date: message.date,
+            kind: MessageKind::from_message(message),
+            has_attachments: message.has_attachments,
+            preview: if message.is_handwriting() {
+                stroke_count(message)
+            } else {
+                preview(message.text.as_deref().unwrap_or_default())
+            },
+            parent: message.parent,
+            reason: message.reason,
+            live_rowid: message.live_rowid,
+            group_change: message.group_change.clone(),
+            truncated: message.truncated,
+        }
+    }
+}
+
+/// A summary of the messages a scan can recover
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// The number of recoverable messages
+    pub count: usize,
+    /// The earliest and latest message dates, if any messages were found
+    pub date_range: Option<(i64, i64)>,
+    /// The chats that recoverable messages belonged to
+    pub chats: BTreeSet<i32>,
+    /// A preview of each recoverable message
+    pub messages: Vec<MessagePreview>,
+}
+
+impl RecoveryReport {
+    /// Summarize a set of recovered messages
+    pub fn from_messages(messages: &[RecoveredMessage]) -> Self {
+        let date_range = messages
+            .iter()
+            .map(|message| message.date)
+            .fold(None, |range, date| match range {
+                Some((first, last)) => Some((date.min(first), date.max(last))),
+                None => Some((date, date)),
+            });
+
+        RecoveryReport {
+            count: messages.len(),
+            date_range,
+            chats: messages
+                .iter()
+                .filter_map(|message| message.chat_id)
+                .collect(),
+            messages: messages.iter().map(MessagePreview::from_message).collect(),
+        }
+    }
+}
+
+/// Describe the number of strokes in a handwritten message, if its drawing was recovered
+fn stroke_count(message: &RecoveredMessage) -> String {
+    match message
+        .payload_data
+        .as_deref()
+        .map(HandwrittenMessage::from_payload)
+    {
+        Some(Ok(drawing)) if drawing.strokes.len() == 1 => "1 stroke".to_string(),
+        Some(Ok(drawing)) => format!("{} strokes", drawing.strokes.len()),
+        Some(Err(_)) | None => String::new(),
+    }
+}
+
+/// Truncate `text` to [`PREVIEW_LENGTH`] characters
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::app::recovery::{
+        models::{DeletionReason, RecoveredMessage, Sender},
+        report::{PREVIEW_LENGTH, RecoveryReport},
+    };
+
+    fn message(rowid: i64, date: i64, chat_id: Option<i32>, text: &str) -> RecoveredMessage {
+        RecoveredMessage {
+            rowid,
+            guid: format!("guid-{rowid}"),
+            text: Some(text.to_string()),
+            handle_id: Some(1),
+            sender: Sender::Unknown(1),
+            date,
+            is_from_me: false,
+            chat_id,
+            associated_message_guid: None,
+            associated_message_type: None,
+            parent: None,
+            reason: DeletionReason::AbsentFromMessages,
+            live_rowid: None,
+            group_change: None,
+            participant: None,
+            has_attachments: false,
+            balloon_bundle_id: None,
+            payload_data: None,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn can_summarize_messages() {
+        let messages = vec![
+            message(1, 300, Some(2), "Hello"),
+            message(2, 100, Some(5), "World"),
+            message(3, 200, None, "Orphaned"),
+        ];
+
+        let report = RecoveryReport::from_messages(&messages);
+        assert_eq!(report.count, 3);
+        assert_eq!(report.date_range, Some((100, 300)));
+        assert_eq!(report.chats, BTreeSet::from([2, 5]));
+        assert_eq!(report.messages.len(), 3);
+        assert_eq!(report.messages[1].guid, "guid-2");
+        assert_eq!(report.messages[1].preview, "World");
+    }
+
+    #[test]
+    fn can_summarize_nothing() {
+        assert_eq!(
+            RecoveryReport::from_messages(&[]),
+            RecoveryReport::default()
+        );
+    }
+
+    #[test]
+    fn can_truncate_preview() {
+        let text = "🫠".repeat(PREVIEW_LENGTH + 1);
+        let report = RecoveryReport::from_messages(&[message(1, 1, None, &text)]);
+
+        assert_eq!(
+            report.messages[0].preview,
+            format!("{}…", "🫠".repeat(PREVIEW_LENGTH))
+        );
+    }
+
+    #[test]
+    fn can_report_repeatedly() {
+        let messages = vec![message(1, 1, Some(1), "Hello")];
+
+        assert_eq!(
+            RecoveryReport::from_messages(&messages),
+            RecoveryReport::from_messages(&messages)
+        );
+    }
+}
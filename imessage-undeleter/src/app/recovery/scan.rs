@@ -0,0 +1,1318 @@
+/*!
+ Entry points that search the database's sidecar files for deleted messages.
+*/
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::{Display, Formatter, Result as FmtResult},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rusqlite::{Connection, OptionalExtension};
+
+use imessage_database::{error::table::TableError, tables::table::get_connection};
+
+use crate::app::{
+    error::RuntimeError,
+    recovery::{
+        database::DatabasePages,
+        filter::RecoveryFilter,
+        freelist::freelist_pages,
+        models::{DeletionReason, MessageColumns, ParentMessage, RecoveredMessage, Sender},
+        page::{Value, table_child_pages, table_leaf_rows},
+        wal::Wal,
+    },
+};
+
+/// Options that change how a scan treats the rows it finds, as opposed to which messages [`RecoveryFilter`] allows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Also recover rows whose message still exists in the live `message` table, such as older versions of edited
+    /// messages or rows left on reused pages, instead of dropping them
+    pub keep_duplicates: bool,
+}
+
+/// Tables that record the `guid` of deleted messages so the deletion can sync to other devices
+const TOMBSTONE_TABLES: [&str; 2] = ["deleted_messages", "sync_deleted_messages"];
+/// The table that links messages to the chats they belong to
+const CHAT_MESSAGE_JOIN: &str = "chat_message_join";
+
+/// Where a batched scan stopped, so it can be resumed with [`scan_wal_batch`].
+///
+/// Cursors can be saved with [`Display`] and restored with [`FromStr`], so a scan can be resumed after
+/// the process restarts. A cursor is tied to the WAL file it was created for and is rejected once that
+/// file is checkpointed and reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanCursor {
+    /// The WAL frame and `ROWID` of the last message returned, and the salt of the WAL file it was read from,
+    /// or `None` at the start of a scan
+    position: Option<(usize, i64, (u32, u32))>,
+}
+
+impl Display for ScanCursor {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        match self.position {
+            Some((frame, rowid, (salt_1, salt_2))) => {
+                write!(fmt, "{frame}.{rowid}.{salt_1:08x}.{salt_2:08x}")
+            }
+            None => write!(fmt, "start"),
+        }
+    }
+}
+
+impl FromStr for ScanCursor {
+    type Err = RuntimeError;
+
+    fn from_str(cursor: &str) -> Result<Self, Self::Err> {
+        if cursor == "start" {
+            return Ok(ScanCursor::default());
+        }
+
+        let invalid =
+            || RuntimeError::InvalidOptions(format!("`{cursor}` is not a valid scan cursor!"));
+        let mut parts = cursor.split('.');
+        let (Some(frame), Some(rowid), Some(salt_1), Some(salt_2), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return Err(invalid());
+        };
+        Ok(ScanCursor {
+            position: Some((
+                frame.parse().map_err(|_| invalid())?,
+                rowid.parse().map_err(|_| invalid())?,
+                (
+                    u32::from_str_radix(salt_1, 16).map_err(|_| invalid())?,
+                    u32::from_str_radix(salt_2, 16).map_err(|_| invalid())?,
+                ),
+            )),
+        })
+    }
+}
+
+/// Get the default location of the WAL file for the database at `db_path`
+pub fn default_wal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-wal");
+    PathBuf::from(path)
+}
+
+/// Find messages that exist in the WAL file but not in the committed database.
+///
+/// Every page version in the WAL is decoded, including versions superseded by later writes, so
+/// rows deleted before the WAL was checkpointed can still be found. If a row appears in several
+/// versions, the newest one is used.
+///
+/// Messages are sorted by chat, then date, then `ROWID`, so repeated scans produce the same output.
+/// Messages whose chat could not be found are sorted first.
+///
+/// If `wal_path` is `None`, the file next to `db_path` is used; if that file does not exist, nothing is found.
+///
+/// Only messages that pass `filter` are recovered, and `options` decides whether messages that are still live are kept.
+///
+/// Reactions, stickers, and edits are linked to the message they point at, which may be live or also recovered.
+///
+/// Each message is tagged with the [`DeletionReason`] it is considered deleted for.
+pub fn scan_wal(
+    db_path: &Path,
+    wal_path: Option<&Path>,
+    filter: &RecoveryFilter,
+    options: &ScanOptions,
+) -> Result<Vec<RecoveredMessage>, RuntimeError> {
+    let mut messages =
+        scan_wal_iter(db_path, wal_path, filter, options)?.collect::<Result<Vec<_>, _>>()?;
+    messages.sort_by_key(|message| (message.chat_id, message.date, message.rowid));
+    Ok(messages)
+}
+
+/// Find the same messages as [`scan_wal`], yielding each one as soon as it is decoded.
+///
+/// Messages are yielded in the order their newest version appears in the WAL file, then by `ROWID`, instead of
+/// sorted, so a caller can write them out as they arrive without holding every message in memory.
+///
+/// Opening the scan still reads every frame once to find the newest version of each message, keeping the
+/// frame headers and a small index of each message's `ROWID`, `guid`, and frame. Page contents and message
+/// bodies are not kept, so memory grows with the number of frames and deleted messages, not the size of the WAL.
+pub fn scan_wal_iter(
+    db_path: &Path,
+    wal_path: Option<&Path>,
+    filter: &RecoveryFilter,
+    options: &ScanOptions,
+) -> Result<RecoveredMessages, RuntimeError> {
+    Ok(RecoveredMessages {
+        scan: Scan::open(db_path, wal_path, filter, options, &ScanCursor::default())?,
+    })
+}
+
+/// Find up to `limit` of the messages [`scan_wal`] would find, starting after `cursor`.
+///
+/// Batches are taken in the order [`scan_wal_iter`] yields messages and each batch is sorted like [`scan_wal`].
+/// Alongside the batch, this returns the cursor to pass to the next call, or `None` once every message has been
+/// returned. Running every batch finds the same messages as a single [`scan_wal`], each exactly once.
+///
+/// A resumed scan does not read the frames before the cursor again, except for the `chat_message_join` pages
+/// needed to link messages to their chats. Because of that, a message is only linked to a recovered
+/// [`ParentMessage`] from the same or a later batch, and a message is only known to have committed if a
+/// committed version of it was written at or after the cursor.
+pub fn scan_wal_batch(
+    db_path: &Path,
+    wal_path: Option<&Path>,
+    filter: &RecoveryFilter,
+    options: &ScanOptions,
+    cursor: &ScanCursor,
+    limit: usize,
+) -> Result<(Vec<RecoveredMessage>, Option<ScanCursor>), RuntimeError> {
+    let mut messages = RecoveredMessages {
+        scan: Scan::open(db_path, wal_path, filter, options, cursor)?,
+    };
+
+    // Every batch must make progress, or the scan could never finish
+    let mut batch = messages
+        .by_ref()
+        .take(limit.max(1))
+        .collect::<Result<Vec<_>, _>>()?;
+    let next = match (&messages.scan, batch.last()) {
+        (Some(scan), Some(last)) if !(scan.pending.is_empty() && scan.frames.is_empty()) => {
+            Some(ScanCursor {
+                position: Some((scan.newest[&last.rowid], last.rowid, scan.wal.salt)),
+            })
+        }
+        _ => None,
+    };
+    batch.sort_by_key(|message| (message.chat_id, message.date, message.rowid));
+
+    Ok((batch, next))
+}
+
+/// An iterator over the messages found by [`scan_wal_iter`].
+///
+/// Each frame holding the newest version of a message is read from the WAL file again and decoded as the
+/// iterator reaches it; frames without one are never read a second time.
+pub struct RecoveredMessages {
+    /// `None` if there was no WAL file to scan
+    scan: Option<Scan>,
+}
+
+impl Iterator for RecoveredMessages {
+    type Item = Result<RecoveredMessage, RuntimeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let scan = self.scan.as_mut()?;
+        loop {
+            if let Some(message) = scan.pending.pop_front() {
+                return Some(scan.finish(message));
+            }
+
+            let idx = scan.frames.pop_front()?;
+            let data = match scan.wal.read_frame(idx) {
+                Ok(data) => data,
+                Err(why) => return Some(Err(why)),
+            };
+            let page_number = scan.wal.frames[idx].page_number;
+            let overflow = |page_number| scan.pages.as_of(&mut scan.wal, idx, page_number);
+            for row in table_leaf_rows(&data, page_number, overflow) {
+                if let Some(message) = scan.columns.read(&row, &scan.filter) {
+                    // Older versions of the row are skipped, and live rows never have a newest version
+                    if scan.newest.get(&message.rowid) == Some(&idx) {
+                        scan.pending.push_back(message);
+                    }
+                }
+            }
+            // A cursor records the last `ROWID` returned from its frame, so each frame is yielded in `ROWID` order
+            scan.pending
+                .make_contiguous()
+                .sort_by_key(|message| message.rowid);
+        }
+    }
+}
+
+/// The state of a scan in progress
+struct Scan {
+    db: Connection,
+    wal: Wal,
+    /// Used to read overflow pages that are not in the WAL
+    pages: DatabasePages,
+    columns: MessageColumns,
+    filter: RecoveryFilter,
+    /// Map of recovered message ROWID to the index of the frame holding its newest version
+    newest: HashMap<i64, usize>,
+    /// Map of recovered message GUID to ROWID, used to link messages to recovered parents
+    guids: HashMap<String, i64>,
+    /// Map of recovered message ROWID to the ROWID of the live message it duplicates
+    live: HashMap<i64, i64>,
+    /// Recovered message ROWIDs that were only found in frames that have not committed
+    uncommitted: HashSet<i64>,
+    /// Pages on the committed database's freelist
+    freelist: HashSet<u32>,
+    /// GUIDs recorded in [`TOMBSTONE_TABLES`]
+    tombstones: HashSet<String>,
+    /// Map of (message ROWID, message date) to chat ROWID, from `chat_message_join` rows
+    chats: HashMap<(i64, i64), i32>,
+    /// The frames holding the newest version of at least one message that have not been decoded yet, in file order
+    frames: VecDeque<usize>,
+    /// Messages decoded from the last frame that have not been yielded yet
+    pending: VecDeque<RecoveredMessage>,
+}
+
+impl Scan {
+    /// Read the WAL file and find where the newest version of each deleted message after `cursor` is.
+    ///
+    /// Returns `None` if `wal_path` is `None` and the default WAL file does not exist.
+    fn open(
+        db_path: &Path,
+        wal_path: Option<&Path>,
+        filter: &RecoveryFilter,
+        options: &ScanOptions,
+        cursor: &ScanCursor,
+    ) -> Result<Option<Self>, RuntimeError> {
+        let wal_path = match wal_path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = default_wal_path(db_path);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                path
+            }
+        };
+
+        let db = get_connection(db_path)?;
+        let columns = MessageColumns::from_connection(&db)?;
+        let filter = filter.resolve(&db)?;
+        // Live rows outside of the date range can never match, so they do not need to be loaded
+        let live_rowids = db
+            .prepare(&format!(
+                "SELECT ROWID FROM message{}",
+                filter.date_clause()
+            ))
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<HashSet<i64>, _>>()
+            })
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+        let mut wal = Wal::from_path(&wal_path)?;
+        let resume = match cursor.position {
+            Some((_, _, salt)) if salt != wal.salt => {
+                return Err(RuntimeError::InvalidOptions(format!(
+                    "The scan cursor `{cursor}` was created for a different WAL file!"
+                )));
+            }
+            Some((frame, rowid, _)) => Some((frame, rowid)),
+            None => None,
+        };
+        let last_commit = wal.last_commit();
+        let mut pages = DatabasePages::open(db_path, &wal)?;
+        // Pages do not record which table they belong to, and any table can have rows of three integers
+        let join_pages = table_pages(&db, &mut pages, &mut wal, CHAT_MESSAGE_JOIN)?;
+
+        // Map of message ROWID to the index of the frame holding its newest version, and its GUID
+        let mut newest: HashMap<i64, (usize, String)> = HashMap::new();
+        let mut committed: HashSet<i64> = HashSet::new();
+        let mut chats: HashMap<(i64, i64), i32> = HashMap::new();
+
+        // Frames are read one at a time, so only the index of each message is kept
+        for idx in 0..wal.frames.len() {
+            let (page_number, salt) = (wal.frames[idx].page_number, wal.frames[idx].salt);
+            // Messages before the cursor were already returned, but their chats may still be needed
+            let before_cursor = resume.is_some_and(|(frame, _)| idx < frame);
+            if before_cursor && !join_pages.contains(&page_number) {
+                continue;
+            }
+            let data = wal.read_frame(idx)?;
+            // Frames from before the last checkpoint were all committed
+            let is_committed = salt != wal.salt || last_commit.is_some_and(|last| idx <= last);
+            let overflow = |page_number| pages.as_of(&mut wal, idx, page_number);
+            for row in table_leaf_rows(&data, page_number, overflow) {
+                if let Some(message) = columns.read(&row, &filter) {
+                    if before_cursor {
+                        continue;
+                    }
+                    if is_committed {
+                        committed.insert(message.rowid);
+                    }
+                    newest.insert(message.rowid, (idx, message.guid));
+                } else if !join_pages.contains(&page_number) {
+                    continue;
+                } else if let [
+                    Value::Integer(chat_id),
+                    Value::Integer(message_id),
+                    Value::Integer(message_date),
+                ] = row.values[..]
+                {
+                    if let Ok(chat_id) = i32::try_from(chat_id) {
+                        chats.insert((message_id, message_date), chat_id);
+                    }
+                }
+            }
+        }
+
+        // Messages in the cursor's own frame were yielded in `ROWID` order, up to and including the cursor's
+        if let Some((frame, after)) = resume {
+            newest.retain(|rowid, (idx, _)| *idx > frame || *rowid > after);
+        }
+
+        // Rows can still be live under the same ROWID, such as older versions of edited messages, or under
+        // the same GUID, such as messages that were written again
+        let mut live = HashMap::new();
+        for (rowid, (_, guid)) in &newest {
+            let live_rowid = if live_rowids.contains(rowid) {
+                Some(*rowid)
+            } else {
+                live_guid_rowid(&db, guid)?
+            };
+            if let Some(live_rowid) = live_rowid {
+                live.insert(*rowid, live_rowid);
+            }
+        }
+        if !options.keep_duplicates {
+            newest.retain(|rowid, _| !live.contains_key(rowid));
+        }
+
+        let guids = newest
+            .iter()
+            .map(|(rowid, (_, guid))| (guid.clone(), *rowid))
+            .collect();
+        let uncommitted = newest
+            .keys()
+            .filter(|rowid| !committed.contains(rowid))
+            .copied()
+            .collect();
+        let freelist = freelist_pages(&mut pages, &mut wal);
+        let frames = newest
+            .values()
+            .map(|(idx, _)| *idx)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let tombstones = tombstones(&db)?;
+        Ok(Some(Scan {
+            db,
+            wal,
+            pages,
+            columns,
+            filter,
+            newest: newest
+                .into_iter()
+                .map(|(rowid, (idx, _))| (rowid, idx))
+                .collect(),
+            guids,
+            live,
+            uncommitted,
+            freelist,
+            tombstones,
+            chats,
+            frames,
+            pending: VecDeque::new(),
+        }))
+    }
+
+    /// Fill in the chat, sender, parent, and deletion reason of a decoded message.
+    ///
+    /// Parents are checked against live messages first, then the other recovered messages.
+    fn finish(&self, mut message: RecoveredMessage) -> Result<RecoveredMessage, RuntimeError> {
+        // `chat_message_join` repeats the message date, so both must match
+        message.chat_id = self.chats.get(&(message.rowid, message.date)).copied();
+        message.reason = self.reason(&message);
+        message.live_rowid = self.live.get(&message.rowid).copied();
+        // The sender's or participant's `handle` row may have been deleted along with the message
+        for who in [Some(&mut message.sender), message.participant.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            if let Sender::Unknown(handle_id) = who {
+                if let Some(address) = handle_address(&self.db, *handle_id)? {
+                    *who = Sender::Known(address);
+                }
+            }
+        }
+
+        if let Some(guid) = message.associated_guid() {
+            message.parent = live_guid_rowid(&self.db, guid)?
+                .map(ParentMessage::Live)
+                .or_else(|| self.guids.get(guid).copied().map(ParentMessage::Recovered));
+        }
+        Ok(message)
+    }
+
+    /// Decide why `message` is considered deleted, preferring the strongest evidence
+    fn reason(&self, message: &RecoveredMessage) -> DeletionReason {
+        if self.tombstones.contains(&message.guid) {
+            DeletionReason::Tombstoned
+        } else if self.uncommitted.contains(&message.rowid) {
+            DeletionReason::FoundInWalOnly
+        } else if self
+            .newest
+            .get(&message.rowid)
+            .is_some_and(|idx| self.freelist.contains(&self.wal.frames[*idx].page_number))
+        {
+            DeletionReason::FreelistPage
+        } else {
+            DeletionReason::AbsentFromMessages
+        }
+    }
+}
+
+/// Find every page of `table` in the committed database, walking its b-tree down from the root page in `sqlite_master`.
+///
+/// Pages that cannot be read are skipped, and a table that does not exist has no pages.
+fn table_pages(
+    db: &Connection,
+    pages: &mut DatabasePages,
+    wal: &mut Wal,
+    table: &str,
+) -> Result<HashSet<u32>, RuntimeError> {
+    let root: Option<u32> = db
+        .query_row(
+            "SELECT rootpage FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+
+    let mut found = HashSet::new();
+    let mut pending: Vec<u32> = root.into_iter().collect();
+    // A damaged b-tree could point back at a page that was already read
+    while let Some(page_number) = pending.pop() {
+        if !found.insert(page_number) {
+            continue;
+        }
+        if let Some(page) = pages.committed(wal, page_number) {
+            pending.extend(table_child_pages(&page, page_number));
+        }
+    }
+    Ok(found)
+}
+
+/// Find the phone number or email of the `handle` row with `handle_id`, if it still exists
+fn handle_address(db: &Connection, handle_id: i64) -> Result<Option<String>, RuntimeError> {
+    db.prepare_cached("SELECT id FROM handle WHERE ROWID = ?1")
+        .and_then(|mut statement| {
+            statement
+                .query_row([handle_id], |row| row.get(0))
+                .optional()
+        })
+        .map_err(|err| RuntimeError::DatabaseError(TableError::Handle(err)))
+}
+
+/// Find the `ROWID` of the live message with `guid`, if there is one
+fn live_guid_rowid(db: &Connection, guid: &str) -> Result<Option<i64>, RuntimeError> {
+    db.prepare_cached("SELECT ROWID FROM message WHERE guid = ?1")
+        .and_then(|mut statement| statement.query_row([guid], |row| row.get(0)).optional())
+        .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))
+}
+
+/// Read the GUIDs recorded in each of the [`TOMBSTONE_TABLES`] the database has
+fn tombstones(db: &Connection) -> Result<HashSet<String>, RuntimeError> {
+    let mut guids = HashSet::new();
+    for table in TOMBSTONE_TABLES {
+        let has_guid = db
+            .query_row(
+                "SELECT 1 FROM pragma_table_info(?1) WHERE name = 'guid'",
+                [table],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?
+            .is_some();
+        if !has_guid {
+            continue;
+        }
+        let deleted = db
+            .prepare(&format!("SELECT guid FROM {table} WHERE guid IS NOT NULL"))
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<Vec<String>, _>>()
+            })
+            .map_err(|err| RuntimeError::DatabaseError(TableError::Messages(err)))?;
+        guids.extend(deleted);
+    }
+    Ok(guids)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        env::temp_dir,
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use rusqlite::Connection;
+
+    use crate::app::{
+        error::RuntimeError,
+        recovery::{
+            filter::RecoveryFilter,
+            models::{DeletionReason, GroupChange, ParentMessage, Sender},
+            scan::{
+                RecoveredMessages, Scan, ScanCursor, ScanOptions, default_wal_path, scan_wal,
+                scan_wal_batch, scan_wal_iter,
+            },
+        },
+    };
+
+    /// Create a database in WAL mode that will not checkpoint while the returned connection is open
+    fn wal_database(name: &str) -> (PathBuf, Connection) {
+        let dir = temp_dir().join(format!("imessage-undeleter-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA wal_autocheckpoint = 0;
+            CREATE TABLE message (
+                ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+                guid TEXT UNIQUE NOT NULL,
+                text TEXT,
+                handle_id INTEGER DEFAULT 0,
+                attributedBody BLOB,
+                date INTEGER,
+                is_from_me INTEGER DEFAULT 0,
+                associated_message_guid TEXT,
+                associated_message_type INTEGER DEFAULT 0,
+                item_type INTEGER DEFAULT 0,
+                other_handle INTEGER DEFAULT 0,
+                group_title TEXT,
+                group_action_type INTEGER DEFAULT 0
+            );
+            CREATE TABLE chat_message_join (
+                chat_id INTEGER,
+                message_id INTEGER,
+                message_date INTEGER DEFAULT 0,
+                PRIMARY KEY (chat_id, message_id)
+            );
+            CREATE TABLE handle (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, id TEXT NOT NULL);",
+        )
+        .unwrap();
+        (path, conn)
+    }
+
+    #[test]
+    fn can_recover_row_only_in_wal() {
+        let (path, conn) = wal_database("only-in-wal");
+        conn.execute_batch(
+            "INSERT INTO handle (id) VALUES ('+15558675309');
+            INSERT INTO message (guid, text, handle_id, date, is_from_me) VALUES ('kept', 'Still here', 1, 100, 0);
+            INSERT INTO message (guid, text, handle_id, date, is_from_me) VALUES ('deleted', 'Gone', 1, 200, 1);
+            INSERT INTO chat_message_join VALUES (4, 1, 100), (4, 2, 200);
+            DELETE FROM chat_message_join WHERE message_id = 2;
+            DELETE FROM message WHERE guid = 'deleted';",
+        )
+        .unwrap();
+
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].rowid, 2);
+        assert_eq!(recovered[0].guid, "deleted");
+        assert_eq!(recovered[0].text.as_deref(), Some("Gone"));
+        assert_eq!(recovered[0].handle_id, Some(1));
+        assert_eq!(recovered[0].date, 200);
+        assert!(recovered[0].is_from_me);
+        assert_eq!(recovered[0].chat_id, Some(4));
+        assert_eq!(recovered[0].reason, DeletionReason::AbsentFromMessages);
+    }
+
+    #[test]
+    fn can_recover_overflowing_rows() {
+        let (path, conn) = wal_database("overflow");
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('long', printf('%.10000c', 'x') || 'end', 100);
+            INSERT INTO message (guid, text, date) VALUES ('short', 'Hi', 200);
+            DELETE FROM message;",
+        )
+        .unwrap();
+
+        // A 10 KB row spills over two overflow pages of a 4 KB page database
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].guid, "long");
+        let text = recovered[0].text.as_deref().unwrap();
+        assert_eq!(text.len(), 10_003);
+        assert!(text.ends_with("xend"));
+        assert!(!recovered[0].truncated);
+        assert_eq!(recovered[1].text.as_deref(), Some("Hi"));
+    }
+
+    #[test]
+    fn cant_link_chats_from_other_tables() {
+        let (path, conn) = wal_database("other-joins");
+        conn.execute_batch(
+            "CREATE TABLE other_join (a INTEGER, b INTEGER, c INTEGER);
+            INSERT INTO message (guid, text, date) VALUES ('in-chat', 'Hi', 100);
+            INSERT INTO message (guid, text, date) VALUES ('no-chat', 'Bye', 200);
+            INSERT INTO chat_message_join VALUES (4, 1, 100);
+            INSERT INTO other_join VALUES (9, 1, 100), (9, 2, 200);
+            DELETE FROM chat_message_join;
+            DELETE FROM other_join;
+            DELETE FROM message;",
+        )
+        .unwrap();
+
+        // `other_join` rows have the same shape as `chat_message_join` rows, but are not read as chats
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let chats: Vec<_> = recovered
+            .iter()
+            .map(|message| (message.guid.as_str(), message.chat_id))
+            .collect();
+        assert_eq!(chats, vec![("no-chat", None), ("in-chat", Some(4))]);
+    }
+
+    #[test]
+    fn can_drop_live_duplicates() {
+        let (path, conn) = wal_database("duplicates");
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('edited', 'Frist', 100);
+            UPDATE message SET text = 'First' WHERE guid = 'edited';
+            INSERT INTO message (guid, text, date) VALUES ('rewritten', 'Again', 200);
+            DELETE FROM message WHERE guid = 'rewritten';
+            INSERT INTO message (guid, text, date) VALUES ('rewritten', 'Again', 200);
+            INSERT INTO message (guid, text, date) VALUES ('deleted', 'Gone', 300);
+            DELETE FROM message WHERE guid = 'deleted';",
+        )
+        .unwrap();
+
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].guid, "deleted");
+        assert_eq!(recovered[0].live_rowid, None);
+
+        let options = ScanOptions {
+            keep_duplicates: true,
+        };
+        let recovered = scan_wal(&path, None, &RecoveryFilter::default(), &options).unwrap();
+        let duplicates: Vec<_> = recovered
+            .iter()
+            .map(|message| {
+                (
+                    message.guid.as_str(),
+                    message.text.as_deref(),
+                    message.live_rowid,
+                )
+            })
+            .collect();
+        assert_eq!(
+            duplicates,
+            vec![
+                ("edited", Some("First"), Some(1)),
+                ("rewritten", Some("Again"), Some(3)),
+                ("rewritten", Some("Again"), Some(3)),
+                ("deleted", Some("Gone"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_tag_uncommitted_rows() {
+        let (path, conn) = wal_database("uncommitted");
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('kept', 'Still here', 100);
+            PRAGMA cache_size = 1;
+            BEGIN;
+            INSERT INTO message (guid, text, date) VALUES ('pending', 'Not sent yet', 200);
+            INSERT INTO message (guid, text, date)
+                WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200)
+                SELECT 'filler-' || i, printf('%.500c', 'x'), 300 FROM n;",
+        )
+        .unwrap();
+
+        // The open transaction spilled its pages into the WAL file without committing them
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let pending = recovered
+            .iter()
+            .find(|message| message.guid == "pending")
+            .unwrap();
+        assert_eq!(pending.reason, DeletionReason::FoundInWalOnly);
+        assert!(recovered.iter().all(|message| message.guid != "kept"));
+    }
+
+    #[test]
+    fn can_tag_tombstoned_rows() {
+        let (path, conn) = wal_database("tombstoned");
+        conn.execute_batch(
+            "CREATE TABLE sync_deleted_messages (ROWID INTEGER PRIMARY KEY AUTOINCREMENT, guid TEXT NOT NULL, recordID TEXT);
+            INSERT INTO message (guid, text, date) VALUES ('synced', 'Deleted everywhere', 100);
+            INSERT INTO message (guid, text, date) VALUES ('local', 'Deleted here', 200);
+            DELETE FROM message;
+            INSERT INTO sync_deleted_messages (guid) VALUES ('synced');",
+        )
+        .unwrap();
+
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let reasons: Vec<_> = recovered
+            .iter()
+            .map(|message| (message.guid.as_str(), message.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("synced", DeletionReason::Tombstoned),
+                ("local", DeletionReason::AbsentFromMessages)
+            ]
+        );
+    }
+
+    #[test]
+    fn can_tag_rows_on_freelist_pages() {
+        let (path, conn) = wal_database("freelist");
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date)
+                WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+                SELECT 'long-' || i, printf('%.500c', 'x'), i FROM n;
+            DELETE FROM message;",
+        )
+        .unwrap();
+
+        // The table's root page is kept when it empties, but the pages it split into are freed
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.len(), 100);
+        assert!(
+            recovered
+                .iter()
+                .any(|message| message.reason == DeletionReason::FreelistPage)
+        );
+    }
+
+    #[test]
+    fn can_resolve_senders() {
+        let (path, conn) = wal_database("senders");
+        conn.execute_batch(
+            "INSERT INTO handle (id) VALUES ('+15558675309'), ('gone@example.com');
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('known', 'Hi', 1, 100);
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('unknown', 'Bye', 2, 200);
+            INSERT INTO message (guid, text, handle_id, date, is_from_me) VALUES ('mine', 'Hey', 0, 300, 1);
+            DELETE FROM message;
+            DELETE FROM handle WHERE ROWID = 2;",
+        )
+        .unwrap();
+
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let senders: Vec<_> = recovered
+            .iter()
+            .map(|message| (message.guid.as_str(), &message.sender))
+            .collect();
+        assert_eq!(
+            senders,
+            vec![
+                ("known", &Sender::Known("+15558675309".to_string())),
+                ("unknown", &Sender::Unknown(2)),
+                ("mine", &Sender::Me),
+            ]
+        );
+        assert_eq!(recovered[1].sender.to_string(), "Handle 2");
+    }
+
+    #[test]
+    fn can_link_recovered_tapbacks() {
+        let (path, conn) = wal_database("tapbacks");
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('live-parent', 'Still here', 100);
+            INSERT INTO message (guid, text, date) VALUES ('deleted-parent', 'Gone', 200);
+            INSERT INTO message (guid, text, date, associated_message_guid, associated_message_type)
+                VALUES ('likes-live', 'Liked “Still here”', 300, 'p:0/live-parent', 2001);
+            INSERT INTO message (guid, text, date, associated_message_guid, associated_message_type)
+                VALUES ('likes-deleted', 'Liked “Gone”', 400, 'p:0/deleted-parent', 2001);
+            INSERT INTO message (guid, text, date, associated_message_guid, associated_message_type)
+                VALUES ('likes-nothing', 'Liked “Missing”', 500, 'p:0/missing', 2001);
+            DELETE FROM message WHERE guid != 'live-parent';",
+        )
+        .unwrap();
+
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let links: Vec<_> = recovered
+            .iter()
+            .map(|message| {
+                (
+                    message.guid.as_str(),
+                    message.associated_message_type,
+                    message.parent,
+                )
+            })
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("deleted-parent", Some(0), None),
+                ("likes-live", Some(2001), Some(ParentMessage::Live(1))),
+                (
+                    "likes-deleted",
+                    Some(2001),
+                    Some(ParentMessage::Recovered(2))
+                ),
+                ("likes-nothing", Some(2001), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_recover_group_announcements() {
+        let (path, conn) = wal_database("group-announcements");
+        conn.execute_batch(
+            "INSERT INTO handle (id) VALUES ('+15558675309'), ('+15555550123');
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('kept', 'Welcome', 1, 100);
+            INSERT INTO message (guid, handle_id, date, item_type, other_handle, group_action_type) VALUES ('added', 1, 200, 1, 2, 0);
+            INSERT INTO message (guid, handle_id, date, item_type, group_title) VALUES ('renamed', 2, 300, 2, 'Trip');
+            INSERT INTO chat_message_join VALUES (3, 1, 100), (3, 2, 200), (3, 3, 300);
+            DELETE FROM message WHERE ROWID > 1;",
+        )
+        .unwrap();
+
+        let recovered = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].guid, "added");
+        assert_eq!(recovered[0].text, None);
+        assert_eq!(recovered[0].chat_id, Some(3));
+        assert_eq!(
+            recovered[0].group_change,
+            Some(GroupChange::ParticipantAdded(2))
+        );
+        assert_eq!(
+            recovered[0].participant,
+            Some(Sender::Known("+15555550123".to_string()))
+        );
+        assert_eq!(
+            recovered[1].group_change,
+            Some(GroupChange::NameChange("Trip".to_string()))
+        );
+        assert_eq!(recovered[1].participant, None);
+
+        let names = |handle_id| match handle_id {
+            1 => "Alice",
+            2 => "Bob",
+            _ => "Unknown",
+        };
+        let actor = names(recovered[0].handle_id.unwrap());
+        assert_eq!(
+            recovered[0]
+                .group_change
+                .as_ref()
+                .unwrap()
+                .describe(actor, names),
+            "Alice added Bob to the conversation."
+        );
+    }
+
+    /// Delete one message from each of two handles in each of two months
+    fn deleted_conversation(name: &str) -> (PathBuf, Connection) {
+        let (path, conn) = wal_database(name);
+        conn.execute_batch(
+            "INSERT INTO handle (id) VALUES ('+15558675309'), ('steve@apple.com');
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('a-jan', 'A in January', 1, 100);
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('b-jan', 'B in January', 2, 150);
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('a-feb', 'A in February', 1, 200);
+            INSERT INTO message (guid, text, handle_id, date) VALUES ('b-feb', 'B in February', 2, 250);
+            DELETE FROM message;",
+        )
+        .unwrap();
+        (path, conn)
+    }
+
+    fn guids(path: &Path, filter: &RecoveryFilter) -> Vec<String> {
+        scan_wal(path, None, filter, &ScanOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|message| message.guid)
+            .collect()
+    }
+
+    #[test]
+    fn can_filter_by_date() {
+        let (path, _conn) = deleted_conversation("filter-date");
+        let filter = RecoveryFilter {
+            after: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(guids(&path, &filter), vec!["a-feb", "b-feb"]);
+
+        let filter = RecoveryFilter {
+            after: Some(150),
+            before: Some(250),
+            ..Default::default()
+        };
+        assert_eq!(guids(&path, &filter), vec!["b-jan", "a-feb"]);
+    }
+
+    #[test]
+    fn can_filter_by_handle_id() {
+        let (path, _conn) = deleted_conversation("filter-handle-id");
+        let filter = RecoveryFilter {
+            handle_ids: Some(BTreeSet::from([2])),
+            ..Default::default()
+        };
+
+        assert_eq!(guids(&path, &filter), vec!["b-jan", "b-feb"]);
+    }
+
+    #[test]
+    fn can_filter_by_handle_address() {
+        let (path, _conn) = deleted_conversation("filter-handle-address");
+        let filter = RecoveryFilter {
+            handles: Some(BTreeSet::from(["+15558675309".to_string()])),
+            ..Default::default()
+        };
+        assert_eq!(guids(&path, &filter), vec!["a-jan", "a-feb"]);
+
+        let filter = RecoveryFilter {
+            handles: Some(BTreeSet::from(["nobody@example.com".to_string()])),
+            ..Default::default()
+        };
+        assert!(guids(&path, &filter).is_empty());
+    }
+
+    #[test]
+    fn can_sort_recovered_messages() {
+        let (path, conn) = wal_database("sorted");
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('chat-2-late', '', 300);
+            INSERT INTO message (guid, text, date) VALUES ('chat-1-late', '', 200);
+            INSERT INTO message (guid, text, date) VALUES ('chat-2-early', '', 100);
+            INSERT INTO message (guid, text, date) VALUES ('orphaned', '', 400);
+            INSERT INTO message (guid, text, date) VALUES ('chat-1-early', '', 100);
+            INSERT INTO message (guid, text, date) VALUES ('chat-1-tie', '', 100);
+            INSERT INTO chat_message_join VALUES (2, 1, 300), (1, 2, 200), (2, 3, 100), (1, 5, 100), (1, 6, 100);
+            DELETE FROM chat_message_join;
+            DELETE FROM message;",
+        )
+        .unwrap();
+
+        let first = guids(&path, &RecoveryFilter::default());
+        assert_eq!(
+            first,
+            vec![
+                "orphaned",
+                "chat-1-early",
+                "chat-1-tie",
+                "chat-1-late",
+                "chat-2-early",
+                "chat-2-late"
+            ]
+        );
+        assert_eq!(first, guids(&path, &RecoveryFilter::default()));
+    }
+
+    #[test]
+    fn can_resume_batched_scan() {
+        let (path, _conn) = deleted_conversation("batched");
+        let full = scan_wal(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+
+        let (first, cursor) = scan_wal_batch(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+            &ScanCursor::default(),
+            2,
+        )
+        .unwrap();
+        // Simulate a restart by saving and restoring the cursor
+        let cursor: ScanCursor = cursor.unwrap().to_string().parse().unwrap();
+        let (second, cursor) = scan_wal_batch(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+            &cursor,
+            2,
+        )
+        .unwrap();
+        assert_eq!(cursor, None);
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 2);
+        assert!(first.iter().all(|message| !second.contains(message)));
+        let mut resumed: Vec<_> = first.into_iter().chain(second).collect();
+        resumed.sort_by_key(|message| (message.chat_id, message.date, message.rowid));
+        assert_eq!(resumed, full);
+    }
+
+    #[test]
+    fn can_stream_recovered_messages() {
+        let (path, _conn) = deleted_conversation("streamed");
+        let (batch, cursor) = scan_wal_batch(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+            &ScanCursor::default(),
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(cursor, None);
+
+        let mut streamed = scan_wal_iter(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(streamed.len(), batch.len());
+        assert_eq!(streamed.len(), 4);
+
+        streamed.sort_by_key(|message| (message.chat_id, message.date, message.rowid));
+        assert_eq!(streamed, batch);
+    }
+
+    #[test]
+    fn can_stream_frames_incrementally() {
+        let (path, conn) = wal_database("incremental");
+        // Each statement commits separately, so each deleted message's newest version is in its own frame
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('first', 'One', 100);
+            DELETE FROM message;
+            INSERT INTO handle (id) VALUES ('+15558675309');
+            INSERT INTO message (guid, text, date) VALUES ('second', 'Two', 200);
+            DELETE FROM message;
+            INSERT INTO handle (id) VALUES ('steve@apple.com');",
+        )
+        .unwrap();
+
+        let mut messages = scan_wal_iter(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+        )
+        .unwrap();
+        let scan = messages.scan.as_ref().unwrap();
+        // Only the two frames holding a message are queued to be decoded again
+        assert_eq!(scan.frames.len(), 2);
+        assert!(scan.wal.frames.len() > 2);
+
+        let first = messages.next().unwrap().unwrap();
+        assert_eq!(first.guid, "first");
+        // The second frame has not been read yet
+        let scan = messages.scan.as_ref().unwrap();
+        assert_eq!(scan.frames.len(), 1);
+        assert!(scan.pending.is_empty());
+
+        assert_eq!(messages.next().unwrap().unwrap().guid, "second");
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn cant_reread_frames_before_cursor() {
+        let (path, conn) = wal_database("batched-frames");
+        // Each statement commits separately, so each deleted message's newest version is in its own frame
+        conn.execute_batch(
+            "INSERT INTO message (guid, text, date) VALUES ('first', 'One', 100);
+            DELETE FROM message;
+            INSERT INTO message (guid, text, date) VALUES ('second', 'Two', 200);
+            DELETE FROM message;
+            INSERT INTO message (guid, text, date) VALUES ('third', 'Three', 300);
+            DELETE FROM message;",
+        )
+        .unwrap();
+
+        let (first, cursor) = scan_wal_batch(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+            &ScanCursor::default(),
+            2,
+        )
+        .unwrap();
+        assert_eq!(first.len(), 2);
+        let cursor = cursor.unwrap();
+        let (frame, _, _) = cursor.position.unwrap();
+
+        let scan = Scan::open(
+            &path,
+            None,
+            &RecoveryFilter::default(),
+            &ScanOptions::default(),
+            &cursor,
+        )
+        .unwrap()
+        .unwrap();
+        // Every version of the message page before the cursor is skipped, including the one holding `first`
+        let page_number = scan.wal.frames[frame].page_number;
+        let skipped = (0..frame)
+            .filter(|idx| scan.wal.frames[*idx].page_number == page_number)
+            .count();
+        assert!(skipped >= 2);
+        assert!(
+            scan.wal
+                .reads
+                .iter()
+                .all(|idx| *idx >= frame || scan.wal.frames[*idx].page_number != page_number)
+        );
+        assert!(scan.wal.reads.contains(&frame));
+
+        let mut messages = RecoveredMessages { scan: Some(scan) };
+        assert_eq!(messages.next().unwrap().unwrap().guid, "third");
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn cant_resume_scan_of_different_wal() {
+        let (path, _conn) = deleted_conversation("batched-stale");
+        let cursor: ScanCursor = "3.2.00000000.00000000".parse().unwrap();
+
+        assert!(matches!(
+            scan_wal_batch(
+                &path,
+                None,
+                &RecoveryFilter::default(),
+                &ScanOptions::default(),
+                &cursor,
+                2
+            ),
+            Err(RuntimeError::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn can_parse_scan_cursor() {
+        assert_eq!(
+            "start".parse::<ScanCursor>().unwrap(),
+            ScanCursor::default()
+        );
+        let cursor: ScanCursor = "7.42.0000abcd.00000001".parse().unwrap();
+        assert_eq!(cursor.position, Some((7, 42, (0xabcd, 1))));
+        assert_eq!(cursor.to_string(), "7.42.0000abcd.00000001");
+        assert!("42.xyz".parse::<ScanCursor>().is_err());
+        assert!("42.0000abcd.00000001".parse::<ScanCursor>().is_err());
+    }
+
+    #[test]
+    fn cant_recover_without_deletions() {
+        let (path, conn) = wal_database("no-deletions");
+        conn.execute(
+            "INSERT INTO message (guid, text, date) VALUES ('kept', 'Still here', 100)",
+            [],
+        )
+        .unwrap();
+
+        assert!(
+            scan_wal(
+                &path,
+                None,
+                &RecoveryFilter::default(),
+                &ScanOptions::default()
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn cant_recover_without_wal() {
+        let (path, conn) = wal_database("no-wal");
+        conn.execute_batch("PRAGMA journal_mode = DELETE;").unwrap();
+        assert!(!default_wal_path(&path).exists());
+
+        assert!(
+            scan_wal(
+                &path,
+                None,
+                &RecoveryFilter::default(),
+                &ScanOptions::default()
+            )
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn cant_scan_missing_explicit_wal() {
+        let (path, _conn) = wal_database("missing-wal");
+
+        assert!(matches!(
+            scan_wal(
+                &path,
+                Some(&path.with_file_name("missing-wal")),
+                &RecoveryFilter::default(),
+                &ScanOptions::default()
+            ),
+            Err(RuntimeError::DiskError(_))
+        ));
+    }
+
+    #[test]
+    fn can_build_default_wal_path() {
+        assert_eq!(
+            default_wal_path(&PathBuf::from("/tmp/chat.db")),
+            PathBuf::from("/tmp/chat.db-wal")
+        );
+    }
+}
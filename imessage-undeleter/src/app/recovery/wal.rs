@@ -0,0 +1,290 @@
+/*!
+ Reads page versions out of a SQLite write-ahead log.
+
+ Every write appends a full copy of the changed page to the `-wal` file. Until the log is checkpointed
+ and reset, superseded versions of a page stay in the file, so rows that were deleted from the committed
+ database can still be read from the older frames.
+
+ The format is documented [here](https://www.sqlite.org/fileformat2.html#the_write_ahead_log).
+*/
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::app::{error::RuntimeError, recovery::bytes::read_u32};
+
+/// Size of the header at the start of the WAL file
+pub const WAL_HEADER_SIZE: usize = 32;
+/// Size of the header that precedes each page in the WAL file
+pub const FRAME_HEADER_SIZE: usize = 24;
+/// Magic numbers for WAL files using little- and big-endian checksums
+const WAL_MAGIC: [u32; 2] = [0x377F_0682, 0x377F_0683];
+
+/// The header of a single page version stored in the WAL
+#[derive(Debug, PartialEq, Eq)]
+pub struct WalFrame {
+    /// The database page this frame holds a version of
+    pub page_number: u32,
+    /// For commit frames, the size of the database in pages after the commit, otherwise `0`
+    pub commit_size: u32,
+    /// The salt copied from the WAL header when this frame was written
+    pub salt: (u32, u32),
+    /// Where the page contents start in the WAL file
+    offset: u64,
+}
+
+/// Anything a WAL can be read from
+trait WalSource: Read + Seek {}
+
+impl<T: Read + Seek> WalSource for T {}
+
+/// An open WAL file.
+///
+/// Only the frame headers are read up front; page contents are read from the file with [`Wal::read_frame`] when needed.
+pub struct Wal {
+    /// The database page size
+    pub page_size: usize,
+    /// The salt for the current generation of frames
+    pub salt: (u32, u32),
+    /// Every complete frame in the file in the order it was written, including
+    /// superseded frames and frames left over from before the last checkpoint
+    pub frames: Vec<WalFrame>,
+    /// The WAL file, or its contents if it was parsed from memory
+    source: Box<dyn WalSource>,
+    /// The index of every frame whose contents were read, in order
+    #[cfg(test)]
+    pub reads: Vec<usize>,
+}
+
+impl Wal {
+    /// Open the WAL file at `path`
+    pub fn from_path(path: &Path) -> Result<Self, RuntimeError> {
+        Self::from_source(Box::new(File::open(path)?))
+    }
+
+    /// Parse the contents of a WAL file that is already in memory
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RuntimeError> {
+        Self::from_source(Box::new(std::io::Cursor::new(bytes.to_vec())))
+    }
+
+    /// Read the WAL header and every frame header from `source`
+    fn from_source(mut source: Box<dyn WalSource>) -> Result<Self, RuntimeError> {
+        let length = source.seek(SeekFrom::End(0))?;
+        let mut header = [0; WAL_HEADER_SIZE];
+        if length < WAL_HEADER_SIZE as u64 {
+            return Err(RuntimeError::InvalidWal(format!(
+                "expected a {WAL_HEADER_SIZE} byte header, got {length} bytes"
+            )));
+        }
+        source.seek(SeekFrom::Start(0))?;
+        source.read_exact(&mut header)?;
+
+        // The header and every frame header are long enough for each field read from them
+        let field = |bytes: &[u8], offset: usize| read_u32(bytes, offset).unwrap_or_default();
+
+        let magic = field(&header, 0);
+        if !WAL_MAGIC.contains(&magic) {
+            return Err(RuntimeError::InvalidWal(format!(
+                "unknown magic number {magic:#010x}"
+            )));
+        }
+
+        let page_size = field(&header, 8) as usize;
+        if !page_size.is_power_of_two() || !(512..=65536).contains(&page_size) {
+            return Err(RuntimeError::InvalidWal(format!(
+                "invalid page size {page_size}"
+            )));
+        }
+
+        // A partially written frame at the end of the file is ignored
+        let frame_size = (FRAME_HEADER_SIZE + page_size) as u64;
+        let count = (length - WAL_HEADER_SIZE as u64) / frame_size;
+        let mut frames = vec![];
+        let mut frame_header = [0; FRAME_HEADER_SIZE];
+        for idx in 0..count {
+            let offset = WAL_HEADER_SIZE as u64 + idx * frame_size;
+            source.seek(SeekFrom::Start(offset))?;
+            source.read_exact(&mut frame_header)?;
+            let page_number = field(&frame_header, 0);
+            if page_number != 0 {
+                frames.push(WalFrame {
+                    page_number,
+                    commit_size: field(&frame_header, 4),
+                    salt: (field(&frame_header, 8), field(&frame_header, 12)),
+                    offset: offset + FRAME_HEADER_SIZE as u64,
+                });
+            }
+        }
+
+        Ok(Wal {
+            page_size,
+            salt: (field(&header, 16), field(&header, 20)),
+            frames,
+            source,
+            #[cfg(test)]
+            reads: vec![],
+        })
+    }
+
+    /// Read the page contents of frame `idx` from the file
+    pub fn read_frame(&mut self, idx: usize) -> Result<Vec<u8>, RuntimeError> {
+        let offset = self
+            .frames
+            .get(idx)
+            .ok_or_else(|| RuntimeError::InvalidWal(format!("no frame {idx}")))?
+            .offset;
+        let mut page = vec![0; self.page_size];
+        self.source.seek(SeekFrom::Start(offset))?;
+        self.source.read_exact(&mut page)?;
+        #[cfg(test)]
+        self.reads.push(idx);
+        Ok(page)
+    }
+
+    /// The index of the last commit frame for the current generation of frames, or `None` if nothing in it has committed.
+    ///
+    /// Frames of the current generation written after this one belong to a transaction that has not committed.
+    pub fn last_commit(&self) -> Option<usize> {
+        self.frames
+            .iter()
+            .rposition(|frame| frame.salt == self.salt && frame.commit_size != 0)
+    }
+
+    /// The newest committed version of `page_number` in the current generation of frames, if it was written since the last checkpoint
+    pub fn committed_page(&mut self, page_number: u32) -> Option<Vec<u8>> {
+        let idx = self.frames[..=self.last_commit()?]
+            .iter()
+            .rposition(|frame| frame.salt == self.salt && frame.page_number == page_number)?;
+        self.read_frame(idx).ok()
+    }
+
+    /// The version of `page_number` that was current when the transaction holding frame `idx` ended, if it was written to the WAL.
+    ///
+    /// A transaction writes its pages in no particular order, so this includes frames written after `idx` in the same transaction.
+    pub fn page_as_of(&mut self, idx: usize, page_number: u32) -> Option<Vec<u8>> {
+        let salt = self.frames.get(idx)?.salt;
+        let mut end = idx;
+        while let Some(next) = self.frames.get(end + 1) {
+            if self.frames[end].commit_size != 0 || next.salt != salt {
+                break;
+            }
+            end += 1;
+        }
+        let idx = self.frames[..=end]
+            .iter()
+            .rposition(|frame| frame.page_number == page_number)?;
+        self.read_frame(idx).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        error::RuntimeError,
+        recovery::wal::{FRAME_HEADER_SIZE, WAL_HEADER_SIZE, Wal},
+    };
+
+    fn header(page_size: u32) -> Vec<u8> {
+        let mut header = vec![0; WAL_HEADER_SIZE];
+        header[0..4].copy_from_slice(&0x377F_0682u32.to_be_bytes());
+        header[8..12].copy_from_slice(&page_size.to_be_bytes());
+        header[16..24].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2]);
+        header
+    }
+
+    #[test]
+    fn can_read_frames() {
+        let mut wal = header(512);
+        for page_number in [2u32, 3] {
+            let mut frame = vec![0; FRAME_HEADER_SIZE + 512];
+            frame[0..4].copy_from_slice(&page_number.to_be_bytes());
+            frame[FRAME_HEADER_SIZE] = page_number as u8;
+            wal.extend(frame);
+        }
+        // Partial frame
+        wal.extend([0; 100]);
+
+        let mut wal = Wal::from_bytes(&wal).unwrap();
+        assert_eq!(wal.page_size, 512);
+        assert_eq!(wal.salt, (1, 2));
+        assert_eq!(wal.frames.len(), 2);
+        assert_eq!(wal.frames[0].page_number, 2);
+        assert_eq!(wal.read_frame(1).unwrap()[0], 3);
+        assert!(wal.read_frame(2).is_err());
+    }
+
+    #[test]
+    fn can_find_committed_pages() {
+        let mut wal = header(512);
+        // Page 2 is written twice, but only the first version is committed
+        for (page_number, commit_size, version) in [(2u32, 0u32, 1u8), (3, 3, 2), (2, 0, 3)] {
+            let mut frame = vec![0; FRAME_HEADER_SIZE + 512];
+            frame[0..4].copy_from_slice(&page_number.to_be_bytes());
+            frame[4..8].copy_from_slice(&commit_size.to_be_bytes());
+            frame[8..16].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2]);
+            frame[FRAME_HEADER_SIZE] = version;
+            wal.extend(frame);
+        }
+
+        let mut wal = Wal::from_bytes(&wal).unwrap();
+        assert_eq!(wal.last_commit(), Some(1));
+        assert_eq!(wal.committed_page(2).map(|page| page[0]), Some(1));
+        assert_eq!(wal.committed_page(3).map(|page| page[0]), Some(2));
+        assert_eq!(wal.committed_page(4), None);
+    }
+
+    #[test]
+    fn can_find_pages_as_of_a_frame() {
+        let mut wal = header(512);
+        // Two transactions each write page 2 and commit with page 3
+        for (page_number, commit_size, version) in
+            [(2u32, 0u32, 1u8), (3, 3, 2), (2, 0, 3), (3, 3, 4)]
+        {
+            let mut frame = vec![0; FRAME_HEADER_SIZE + 512];
+            frame[0..4].copy_from_slice(&page_number.to_be_bytes());
+            frame[4..8].copy_from_slice(&commit_size.to_be_bytes());
+            frame[8..16].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2]);
+            frame[FRAME_HEADER_SIZE] = version;
+            wal.extend(frame);
+        }
+
+        let mut wal = Wal::from_bytes(&wal).unwrap();
+        assert_eq!(wal.page_as_of(0, 3).map(|page| page[0]), Some(2));
+        assert_eq!(wal.page_as_of(1, 2).map(|page| page[0]), Some(1));
+        assert_eq!(wal.page_as_of(2, 2).map(|page| page[0]), Some(3));
+        assert_eq!(wal.page_as_of(2, 3).map(|page| page[0]), Some(4));
+        assert_eq!(wal.page_as_of(0, 4), None);
+        assert_eq!(wal.page_as_of(4, 2), None);
+    }
+
+    #[test]
+    fn cant_read_bad_magic() {
+        let mut wal = header(512);
+        wal[0] = 0;
+
+        assert!(matches!(
+            Wal::from_bytes(&wal),
+            Err(RuntimeError::InvalidWal(_))
+        ));
+    }
+
+    #[test]
+    fn cant_read_bad_page_size() {
+        assert!(matches!(
+            Wal::from_bytes(&header(1000)),
+            Err(RuntimeError::InvalidWal(_))
+        ));
+    }
+
+    #[test]
+    fn cant_read_short_header() {
+        assert!(matches!(
+            Wal::from_bytes(&[0; 10]),
+            Err(RuntimeError::InvalidWal(_))
+        ));
+    }
+}
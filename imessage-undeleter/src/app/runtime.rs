@@ -6,7 +6,7 @@ use std::{
     cmp::min,
     collections::{BTreeSet, HashMap, HashSet},
     fs::{self, File, OpenOptions, create_dir_all, remove_dir_all, remove_file, rename},
-    io::Write,
+    io::{BufWriter, Write},
     path::PathBuf,
     thread,
     time::Duration,
@@ -18,12 +18,25 @@ use rusqlite::Connection;
 use crate::{
     TXT,
     app::{
-        compatibility::backup::{decrypt_backup, get_decrypted_message_database},
+        compatibility::{
+            attachment_resolver::{AttachmentResolver, NoopResolver, resolve_missing_attachment},
+            backup::{decrypt_backup, get_decrypted_message_database},
+        },
         error::RuntimeError,
-        options::{OPTION_CLEARTEXT_PASSWORD, Options},
+        options::{OPTION_CLEARTEXT_PASSWORD, OPTION_WAL_CURSOR, Options},
+        recovery::{
+            filter::RecoveryFilter,
+            models::{DeletionReason, ParentMessage},
+            report::RecoveryReport,
+            scan::{ScanOptions, scan_wal, scan_wal_batch},
+        },
         sanitizers::sanitize_filename,
     },
-    exporters::exporter::ATTACHMENT_NO_FILENAME,
+    exporters::{
+        csv::write_csv,
+        exporter::ATTACHMENT_NO_FILENAME,
+        html::{HandwritingFailures, format_handwriting, render_conversation_html},
+    },
 };
 
 use imessage_database::{
@@ -38,11 +51,17 @@ use imessage_database::{
             get_connection,
         },
     },
-    util::{dates::get_offset, platform::Platform},
+    util::{
+        dates::{format, get_local_time, get_offset},
+        platform::Platform,
+    },
 };
 
 const MAX_LENGTH: usize = 235;
 
+/// A message being watched for deletion, its locally saved attachments, and its handwriting payload
+type TrackedMessage = (Message, Vec<PathBuf>, Option<Vec<u8>>);
+
 /// Stores the application state and handles application lifecycle
 pub struct Config {
     /// Map of chatroom ID to chatroom information
@@ -65,6 +84,8 @@ pub struct Config {
     pub db: Option<Connection>,
     /// An optional encrypted iOS backup
     pub backup: Option<Backup>,
+    /// Locates media for deleted messages whose attachments were never saved
+    pub attachment_resolver: Box<dyn AttachmentResolver>,
 }
 
 impl Config {
@@ -261,6 +282,7 @@ impl Config {
             offset: get_offset(),
             db: Some(conn),
             backup,
+            attachment_resolver: Box::new(NoopResolver),
         })
     }
 
@@ -378,6 +400,10 @@ impl Config {
             }
         }
 
+        if self.options.scan_wal {
+            return self.list_wal_messages();
+        }
+
         // Ensure the path we want to export to exists
         create_dir_all(&self.options.export_path)?;
         if self.tmp_attachment_path().is_dir() {
@@ -391,7 +417,7 @@ impl Config {
         create_dir_all(&self.attachment_path())?;
         create_dir_all(&self.tmp_attachment_path())?;
 
-        let mut last_messages: HashMap<i32, (Message, Vec<PathBuf>)> = HashMap::new();
+        let mut last_messages: HashMap<i32, TrackedMessage> = HashMap::new();
         let mut min_attachment_number: i32 = self.find_min_attachment_number(0)?;
         let mut outfile = OpenOptions::new()
             .write(true)
@@ -399,30 +425,39 @@ impl Config {
             .create(true)
             .open(&self.options.export_path.join("LOGFILE.html"))?;
         let mut txt_instance = TXT::new(self)?;
+        let mut handwriting_failures = HandwritingFailures::default();
         loop {
             let new_messages = txt_instance.iter_messages()?; // TODO: Filter out messages from self
-            let mut new_messages_with_attachments: HashMap<i32, (Message, Vec<PathBuf>)> =
+            let mut new_messages_with_attachments: HashMap<i32, TrackedMessage> =
                 HashMap::new();
 
             for (msg_id, mut new_message) in new_messages {
                 let _ = new_message.generate_text(self.db());
                 let attachments = Attachment::from_message(self.db(), &new_message)?;
                 let mut attachment_destinations: Vec<PathBuf> = Vec::new();
+                let mut handwriting: Option<Vec<u8>> = None;
 
                 // Detect deleted messages
-                if let Some((last_message, last_message_attachments)) =
+                if let Some((last_message, last_message_attachments, last_handwriting)) =
                     last_messages.remove(&msg_id)
                 {
                     if new_message.is_fully_unsent() && !last_message.is_fully_unsent() {
                         self.handle_deleted_message(
                             &last_message,
                             &last_message_attachments,
+                            last_handwriting.as_deref(),
                             &mut outfile,
                             &txt_instance,
+                            &mut handwriting_failures,
                         )?;
                     }
                     attachment_destinations = last_message_attachments;
+                    handwriting = last_handwriting;
                 } else {
+                    // Unsending clears the payload, so keep a copy of any handwriting now
+                    if new_message.is_handwriting() {
+                        handwriting = new_message.raw_payload_data(self.db());
+                    }
                     // Completely new message
                     if new_message.has_attachments() {
                         self.save_attachments_locally(
@@ -434,10 +469,10 @@ impl Config {
                     }
                 }
                 new_messages_with_attachments
-                    .insert(msg_id.clone(), (new_message, attachment_destinations));
+                    .insert(msg_id.clone(), (new_message, attachment_destinations, handwriting));
             }
             // See what old messages no longer exist, and remove any temporary attachments!
-            for (msg_id, (_, attachments)) in last_messages {
+            for (msg_id, (_, attachments, _)) in last_messages {
                 self.handle_untracked_message(msg_id, &attachments);
             }
 
@@ -446,6 +481,108 @@ impl Config {
         }
     }
 
+    /// Print a report of every message that exists in the WAL file but not in the committed database
+    ///
+    /// Nothing is written to the export directory.
+    pub fn list_wal_messages(&self) -> Result<(), RuntimeError> {
+        // Encrypted backups are decrypted to a temporary file, so prefer the open database's path
+        let db_path = self
+            .db()
+            .path()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.options.get_db_path());
+        let filter = RecoveryFilter {
+            handle_ids: self.options.query_context.selected_handle_ids.clone(),
+            ..Default::default()
+        };
+        let scan_options = ScanOptions {
+            keep_duplicates: self.options.wal_keep_duplicates,
+        };
+        let wal_path = self.options.wal_path.as_deref();
+
+        // Batched scans print a cursor that a later run can resume from
+        let (messages, next) = if self.options.wal_batch.is_some() || self.options.wal_cursor.is_some() {
+            scan_wal_batch(
+                &db_path,
+                wal_path,
+                &filter,
+                &scan_options,
+                &self.options.wal_cursor.unwrap_or_default(),
+                self.options.wal_batch.unwrap_or(usize::MAX),
+            )?
+        } else {
+            (scan_wal(&db_path, wal_path, &filter, &scan_options)?, None)
+        };
+        let report = RecoveryReport::from_messages(&messages);
+
+        println!(
+            "Found {} messages across {} chats in the WAL file.",
+            report.count,
+            report.chats.len()
+        );
+        if let Some((first, last)) = report.date_range {
+            println!(
+                "Sent between {} and {}",
+                format(&get_local_time(&first, &self.offset)),
+                format(&get_local_time(&last, &self.offset))
+            );
+        }
+        for message in &report.messages {
+            let parent = match message.parent {
+                Some(ParentMessage::Live(rowid)) => format!(" (re: message {rowid})"),
+                Some(ParentMessage::Recovered(rowid)) => {
+                    format!(" (re: recovered message {rowid})")
+                }
+                None => String::new(),
+            };
+            let date = format(&get_local_time(&message.date, &self.offset));
+            let who = self.who(message.handle_id, message.is_from_me, &None);
+            // Group chat changes are system lines, not text from the sender
+            if let Some(change) = &message.group_change {
+                let line = change.describe(who, |handle_id| self.who(Some(handle_id), false, &None));
+                println!("{date} {line}");
+                continue;
+            }
+            // Most messages are plain deletions, so only the less common reasons are called out
+            let reason = match message.reason {
+                DeletionReason::AbsentFromMessages => String::new(),
+                reason => format!(" [{reason}]"),
+            };
+            let live = match message.live_rowid {
+                Some(rowid) => format!(" (still live as message {rowid})"),
+                None => String::new(),
+            };
+            let truncated = if message.truncated {
+                " [truncated]"
+            } else {
+                ""
+            };
+            println!(
+                "{date} {who}: {}{parent}{reason}{live}{truncated}",
+                message.preview
+            );
+        }
+        if let Some(path) = &self.options.wal_csv {
+            write_csv(
+                &report,
+                self.options.wal_redact,
+                BufWriter::new(File::create(path)?),
+            )?;
+            println!("Wrote {} messages to {}", report.count, path.display());
+        }
+        if let Some(path) = &self.options.wal_html {
+            fs::write(
+                path,
+                render_conversation_html(&messages, self.options.wal_redact),
+            )?;
+            println!("Wrote a transcript of {} messages to {}", report.count, path.display());
+        }
+        if let Some(next) = next {
+            println!("More messages remain; resume with --{OPTION_WAL_CURSOR} {next}");
+        }
+        Ok(())
+    }
+
     pub fn find_min_attachment_number(&self, start: i32) -> Result<i32, RuntimeError> {
         let mut n = start;
         while self.attachment_path().join(n.to_string()).try_exists()? {
@@ -484,8 +621,10 @@ impl Config {
         &self,
         last_message: &Message,
         last_message_attachments: &Vec<PathBuf>,
+        handwriting: Option<&[u8]>,
         outfile: &mut File,
         txt_instance: &TXT,
+        handwriting_failures: &mut HandwritingFailures,
     ) -> Result<(), RuntimeError> {
         println!(
             "Deleted message Detected! \"{}\" with {} attachments.",
@@ -508,6 +647,20 @@ impl Config {
                 writeln!(outfile, "<p>Text: {}</p><br>", text)?;
             }
         }
+        if let Some(payload) = handwriting {
+            match format_handwriting(
+                payload,
+                last_message.expressive_send_style_id.as_deref(),
+            ) {
+                Ok(html) => writeln!(outfile, "{html}<br>")?,
+                Err(why) => {
+                    let failure = handwriting_failures.record(&why);
+                    eprintln!("Unable to render handwriting: {why}");
+                    eprintln!("{}", handwriting_failures.describe(failure));
+                    writeln!(outfile, "<p>Unable to render handwriting: {failure}</p><br>")?;
+                }
+            }
+        }
         writeln!(outfile, "<p>Attachments:</p><br>")?;
         for attachment in last_message_attachments {
             let mut attachment_path = self.attachment_path().canonicalize().unwrap();
@@ -523,6 +676,19 @@ impl Config {
                     .unwrap_or("?".to_string())
             )?;
         }
+        // The attachments were never saved, so see if they can be found elsewhere
+        if let Some(resolved) = resolve_missing_attachment(
+            self.attachment_resolver.as_ref(),
+            last_message,
+            last_message_attachments,
+        ) {
+            println!("Resolved attachment for {} to {:?}", last_message.guid, &resolved);
+            writeln!(
+                outfile,
+                "<img src=\"{}\" style='width:300px'><br>",
+                resolved.display()
+            )?;
+        }
         Ok(())
     }
 
@@ -575,6 +741,7 @@ impl Config {
             offset: get_offset(),
             db: Some(connection),
             backup: None,
+            attachment_resolver: Box::new(NoopResolver),
         }
     }
 
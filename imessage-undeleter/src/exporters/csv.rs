@@ -0,0 +1,251 @@
+/*!
+ Writes recoverable message summaries as CSV for use in a spreadsheet.
+*/
+
+use std::{borrow::Cow, io::Write};
+
+use imessage_database::util::dates::{get_local_time, get_offset};
+
+use crate::{
+    app::{error::RuntimeError, recovery::report::RecoveryReport},
+    exporters::redact::Pseudonyms,
+};
+
+/// The first row of every CSV file
+pub const CSV_HEADER: [&str; 8] = [
+    "guid",
+    "chat",
+    "sender",
+    "date",
+    "has_attachment",
+    "message_type",
+    "deletion_reason",
+    "text_preview",
+];
+/// Characters that make a spreadsheet treat a field as a formula
+const FORMULA_PREFIXES: [char; 4] = ['=', '+', '-', '@'];
+
+/// Write one row for each message in `report`, after a [`CSV_HEADER`] row.
+///
+/// Dates are in local time. Senders are `me`, the sender's address, or `Handle N` if their `handle` row is gone, and
+/// fields that are unknown are left empty.
+/// If `redact` is true, senders, emails, and phone numbers in the preview are replaced with [`Pseudonyms`].
+pub fn write_csv(
+    report: &RecoveryReport,
+    redact: bool,
+    mut w: impl Write,
+) -> Result<(), RuntimeError> {
+    write_row(&mut w, CSV_HEADER.map(Cow::Borrowed))?;
+
+    let offset = get_offset();
+    let mut pseudonyms = redact.then(Pseudonyms::default);
+    for message in &report.messages {
+        let sender = match (message.is_from_me, message.handle_id, &mut pseudonyms) {
+            (true, _, _) => "me".to_string(),
+            (false, Some(_), Some(pseudonyms)) => pseudonyms.sender(&message.sender),
+            (false, Some(_), None) => message.sender.to_string(),
+            (false, None, _) => String::new(),
+        };
+        let preview = match &mut pseudonyms {
+            Some(pseudonyms) => Cow::Owned(pseudonyms.redact_text(&message.preview)),
+            None => Cow::Borrowed(message.preview.as_str()),
+        };
+        let date = get_local_time(&message.date, &offset)
+            .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        write_row(
+            &mut w,
+            [
+                Cow::Borrowed(message.guid.as_str()),
+                Cow::Owned(
+                    message
+                        .chat_id
+                        .map(|chat_id| chat_id.to_string())
+                        .unwrap_or_default(),
+                ),
+                Cow::Owned(sender),
+                Cow::Owned(date),
+                Cow::Borrowed(if message.has_attachments {
+                    "true"
+                } else {
+                    "false"
+                }),
+                Cow::Owned(message.kind.to_string()),
+                Cow::Owned(message.reason.to_string()),
+                preview,
+            ],
+        )?;
+    }
+    Ok(w.flush()?)
+}
+
+/// Write `fields` as one line, escaping each one
+fn write_row<const N: usize>(
+    w: &mut impl Write,
+    fields: [Cow<str>; N],
+) -> Result<(), RuntimeError> {
+    let fields: Vec<Cow<str>> = fields.iter().map(|field| escape(field)).collect();
+    Ok(writeln!(w, "{}", fields.join(","))?)
+}
+
+/// Quote `field` if it contains a delimiter, quote, or line break, doubling any quotes inside it.
+///
+/// Fields that start with one of [`FORMULA_PREFIXES`] are led by `'`, so spreadsheets show them as text instead of
+/// evaluating them.
+fn escape(field: &str) -> Cow<'_, str> {
+    let field = if field.starts_with(FORMULA_PREFIXES) {
+        Cow::Owned(format!("'{field}"))
+    } else {
+        Cow::Borrowed(field)
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::current_dir, fs::File, io::Read};
+
+    use crate::{
+        app::recovery::{
+            models::{DeletionReason, RecoveredMessage, Sender},
+            report::RecoveryReport,
+        },
+        exporters::csv::{CSV_HEADER, escape, write_csv},
+    };
+
+    fn message(rowid: i64, text: Option<&str>) -> RecoveredMessage {
+        RecoveredMessage {
+            rowid,
+            guid: format!("guid-{rowid}"),
+            text: text.map(String::from),
+            handle_id: Some(3),
+            sender: Sender::Unknown(3),
+            date: 0,
+            is_from_me: false,
+            chat_id: Some(1),
+            associated_message_guid: None,
+            associated_message_type: None,
+            parent: None,
+            reason: DeletionReason::AbsentFromMessages,
+            live_rowid: None,
+            group_change: None,
+            participant: None,
+            has_attachments: false,
+            balloon_bundle_id: None,
+            payload_data: None,
+            truncated: false,
+        }
+    }
+
+    fn read_handwriting(name: &str) -> Vec<u8> {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .join("imessage-database/test_data/handwritten_message")
+            .join(name);
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn can_escape_fields() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn can_escape_formulas() {
+        assert_eq!(escape("=1+2"), "'=1+2");
+        assert_eq!(escape("+15558675309"), "'+15558675309");
+        assert_eq!(escape("-2"), "'-2");
+        assert_eq!(escape("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(
+            escape("=HYPERLINK(\"http://example.com\",\"x\")"),
+            "\"'=HYPERLINK(\"\"http://example.com\"\",\"\"x\"\")\""
+        );
+        assert_eq!(escape("a=b"), "a=b");
+    }
+
+    #[test]
+    fn can_round_trip_csv() {
+        let mut from_me = message(2, Some("Hello, \"world\"\nagain"));
+        from_me.is_from_me = true;
+        from_me.has_attachments = true;
+        let mut handwriting = message(3, None);
+        handwriting.balloon_bundle_id = Some(
+            "com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.Handwriting.HandwritingProvider"
+                .to_string(),
+        );
+        handwriting.payload_data = Some(read_handwriting("hello.bin"));
+        let mut known = message(5, Some("known"));
+        known.sender = Sender::Known("+15558675309".to_string());
+        let mut orphaned = message(4, Some("plain"));
+        orphaned.chat_id = None;
+        orphaned.handle_id = None;
+        let report = RecoveryReport::from_messages(&[from_me, handwriting, known, orphaned]);
+
+        let mut data = vec![];
+        write_csv(&report, false, &mut data).unwrap();
+
+        let mut reader = csv::Reader::from_reader(data.as_slice());
+        assert_eq!(reader.headers().unwrap(), CSV_HEADER.as_slice());
+        let rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect())
+            .collect();
+        assert_eq!(rows.len(), 4);
+
+        assert_eq!(rows[0][0], "guid-2");
+        assert_eq!(rows[0][1], "1");
+        assert_eq!(rows[0][2], "me");
+        assert_eq!(rows[0][4], "true");
+        assert_eq!(rows[0][5], "text");
+        assert_eq!(rows[0][6], "absent_from_messages");
+        assert_eq!(rows[0][7], "Hello, \"world\"\nagain");
+
+        assert_eq!(rows[1][2], "Handle 3");
+        assert_eq!(rows[1][4], "false");
+        assert_eq!(rows[1][5], "handwriting");
+        assert_eq!(rows[1][7], "1 stroke");
+
+        assert_eq!(rows[2][2], "'+15558675309");
+
+        assert_eq!(rows[3][1], "");
+        assert_eq!(rows[3][2], "");
+        assert_eq!(rows[3][7], "plain");
+        assert_eq!(rows[0][3], rows[3][3]);
+    }
+
+    #[test]
+    fn can_redact_csv() {
+        let mut other = message(3, Some("Text me at 555-867-5309"));
+        other.handle_id = Some(4);
+        other.sender = Sender::Known("+15558675309".to_string());
+        let report = RecoveryReport::from_messages(&[
+            message(1, Some("Hi")),
+            other,
+            message(2, Some("Again")),
+        ]);
+
+        let mut data = vec![];
+        write_csv(&report, true, &mut data).unwrap();
+
+        let rows: Vec<Vec<String>> = csv::Reader::from_reader(data.as_slice())
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect())
+            .collect();
+        assert_eq!(rows[0][2], "Contact A");
+        assert_eq!(rows[1][2], "Contact B");
+        assert_eq!(rows[1][7], "Text me at Contact B");
+        assert_eq!(rows[2][2], "Contact A");
+    }
+}
@@ -0,0 +1,461 @@
+/*!
+ Formats message content as HTML for the deleted message log.
+*/
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult, Write},
+};
+
+use imessage_database::{
+    error::handwriting::HandwritingError,
+    message_types::handwriting::{HandwrittenMessage, SvgRenderOptions},
+    util::dates::{format, get_local_time, get_offset},
+};
+
+use crate::{
+    app::{
+        recovery::{
+            models::{GroupChange, ParentMessage, RecoveredMessage},
+            report::MessageKind,
+        },
+        sanitizers::sanitize_html,
+    },
+    exporters::redact::Pseudonyms,
+};
+
+/// Styles for transcripts built by [`render_conversation_html`]
+const TRANSCRIPT_STYLE: &str = include_str!("resources/style.css");
+
+/// Why a handwritten message could not be exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandwritingFailure {
+    /// The payload is not a valid handwriting protobuf
+    InvalidPayload,
+    /// The payload is valid, but does not contain a drawing
+    NotHandwriting,
+    /// The stroke data uses a compression method that is not supported
+    UnsupportedCompression,
+    /// The compressed stroke data could not be decompressed
+    InvalidCompression,
+    /// The frame describing the canvas size is malformed
+    InvalidFrame,
+    /// The stroke data ended early
+    InvalidStrokes,
+    /// The stroke coordinates could not be converted
+    InvalidCoordinates,
+    /// The parsed strokes could not be drawn
+    RenderFailed,
+}
+
+impl From<&HandwritingError> for HandwritingFailure {
+    fn from(err: &HandwritingError) -> Self {
+        match err {
+            HandwritingError::ProtobufError(_)
+            | HandwritingError::UnknownFields(_)
+            | HandwritingError::NotHandwritingPayload
+            | HandwritingError::InvalidBase64
+            | HandwritingError::ReadError(_) => HandwritingFailure::InvalidPayload,
+            HandwritingError::MissingHandwritingData => HandwritingFailure::NotHandwriting,
+            HandwritingError::CompressionUnknown => HandwritingFailure::UnsupportedCompression,
+            HandwritingError::XZError(_)
+            | HandwritingError::DecompressedNotSet
+            | HandwritingError::InvalidDecompressedLength(_, _) => {
+                HandwritingFailure::InvalidCompression
+            }
+            HandwritingError::InvalidFrameSize(_, _) => HandwritingFailure::InvalidFrame,
+            HandwritingError::InvalidStrokesLength(_, _, _)
+            | HandwritingError::TooManyStrokes(_, _) => HandwritingFailure::InvalidStrokes,
+            HandwritingError::ConversionError | HandwritingError::ResizeError(_) => {
+                HandwritingFailure::InvalidCoordinates
+            }
+            HandwritingError::PdfError(_)
+            | HandwritingError::RasterError(_)
+            | HandwritingError::InvalidScale(_) => HandwritingFailure::RenderFailed,
+        }
+    }
+}
+
+impl Display for HandwritingFailure {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            HandwritingFailure::InvalidPayload => write!(fmt, "invalid payload"),
+            HandwritingFailure::NotHandwriting => write!(fmt, "no handwriting data"),
+            HandwritingFailure::UnsupportedCompression => write!(fmt, "unsupported compression"),
+            HandwritingFailure::InvalidCompression => write!(fmt, "invalid compressed data"),
+            HandwritingFailure::InvalidFrame => write!(fmt, "invalid frame"),
+            HandwritingFailure::InvalidStrokes => write!(fmt, "invalid stroke length"),
+            HandwritingFailure::InvalidCoordinates => write!(fmt, "invalid coordinates"),
+            HandwritingFailure::RenderFailed => write!(fmt, "render failed"),
+        }
+    }
+}
+
+/// Counts of handwritten messages that were skipped during an export, by cause
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HandwritingFailures {
+    pub skipped: BTreeMap<HandwritingFailure, usize>,
+}
+
+impl HandwritingFailures {
+    /// Record a skipped message, returning its cause
+    pub fn record(&mut self, err: &HandwritingError) -> HandwritingFailure {
+        let failure = HandwritingFailure::from(err);
+        *self.skipped.entry(failure).or_default() += 1;
+        failure
+    }
+
+    /// Describe how many messages were skipped for `failure`
+    pub fn describe(&self, failure: HandwritingFailure) -> String {
+        let count = self.skipped.get(&failure).copied().unwrap_or_default();
+        format!(
+            "{count} handwriting message{} skipped: {failure}",
+            if count == 1 { "" } else { "s" }
+        )
+    }
+
+    /// Describe every cause of skipped messages
+    pub fn summary(&self) -> Vec<String> {
+        self.skipped
+            .keys()
+            .map(|failure| self.describe(*failure))
+            .collect()
+    }
+}
+
+/// Format a handwritten message payload as HTML, inlining the rendered `svg`.
+///
+/// `effect` is the message's `expressive_send_style_id`. It is noted on the bubble as a `data-effect` attribute,
+/// but never applied, so drawings sent with invisible ink stay readable.
+///
+/// Every failure happens while parsing the payload; once parsed, rendering the `svg` cannot fail, so there is no
+/// fallback rendering.
+pub fn format_handwriting(
+    payload: &[u8],
+    effect: Option<&str>,
+) -> Result<String, HandwritingError> {
+    let handwriting = HandwrittenMessage::from_payload(payload)?.with_effect(effect);
+    let effect = handwriting
+        .effect
+        .as_ref()
+        .map(|effect| format!(" data-effect=\"{}\"", sanitize_html(effect)))
+        .unwrap_or_default();
+    let svg = handwriting.render_svg_with_options(&SvgRenderOptions::default());
+    Ok(format!(
+        "<div class=\"handwriting\"{effect}>{}</div>",
+        svg.trim()
+    ))
+}
+
+/// Render recovered messages as a standalone, styled HTML transcript with one bubble per message in chronological order.
+///
+/// Each message is drawn by its [`MessageKind`]: text as a bubble, handwriting as an inline `svg`, tapbacks and
+/// other reactions as a note about the message they point at, and group changes as announcements. Recovered rows
+/// do not include attachment files, so attachments are shown as a placeholder.
+///
+/// Senders and the participants of group changes are named by their resolved
+/// [`Sender`](crate::app::recovery::models::Sender). If `redact` is true, senders, participants, and the emails and
+/// phone numbers in the text are replaced with [`Pseudonyms`] instead.
+pub fn render_conversation_html(messages: &[RecoveredMessage], redact: bool) -> String {
+    let mut ordered: Vec<&RecoveredMessage> = messages.iter().collect();
+    ordered.sort_by_key(|message| (message.date, message.rowid));
+
+    let offset = get_offset();
+    let mut pseudonyms = redact.then(Pseudonyms::default);
+    let mut html = format!(
+        "<html>\n<meta charset=\"UTF-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<style>\n{TRANSCRIPT_STYLE}</style>\n"
+    );
+    for message in ordered {
+        let date = format(&get_local_time(&message.date, &offset));
+        let sender = match &mut pseudonyms {
+            Some(pseudonyms) => pseudonyms.sender(&message.sender),
+            None => message.sender.to_string(),
+        };
+
+        if let Some(change) = &message.group_change {
+            let names: BTreeMap<i32, String> = match (change, &message.participant) {
+                (
+                    GroupChange::ParticipantAdded(who) | GroupChange::ParticipantRemoved(who),
+                    Some(participant),
+                ) => {
+                    let name = match &mut pseudonyms {
+                        Some(pseudonyms) => pseudonyms.sender(participant),
+                        None => participant.to_string(),
+                    };
+                    BTreeMap::from([(*who, name)])
+                }
+                _ => BTreeMap::new(),
+            };
+            let line = change.describe(&sender, |who| {
+                names.get(&who).map(String::as_str).unwrap_or_default()
+            });
+            let _ = writeln!(
+                html,
+                "<div class=\"announcement\">\n    <p><span class=\"timestamp\">{date}</span> {}</p>\n</div>",
+                sanitize_html(&line)
+            );
+            continue;
+        }
+
+        let side = if message.is_from_me {
+            "sent"
+        } else {
+            "received"
+        };
+        let _ = write!(
+            html,
+            "<div class=\"message\">\n    <div class=\"{side}\">\n        <p><span class=\"timestamp\">{date}</span>\n            <span class=\"sender\">{}</span>\n        </p>\n        <hr>\n        ",
+            sanitize_html(&sender)
+        );
+        html.push_str(&message_body(message, pseudonyms.as_mut()));
+        if message.has_attachments {
+            html.push_str(
+                "\n        <div class=\"attachment\"><p>Attachment not recovered</p></div>",
+            );
+        }
+        html.push_str("\n    </div>\n</div>\n");
+    }
+    html.push_str("</html>\n");
+    html
+}
+
+/// Render the content of a message bubble for [`render_conversation_html`], redacting the text if `pseudonyms` are given
+fn message_body(message: &RecoveredMessage, pseudonyms: Option<&mut Pseudonyms>) -> String {
+    let text = message.text.as_deref().unwrap_or_default();
+    let text = match pseudonyms {
+        Some(pseudonyms) => sanitize_html(&pseudonyms.redact_text(text)).into_owned(),
+        None => sanitize_html(text).into_owned(),
+    };
+    match MessageKind::from_message(message) {
+        MessageKind::Handwriting => match message.payload_data.as_deref() {
+            Some(payload) => format_handwriting(payload, None).unwrap_or_else(|why| {
+                format!(
+                    "<p>Unable to render handwriting: {}</p>",
+                    HandwritingFailure::from(&why)
+                )
+            }),
+            None => String::from("<p>Handwriting not recovered</p>"),
+        },
+        MessageKind::Reaction => {
+            let parent = match message.parent {
+                Some(ParentMessage::Live(rowid)) => format!(" (re: message {rowid})"),
+                Some(ParentMessage::Recovered(rowid)) => {
+                    format!(" (re: recovered message {rowid})")
+                }
+                None => String::new(),
+            };
+            format!("<div class=\"tapback\"><span>{text}{parent}</span></div>")
+        }
+        MessageKind::App if text.is_empty() => {
+            String::from("<div class=\"app\"><p>App message</p></div>")
+        }
+        MessageKind::Text | MessageKind::App | MessageKind::GroupChange => {
+            format!("<div class=\"message_part\"><span class=\"bubble\">{text}</span></div>")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::current_dir, fs::File, io::Read};
+
+    use crate::{
+        app::recovery::models::{
+            DeletionReason, GroupChange, ParentMessage, RecoveredMessage, Sender,
+        },
+        exporters::html::{
+            HandwritingFailure, HandwritingFailures, format_handwriting, render_conversation_html,
+        },
+    };
+
+    fn message(rowid: i64, date: i64, text: Option<&str>) -> RecoveredMessage {
+        RecoveredMessage {
+            rowid,
+            guid: format!("guid-{rowid}"),
+            text: text.map(String::from),
+            handle_id: Some(2),
+            sender: Sender::Known("+15558675309".to_string()),
+            date,
+            is_from_me: false,
+            chat_id: Some(1),
+            associated_message_guid: None,
+            associated_message_type: None,
+            parent: None,
+            reason: DeletionReason::AbsentFromMessages,
+            live_rowid: None,
+            group_change: None,
+            participant: None,
+            has_attachments: false,
+            balloon_bundle_id: None,
+            payload_data: None,
+            truncated: false,
+        }
+    }
+
+    fn read_handwriting(name: &str) -> Vec<u8> {
+        let protobuf_path = current_dir()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .join("imessage-database/test_data/handwritten_message")
+            .join(name);
+        let mut proto_data = File::open(protobuf_path).unwrap();
+        let mut data = vec![];
+        proto_data.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn can_format_handwriting_as_svg() {
+        let html = format_handwriting(&read_handwriting("hello.bin"), None).unwrap();
+        assert!(html.starts_with("<div class=\"handwriting\"><svg "));
+        assert!(html.ends_with("</svg></div>"));
+    }
+
+    #[test]
+    fn can_format_handwriting_effect() {
+        let html = format_handwriting(
+            &read_handwriting("hello.bin"),
+            Some("com.apple.MobileSMS.expressivesend.invisibleink"),
+        )
+        .unwrap();
+        assert!(html.starts_with(
+            "<div class=\"handwriting\" data-effect=\"com.apple.MobileSMS.expressivesend.invisibleink\"><svg "
+        ));
+        assert!(!html.contains("feGaussianBlur"));
+    }
+
+    #[test]
+    fn can_escape_handwriting_id_and_effect() {
+        // A later `ID` field (3) replaces the one already in the payload
+        let id = b"</title><script>alert(1)</script>";
+        let mut payload = read_handwriting("hello.bin");
+        payload.extend_from_slice(&[0x1A, id.len() as u8]);
+        payload.extend_from_slice(id);
+
+        let html = format_handwriting(&payload, Some("\"><script>alert(2)</script>")).unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<title>&lt;/title>&lt;script>alert(1)&lt;/script></title>"));
+        assert!(html.starts_with(
+            "<div class=\"handwriting\" data-effect=\"&quot;&gt;&lt;script&gt;alert(2)&lt;/script&gt;\"><svg "
+        ));
+    }
+
+    #[test]
+    fn cant_format_invalid_handwriting() {
+        let mut failures = HandwritingFailures::default();
+        let err = format_handwriting(&[0xFF, 0x00, 0x01], None).unwrap_err();
+
+        assert_eq!(failures.record(&err), HandwritingFailure::InvalidPayload);
+    }
+
+    #[test]
+    fn can_count_handwriting_failures() {
+        let data = read_handwriting("hello.bin");
+
+        // Replace the XZ compression flag (field 5) with `Unknown`
+        let mut corrupt = data.clone();
+        let flag_at = corrupt
+            .windows(3)
+            .position(|window| window == [0x28, 0x04, 0x30])
+            .unwrap();
+        corrupt[flag_at + 1] = 0x00;
+
+        let mut failures = HandwritingFailures::default();
+        for payload in [&corrupt, &corrupt, &corrupt] {
+            failures.record(&format_handwriting(payload, None).unwrap_err());
+        }
+        failures.record(&format_handwriting(&[0xFF], None).unwrap_err());
+        assert!(format_handwriting(&data, None).is_ok());
+
+        assert_eq!(
+            failures.summary(),
+            vec![
+                "1 handwriting message skipped: invalid payload",
+                "3 handwriting messages skipped: unsupported compression",
+            ]
+        );
+    }
+
+    #[test]
+    fn can_render_conversation_html() {
+        let mut handwriting = message(2, 200_000_000_000, None);
+        handwriting.is_from_me = true;
+        handwriting.sender = Sender::Me;
+        handwriting.balloon_bundle_id = Some(
+            "com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.Handwriting.HandwritingProvider"
+                .to_string(),
+        );
+        handwriting.payload_data = Some(read_handwriting("hello.bin"));
+        let mut tapback = message(3, 300_000_000_000, Some("Loved “<3 you”"));
+        tapback.associated_message_guid = Some("p:0/guid-1".to_string());
+        tapback.associated_message_type = Some(2000);
+        tapback.parent = Some(ParentMessage::Recovered(1));
+        let mut photo = message(4, 400_000_000_000, Some("\u{FFFC}"));
+        photo.has_attachments = true;
+        let mut added = message(5, 500_000_000_000, None);
+        added.group_change = Some(GroupChange::ParticipantAdded(7));
+        added.participant = Some(Sender::Known("+15555550123".to_string()));
+
+        // Out of order, to check that the transcript is sorted by date
+        let messages = vec![
+            tapback,
+            added,
+            message(1, 100_000_000_000, Some("Hello")),
+            photo,
+            handwriting,
+        ];
+        let html = render_conversation_html(&messages, false);
+
+        assert!(html.starts_with("<html>"));
+        assert!(html.contains("<style>"));
+        assert_eq!(html.matches("<div class=\"message\">").count(), 4);
+        assert_eq!(html.matches("<div class=\"announcement\">").count(), 1);
+
+        let positions: Vec<usize> = [
+            "<span class=\"bubble\">Hello</span>",
+            "<div class=\"sent\">",
+            "<div class=\"handwriting\"><svg ",
+            "<div class=\"tapback\"><span>Loved “&lt;3 you” (re: recovered message 1)</span></div>",
+            "<div class=\"attachment\">",
+            "+15558675309 added +15555550123 to the conversation.",
+        ]
+        .iter()
+        .map(|part| html.find(part).expect(part))
+        .collect();
+        assert!(positions.is_sorted());
+    }
+
+    #[test]
+    fn can_render_redacted_conversation_html() {
+        let mut other = message(2, 200_000_000_000, Some("Call 555-867-5309"));
+        other.handle_id = Some(4);
+        other.sender = Sender::Unknown(4);
+        let mut added = message(4, 400_000_000_000, None);
+        added.group_change = Some(GroupChange::ParticipantAdded(7));
+        added.participant = Some(Sender::Unknown(7));
+        let messages = vec![
+            message(1, 100_000_000_000, Some("Hello")),
+            other,
+            message(3, 300_000_000_000, Some("Again")),
+            added,
+        ];
+        let html = render_conversation_html(&messages, true);
+
+        assert!(!html.contains("+15558675309"));
+        assert!(!html.contains("867-5309"));
+        assert_eq!(
+            html.matches("<span class=\"sender\">Contact A</span>")
+                .count(),
+            2
+        );
+        assert_eq!(
+            html.matches("<span class=\"sender\">Contact B</span>")
+                .count(),
+            1
+        );
+        // The number in the text belongs to the first sender
+        assert!(html.contains("<span class=\"bubble\">Call Contact A</span>"));
+        assert!(html.contains("Contact A added Contact C to the conversation."));
+    }
+}
@@ -1,2 +1,5 @@
+pub mod csv;
 pub mod exporter;
+pub mod html;
+pub mod redact;
 pub mod txt;
@@ -0,0 +1,237 @@
+/*!
+ Replaces the people in an export with stable pseudonyms, so recovered transcripts can be shared.
+*/
+
+use std::collections::HashMap;
+
+use crate::app::recovery::models::Sender;
+
+/// The fewest digits a run of text needs to be treated as a phone number
+const MIN_PHONE_DIGITS: usize = 10;
+/// Phone numbers are matched on their last digits, so `+1 555 867 5309` and `555-867-5309` are the same person
+const PHONE_KEY_DIGITS: usize = 10;
+/// Characters that may appear between the digits of a phone number
+const PHONE_SEPARATORS: [char; 5] = [' ', '(', ')', '-', '.'];
+/// Lengths of the digit groups in a local phone number without an area code, like `555-1234`
+const LOCAL_PHONE_GROUPS: [usize; 2] = [3, 4];
+/// Characters other than letters and digits that may appear before the `@` of an email address
+const EMAIL_SYMBOLS: [char; 5] = ['.', '_', '%', '+', '-'];
+
+/// Assigns each handle or phone number a pseudonym like `Contact A`, in the order they are first seen
+#[derive(Debug, Default)]
+pub struct Pseudonyms {
+    /// Pseudonyms keyed by the normalized address or `handle` `ROWID` they replace
+    names: HashMap<String, String>,
+}
+
+impl Pseudonyms {
+    /// The pseudonym for `sender`; messages from the database owner are left as they are
+    pub fn sender(&mut self, sender: &Sender) -> String {
+        match sender {
+            Sender::Me => sender.to_string(),
+            Sender::Known(address) => self.name(address_key(address)),
+            Sender::Unknown(handle_id) => self.handle(*handle_id),
+        }
+    }
+
+    /// The pseudonym for a sender that is only known by their `handle` `ROWID`
+    pub fn handle(&mut self, handle_id: i64) -> String {
+        self.name(format!("handle:{handle_id}"))
+    }
+
+    /// Replace every email address and phone number in `text` with its pseudonym.
+    ///
+    /// Any run of at least [`MIN_PHONE_DIGITS`] digits, optionally led by `+` and split by spaces, dashes,
+    /// dots, or parentheses, is treated as a phone number, as are local numbers written like `555-1234`.
+    /// This errs toward redacting too much.
+    pub fn redact_text(&mut self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut redacted = String::with_capacity(text.len());
+        let mut idx = 0;
+        while idx < chars.len() {
+            match email_end(&chars, idx).or_else(|| phone_end(&chars, idx)) {
+                Some(end) => {
+                    let address: String = chars[idx..end].iter().collect();
+                    redacted.push_str(&self.name(address_key(&address)));
+                    idx = end;
+                }
+                None => {
+                    redacted.push(chars[idx]);
+                    idx += 1;
+                }
+            }
+        }
+        redacted
+    }
+
+    /// Look up the pseudonym for `key`, assigning the next free one if it is new
+    fn name(&mut self, key: String) -> String {
+        let next = self.names.len();
+        self.names
+            .entry(key)
+            .or_insert_with(|| format!("Contact {}", label(next)))
+            .clone()
+    }
+}
+
+/// The end of the email address that starts at `start` in `chars`, if there is one
+fn email_end(chars: &[char], start: usize) -> Option<usize> {
+    let is_local = |c: &char| c.is_alphanumeric() || EMAIL_SYMBOLS.contains(c);
+    if start > 0 && is_local(&chars[start - 1]) {
+        return None;
+    }
+    let at = start + chars[start..].iter().take_while(|c| is_local(c)).count();
+    if at == start || chars.get(at) != Some(&'@') {
+        return None;
+    }
+    let mut end = at
+        + 1
+        + chars[at + 1..]
+            .iter()
+            .take_while(|c| c.is_alphanumeric() || ['.', '-'].contains(c))
+            .count();
+    // Punctuation after the domain belongs to the surrounding text
+    while end > at + 1 && !chars[end - 1].is_alphanumeric() {
+        end -= 1;
+    }
+    let domain = &chars[at + 1..end];
+    (domain.contains(&'.') && domain[0].is_alphanumeric()).then_some(end)
+}
+
+/// The end of the phone number that starts at `start` in `chars`, if there is one
+fn phone_end(chars: &[char], start: usize) -> Option<usize> {
+    let starts_number = (['+', '('].contains(&chars[start]) || chars[start].is_ascii_digit())
+        && (start == 0 || !chars[start - 1].is_alphanumeric());
+    if !starts_number {
+        return None;
+    }
+    let mut end = start + 1;
+    while end < chars.len()
+        && (chars[end].is_ascii_digit() || PHONE_SEPARATORS.contains(&chars[end]))
+    {
+        end += 1;
+    }
+    // Separators after the last digit belong to the surrounding text
+    while end > start && !chars[end - 1].is_ascii_digit() {
+        end -= 1;
+    }
+    let number = &chars[start..end];
+    let groups: Vec<usize> = number
+        .split(|c| !c.is_ascii_digit())
+        .map(<[char]>::len)
+        .filter(|len| *len > 0)
+        .collect();
+    let is_local =
+        groups == LOCAL_PHONE_GROUPS && ['-', '.'].contains(&number[LOCAL_PHONE_GROUPS[0]]);
+    (groups.iter().sum::<usize>() >= MIN_PHONE_DIGITS || is_local).then_some(end)
+}
+
+/// Normalize a handle address so that different spellings of the same phone number or email match
+fn address_key(address: &str) -> String {
+    if address.contains('@') {
+        return address.to_lowercase();
+    }
+    let digits = digits(address);
+    match digits.len().checked_sub(PHONE_KEY_DIGITS) {
+        Some(start) => digits[start..].to_string(),
+        None => address.to_string(),
+    }
+}
+
+/// The ASCII digits in `text`
+fn digits(text: &str) -> String {
+    text.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// Spreadsheet style letters for `idx`: `A` to `Z`, then `AA`, `AB`, and so on
+fn label(mut idx: usize) -> String {
+    let mut letters = vec![];
+    loop {
+        letters.push(char::from(b'A' + (idx % 26) as u8));
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+    letters.iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        app::recovery::models::Sender,
+        exporters::redact::{Pseudonyms, label},
+    };
+
+    #[test]
+    fn can_label_pseudonyms() {
+        assert_eq!(label(0), "A");
+        assert_eq!(label(25), "Z");
+        assert_eq!(label(26), "AA");
+        assert_eq!(label(27), "AB");
+        assert_eq!(label(702), "AAA");
+    }
+
+    #[test]
+    fn can_assign_stable_pseudonyms() {
+        let mut pseudonyms = Pseudonyms::default();
+
+        assert_eq!(pseudonyms.sender(&Sender::Unknown(3)), "Contact A");
+        assert_eq!(
+            pseudonyms.sender(&Sender::Known("+15558675309".to_string())),
+            "Contact B"
+        );
+        assert_eq!(pseudonyms.sender(&Sender::Unknown(3)), "Contact A");
+        assert_eq!(pseudonyms.handle(4), "Contact C");
+        assert_eq!(pseudonyms.sender(&Sender::Me), "Me");
+    }
+
+    #[test]
+    fn can_redact_phone_numbers_in_text() {
+        let mut pseudonyms = Pseudonyms::default();
+        pseudonyms.sender(&Sender::Known("+15558675309".to_string()));
+
+        assert_eq!(
+            pseudonyms.redact_text("Call (555) 867-5309, or +44 20 7946 0958."),
+            "Call Contact A, or Contact B."
+        );
+        assert_eq!(
+            pseudonyms.redact_text("Order 12345 arrives at 10:30"),
+            "Order 12345 arrives at 10:30"
+        );
+        assert_eq!(pseudonyms.redact_text("v1234567890"), "v1234567890");
+    }
+
+    #[test]
+    fn can_redact_formatted_phone_numbers_in_text() {
+        let mut pseudonyms = Pseudonyms::default();
+
+        assert_eq!(
+            pseudonyms.redact_text("(555) 123-4567 and 555-123-4567"),
+            "Contact A and Contact A"
+        );
+        assert_eq!(
+            pseudonyms.redact_text("Dial 555-1234 or 555.9876."),
+            "Dial Contact B or Contact C."
+        );
+        assert_eq!(
+            pseudonyms.redact_text("On 2024-01-15, room 12-3456"),
+            "On 2024-01-15, room 12-3456"
+        );
+    }
+
+    #[test]
+    fn can_redact_emails_in_text() {
+        let mut pseudonyms = Pseudonyms::default();
+        pseudonyms.sender(&Sender::Known("jane.doe@example.com".to_string()));
+
+        assert_eq!(
+            pseudonyms.redact_text("Write to Jane.Doe@Example.com or bob+news@mail.test.org."),
+            "Write to Contact A or Contact B."
+        );
+        assert_eq!(
+            pseudonyms.redact_text("Ping @bob at home@local, 3 @ 5"),
+            "Ping @bob at home@local, 3 @ 5"
+        );
+    }
+}